@@ -0,0 +1,25 @@
+/*!
+ Errors that can happen when exporting messages as JSON.
+*/
+
+use std::fmt::{Display, Formatter, Result};
+
+use crate::error::{message::MessageError, table::TableError};
+
+/// Errors that can happen when exporting messages as JSON
+#[derive(Debug)]
+pub enum JsonError {
+    Table(TableError),
+    Message(MessageError),
+    Write(std::io::Error),
+}
+
+impl Display for JsonError {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
+        match self {
+            JsonError::Table(why) => write!(fmt, "{why}"),
+            JsonError::Message(why) => write!(fmt, "{why}"),
+            JsonError::Write(why) => write!(fmt, "Failed to write JSON: {why}"),
+        }
+    }
+}
@@ -18,6 +18,7 @@ pub enum PlistParseError {
     WrongMessageType,
     InvalidEditedMessage(String),
     StreamTypedError(StreamTypedError),
+    UndecodablePayload(plist::Error),
 }
 
 impl Display for PlistParseError {
@@ -46,6 +47,9 @@ impl Display for PlistParseError {
                 write!(fmt, "Unable to parse message from binary data: {message}")
             }
             PlistParseError::StreamTypedError(why) => write!(fmt, "{why}"),
+            PlistParseError::UndecodablePayload(why) => {
+                write!(fmt, "Payload data is not a plist or typedstream: {why}")
+            }
         }
     }
 }
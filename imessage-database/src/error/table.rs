@@ -2,11 +2,22 @@
  Errors that can happen when extracting data from a `SQLite` table.
 */
 
-use std::fmt::{Display, Formatter, Result};
+use std::{
+    error::Error,
+    fmt::{Display, Formatter, Result},
+};
+
+use rusqlite::ErrorCode;
 
 /// Errors that can happen when extracting data from a `SQLite` table
+///
+/// Each variant that wraps a [`rusqlite::Error`] names the table that was being read. The per-table variants are
+/// returned when a query fails, i.e. because the database is locked, while [`TableError::CorruptRow`] is returned
+/// when a row was read but its columns could not be parsed. The wrapped error is available from [`Error::source()`].
 #[derive(Debug)]
 pub enum TableError {
+    /// A row was read from the named table, but a column did not contain the expected type
+    CorruptRow(&'static str, rusqlite::Error),
     Attachment(rusqlite::Error),
     ChatToHandle(rusqlite::Error),
     Chat(rusqlite::Error),
@@ -19,6 +30,7 @@ pub enum TableError {
 impl Display for TableError {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> Result {
         match self {
+            TableError::CorruptRow(table, why) => write!(fmt, "Failed to parse {table} row: {why}"),
             TableError::Attachment(why) => write!(fmt, "Failed to parse attachment row: {why}"),
             TableError::ChatToHandle(why) => write!(fmt, "Failed to parse chat handle row: {why}"),
             TableError::Chat(why) => write!(fmt, "Failed to parse chat row: {why}"),
@@ -29,3 +41,112 @@ impl Display for TableError {
         }
     }
 }
+
+impl Error for TableError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TableError::CorruptRow(_, why)
+            | TableError::Attachment(why)
+            | TableError::ChatToHandle(why)
+            | TableError::Chat(why)
+            | TableError::Handle(why)
+            | TableError::Messages(why) => Some(why),
+            TableError::CannotRead(why) => Some(why),
+            TableError::CannotConnect(_) => None,
+        }
+    }
+}
+
+impl TableError {
+    /// `true` if the query failed because another process is writing to the database, else `false`
+    ///
+    /// Messages writes to `chat.db` while it is running, so this error is usually temporary and the query can be retried.
+    pub fn is_database_locked(&self) -> bool {
+        matches!(
+            self.source()
+                .and_then(|why| why.downcast_ref::<rusqlite::Error>())
+                .and_then(rusqlite::Error::sqlite_error_code),
+            Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use rusqlite::ffi;
+
+    use crate::{
+        error::table::TableError,
+        tables::{
+            messages::Message,
+            table::Table,
+            test_utils::{TestDatabase, TestMessage},
+        },
+    };
+
+    fn sqlite_error(code: i32) -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(ffi::Error::new(code), None)
+    }
+
+    #[test]
+    fn can_detect_database_busy() {
+        let error = TableError::Messages(sqlite_error(ffi::SQLITE_BUSY));
+        assert!(error.is_database_locked());
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn can_detect_database_locked() {
+        let error = TableError::Chat(sqlite_error(ffi::SQLITE_LOCKED));
+        assert!(error.is_database_locked());
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn cant_detect_database_locked_corrupt() {
+        let error = TableError::Messages(sqlite_error(ffi::SQLITE_CORRUPT));
+        assert!(!error.is_database_locked());
+        assert!(error.source().is_some());
+    }
+
+    #[test]
+    fn cant_get_source_cannot_connect() {
+        let error = TableError::CannotConnect(String::from("No such file"));
+        assert!(!error.is_database_locked());
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn can_detect_corrupt_row() {
+        let fixture = TestDatabase::new();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                ..Default::default()
+            },
+        );
+        fixture
+            .connection()
+            .execute("UPDATE message SET date = 'yesterday'", [])
+            .unwrap();
+
+        let mut statement = Message::get(fixture.connection()).unwrap();
+        let error = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(Message::extract)
+            .next()
+            .unwrap()
+            .unwrap_err();
+
+        assert!(matches!(error, TableError::CorruptRow("messages", _)));
+        assert!(!error.is_database_locked());
+        assert!(error.source().is_some());
+        assert!(error
+            .to_string()
+            .starts_with("Failed to parse messages row: "));
+    }
+}
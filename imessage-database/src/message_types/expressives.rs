@@ -6,6 +6,7 @@
 ///
 /// Read more [here](https://www.imore.com/how-to-use-bubble-and-screen-effects-imessage-iphone-ipad).
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BubbleEffect {
     Slam,
     Loud,
@@ -17,6 +18,7 @@ pub enum BubbleEffect {
 ///
 /// Read more [here](https://www.imore.com/how-to-use-bubble-and-screen-effects-imessage-iphone-ipad).
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ScreenEffect {
     Confetti,
     Echo,
@@ -50,6 +52,7 @@ pub enum ScreenEffect {
 /// - `com.apple.messages.effect.CKSparklesEffect`
 /// - `com.apple.messages.effect.CKSpotlightEffect`
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Expressive<'a> {
     /// Effects that use the entire screen
     Screen(ScreenEffect),
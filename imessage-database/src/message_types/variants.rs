@@ -36,7 +36,8 @@ use crate::{
 ///   - When messages drop the ROWIDs become non-sequential: the ID of the dropped message row is not reused
 ///   - This means unliking an old message will make it look like the reaction was applied/removed at the
 ///     time of latest change; the history of reaction statuses is not kept
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Reaction {
     /// Heart
     Loved,
@@ -57,6 +58,7 @@ pub enum Reaction {
 /// Messages sent via an app's iMessage integration will send in a special balloon instead of a normal
 /// text balloon. This represents the different variants of message balloon.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CustomBalloon<'a> {
     /// Generic third party [applications](crate::message_types::app)
     Application(&'a str),
@@ -73,7 +75,13 @@ pub enum CustomBalloon<'a> {
     /// [Check In](https://support.apple.com/guide/iphone/use-check-in-iphc143bb7e9/ios) messages
     CheckIn,
     /// Find My messages
-    FindMy
+    FindMy,
+    /// [Digital Touch](https://support.apple.com/guide/iphone/send-a-digital-touch-effect-iph3fadba219/ios) sketches, taps, and heartbeats
+    DigitalTouch,
+    /// Animoji and Memoji recordings
+    Animoji,
+    /// [Apple Messages for Business](https://support.apple.com/en-us/HT208888) interactive messages
+    Business,
 }
 
 /// URL Message Types
@@ -115,6 +123,7 @@ pub enum Announcement<'a> {
 /// Messages can exist as one of many different variants, this encapsulates
 /// all of the possibilities.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Variant<'a> {
     /// A reaction to another message
     Reaction(usize, bool, Reaction),
@@ -132,6 +141,24 @@ pub enum Variant<'a> {
     SharePlay,
 }
 
+impl Variant<'_> {
+    /// Whether a reaction was removed, or `None` if the variant is not a [`Variant::Reaction`]
+    pub fn reaction_was_removed(&self) -> Option<bool> {
+        match self {
+            Variant::Reaction(_, added, _) => Some(!added),
+            _ => None,
+        }
+    }
+
+    /// The kind of reaction, or `None` if the variant is not a [`Variant::Reaction`]
+    pub fn reaction_kind(&self) -> Option<Reaction> {
+        match self {
+            Variant::Reaction(_, _, kind) => Some(*kind),
+            _ => None,
+        }
+    }
+}
+
 /// Defines behavior for different types of messages that have custom balloons
 pub trait BalloonProvider<'a> {
     /// Creates the object from a `HashMap` of item attributes
@@ -139,3 +166,30 @@ pub trait BalloonProvider<'a> {
     where
         Self: Sized;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::message_types::variants::{Reaction, Variant};
+
+    #[test]
+    fn can_get_added_reaction() {
+        let variant = Variant::Reaction(0, true, Reaction::Loved);
+        assert_eq!(variant.reaction_was_removed(), Some(false));
+        assert_eq!(variant.reaction_kind(), Some(Reaction::Loved));
+    }
+
+    #[test]
+    fn can_get_removed_reaction() {
+        let variant = Variant::Reaction(2, false, Reaction::Questioned);
+        assert_eq!(variant.reaction_was_removed(), Some(true));
+        assert_eq!(variant.reaction_kind(), Some(Reaction::Questioned));
+    }
+
+    #[test]
+    fn cant_get_reaction_from_other_variants() {
+        for variant in [Variant::Sticker(0), Variant::Normal, Variant::Unknown(2006)] {
+            assert_eq!(variant.reaction_was_removed(), None);
+            assert_eq!(variant.reaction_kind(), None);
+        }
+    }
+}
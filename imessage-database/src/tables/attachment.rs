@@ -2,10 +2,11 @@
  This module represents common (but not all) columns in the `attachment` table.
 */
 
+use base64::{engine::general_purpose::STANDARD, Engine};
 use rusqlite::{Connection, Error, Result, Row, Statement};
 use sha1::{Digest, Sha1};
 use std::{
-    fs::File,
+    fs::{metadata, File},
     io::Read,
     path::{Path, PathBuf},
 };
@@ -44,6 +45,40 @@ pub enum MediaType<'a> {
     Unknown,
 }
 
+/// Resolves where the data for an attachment can be read from
+///
+/// Reading attachments from disk is expensive and not every export needs them, so rendering paths like
+/// [`Message::render_html()`](crate::tables::messages::Message::render_html) only call the resolver when they
+/// emit an attachment. Implement this to plug in strategies like copying or streaming attachment data.
+pub trait AttachmentResolver {
+    /// Get the path to the attachment's data, or `None` if it is not available
+    fn resolve(&self, attachment: &Attachment) -> Option<PathBuf>;
+}
+
+/// An [`AttachmentResolver`] that returns the expanded path to the attachment if the file exists
+///
+/// Paths are expanded with [`Attachment::resolved_attachment_path()`].
+#[derive(Debug)]
+pub struct PathResolver {
+    /// The platform that created the database
+    pub platform: Platform,
+    /// The path to the database or iOS backup root, as passed to [`get_connection()`](crate::tables::table::get_connection)
+    pub db_path: PathBuf,
+    /// A replacement for [`DEFAULT_ATTACHMENT_ROOT`] when attachments were moved
+    pub custom_attachment_root: Option<String>,
+}
+
+impl AttachmentResolver for PathResolver {
+    fn resolve(&self, attachment: &Attachment) -> Option<PathBuf> {
+        let path = PathBuf::from(attachment.resolved_attachment_path(
+            &self.platform,
+            &self.db_path,
+            self.custom_attachment_root.as_deref(),
+        )?);
+        path.exists().then_some(path)
+    }
+}
+
 /// Represents a single row in the `attachment` table.
 #[derive(Debug)]
 pub struct Attachment {
@@ -88,13 +123,18 @@ impl Table for Attachment {
     fn extract(attachment: Result<Result<Self, Error>, Error>) -> Result<Self, TableError> {
         match attachment {
             Ok(Ok(attachment)) => Ok(attachment),
-            Err(why) | Ok(Err(why)) => Err(TableError::Attachment(why)),
+            Ok(Err(why)) => Err(TableError::CorruptRow("attachment", why)),
+            Err(why) => Err(TableError::Attachment(why)),
         }
     }
 }
 
 impl Attachment {
     /// Gets a Vector of attachments for a single message
+    ///
+    /// Attachments are returned in the order they were added to the `message_attachment_join` table,
+    /// which is the order their [`BubbleComponent::Attachment`](crate::tables::messages::models::BubbleComponent::Attachment)s
+    /// appear in the message body.
     pub fn from_message(db: &Connection, msg: &Message) -> Result<Vec<Attachment>, TableError> {
         let mut out_l = vec![];
         if msg.has_attachments() {
@@ -104,6 +144,7 @@ impl Attachment {
                     SELECT * FROM message_attachment_join j 
                         LEFT JOIN attachment AS a ON j.attachment_id = a.ROWID
                     WHERE j.message_id = {}
+                    ORDER BY j.ROWID
                     ",
                     msg.rowid
                 ))
@@ -179,6 +220,49 @@ impl Attachment {
         Ok(None)
     }
 
+    /// Get a value for an HTML `src` attribute that embeds the attachment as a base64 `data:` URI
+    ///
+    /// Inlining the data makes a single-file export portable, but it grows the output by about a third of the
+    /// attachment's size. Attachments larger than `max_bytes` are not read and fall back to the
+    /// [resolved path](Self::resolved_attachment_path), as do attachments with no MIME type, which would not
+    /// display. Use `None` to inline attachments of any size.
+    ///
+    /// Returns `None` if the attachment does not have a path.
+    ///
+    /// `db_path` is the path to the root of the backup directory.
+    /// This is the same path used by [`get_connection()`](crate::tables::table::get_connection).
+    pub fn as_embedded_src(
+        &self,
+        platform: &Platform,
+        db_path: &Path,
+        custom_attachment_root: Option<&str>,
+        max_bytes: Option<u64>,
+    ) -> Result<Option<String>, AttachmentError> {
+        let Some(file_path) =
+            self.resolved_attachment_path(platform, db_path, custom_attachment_root)
+        else {
+            return Ok(None);
+        };
+        let Some(mime_type) = &self.mime_type else {
+            return Ok(Some(file_path));
+        };
+
+        let size = metadata(&file_path)
+            .map_err(|_| AttachmentError::FileNotFound(file_path.clone()))?
+            .len();
+        if max_bytes.is_some_and(|max_bytes| size > max_bytes) {
+            return Ok(Some(file_path));
+        }
+
+        match self.as_bytes(platform, db_path, custom_attachment_root)? {
+            Some(bytes) => Ok(Some(format!(
+                "data:{mime_type};base64,{}",
+                STANDARD.encode(bytes)
+            ))),
+            None => Ok(Some(file_path)),
+        }
+    }
+
     /// Determine the [`StickerEffect`] of a sticker message
     ///
     /// `db_path` is the path to the root of the backup directory.
@@ -438,11 +522,17 @@ impl Attachment {
 #[cfg(test)]
 mod tests {
     use crate::{
-        tables::attachment::{Attachment, MediaType, DEFAULT_ATTACHMENT_ROOT},
+        tables::attachment::{
+            Attachment, AttachmentResolver, MediaType, PathResolver, DEFAULT_ATTACHMENT_ROOT,
+        },
         util::platform::Platform,
     };
 
-    use std::path::{Path, PathBuf};
+    use std::{
+        env::temp_dir,
+        fs::{remove_file, write},
+        path::{Path, PathBuf},
+    };
 
     fn sample_attachment() -> Attachment {
         Attachment {
@@ -676,4 +766,66 @@ mod tests {
 
         assert_eq!(attachment.file_size(), String::from("16777216.00 TB"));
     }
+
+    #[test]
+    fn can_resolve_existing_path() {
+        let mut attachment = sample_attachment();
+        let manifest = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+        attachment.filename = Some(manifest.to_string());
+
+        let resolver = PathResolver {
+            platform: Platform::macOS,
+            db_path: PathBuf::new(),
+            custom_attachment_root: None,
+        };
+        assert_eq!(resolver.resolve(&attachment), Some(PathBuf::from(manifest)));
+    }
+
+    #[test]
+    fn cant_resolve_missing_path() {
+        let attachment = sample_attachment();
+        let resolver = PathResolver {
+            platform: Platform::macOS,
+            db_path: PathBuf::new(),
+            custom_attachment_root: None,
+        };
+        assert_eq!(resolver.resolve(&attachment), None);
+    }
+
+    #[test]
+    fn can_embed_small_attachment() {
+        let path = temp_dir().join("can_embed_small_attachment.txt");
+        write(&path, "Hello").unwrap();
+        let mut attachment = sample_attachment();
+        attachment.filename = Some(path.display().to_string());
+        attachment.mime_type = Some("text/plain".to_string());
+
+        let src = attachment
+            .as_embedded_src(&Platform::macOS, Path::new(""), None, Some(5))
+            .unwrap();
+        remove_file(&path).unwrap();
+        assert_eq!(src, Some("data:text/plain;base64,SGVsbG8=".to_string()));
+    }
+
+    #[test]
+    fn cant_embed_large_attachment() {
+        let path = temp_dir().join("cant_embed_large_attachment.txt");
+        write(&path, "Hello world").unwrap();
+        let mut attachment = sample_attachment();
+        attachment.filename = Some(path.display().to_string());
+
+        let src = attachment
+            .as_embedded_src(&Platform::macOS, Path::new(""), None, Some(5))
+            .unwrap();
+        remove_file(&path).unwrap();
+        assert_eq!(src, Some(path.display().to_string()));
+    }
+
+    #[test]
+    fn cant_embed_missing_attachment() {
+        let attachment = sample_attachment();
+        assert!(attachment
+            .as_embedded_src(&Platform::macOS, Path::new(""), None, None)
+            .is_err());
+    }
 }
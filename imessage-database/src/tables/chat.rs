@@ -4,11 +4,15 @@
 
 use std::collections::HashMap;
 
-use rusqlite::{Connection, Error, Result, Row, Statement};
+use rusqlite::{params, Connection, Error, Result, Row, Statement};
 
 use crate::{
     error::table::TableError,
-    tables::table::{Cacheable, Table, CHAT},
+    tables::{
+        messages::models::DateSpan,
+        table::{Cacheable, Table, CHAT, CHAT_HANDLE_JOIN, CHAT_MESSAGE_JOIN, HANDLE, MESSAGE},
+    },
+    util::dates::get_local_time,
 };
 
 /// Represents a single row in the `chat` table.
@@ -22,6 +26,19 @@ pub struct Chat {
     pub display_name: Option<String>,
 }
 
+/// Summary of a single conversation, built by [`Chat::summaries()`]
+#[derive(Debug)]
+pub struct ChatSummary {
+    /// The chat's row in the `chat` table
+    pub chat: Chat,
+    /// The number of handles that participate in the chat
+    pub num_participants: usize,
+    /// The number of messages in the chat
+    pub num_messages: u64,
+    /// The dates of the first and last messages in the chat, if it has any messages
+    pub date_span: Option<DateSpan>,
+}
+
 impl Table for Chat {
     fn from_row(row: &Row) -> Result<Chat> {
         Ok(Chat {
@@ -40,7 +57,8 @@ impl Table for Chat {
     fn extract(chat: Result<Result<Self, Error>, Error>) -> Result<Self, TableError> {
         match chat {
             Ok(Ok(chat)) => Ok(chat),
-            Err(why) | Ok(Err(why)) => Err(TableError::Chat(why)),
+            Ok(Err(why)) => Err(TableError::CorruptRow("chat", why)),
+            Err(why) => Err(TableError::Chat(why)),
         }
     }
 }
@@ -102,4 +120,199 @@ impl Chat {
             None => None,
         }
     }
+
+    /// Get the identifiers of the handles that currently participate in a chat, i.e. phone numbers and email addresses
+    ///
+    /// The database owner is not included. Identifiers are sorted and deduplicated, since the same identifier can
+    /// have one handle per service. The number of identifiers can be smaller than
+    /// [`ChatSummary::num_participants`] for that reason.
+    pub fn participant_handles(db: &Connection, chat_id: i32) -> Result<Vec<String>, TableError> {
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT DISTINCT h.id
+                 FROM
+                     {CHAT_HANDLE_JOIN} as j
+                     JOIN {HANDLE} as h ON j.handle_id = h.ROWID
+                 WHERE
+                     j.chat_id = ?1
+                 ORDER BY
+                     h.id"
+            ))
+            .map_err(TableError::Chat)?;
+
+        let rows = statement
+            .query_map(params![chat_id], |row| row.get(0))
+            .map_err(TableError::Chat)?;
+
+        rows.map(|id| id.map_err(TableError::Chat)).collect()
+    }
+
+    /// Get the `ROWID` of every chat, ordered by the date of its most recent message, newest first
+    ///
+    /// Chats without any messages are sorted last, in `ROWID` order, so they are still listed in an export index.
+    /// Use the IDs to order the results of [`Self::summaries()`] or the chat cache.
+    pub fn ids_by_recent_activity(db: &Connection) -> Result<Vec<i32>, TableError> {
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT
+                     c.ROWID
+                 FROM
+                     {CHAT} as c
+                     LEFT JOIN {CHAT_MESSAGE_JOIN} as j ON c.ROWID = j.chat_id
+                     LEFT JOIN {MESSAGE} as m ON j.message_id = m.ROWID
+                 GROUP BY
+                     c.ROWID
+                 ORDER BY
+                     MAX(m.date) IS NULL,
+                     MAX(m.date) DESC,
+                     c.ROWID"
+            ))
+            .map_err(TableError::Chat)?;
+
+        let rows = statement
+            .query_map([], |row| row.get(0))
+            .map_err(TableError::Chat)?;
+
+        rows.map(|id| id.map_err(TableError::Chat)).collect()
+    }
+
+    /// Summarize every chat in the table with a single scan, ordered by `ROWID`
+    ///
+    /// This provides the data needed to build an index of conversations before exporting any messages.
+    pub fn summaries(db: &Connection, offset: &i64) -> Result<Vec<ChatSummary>, TableError> {
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT
+                     c.*,
+                     (SELECT COUNT(*) FROM {CHAT_HANDLE_JOIN} h WHERE h.chat_id = c.ROWID) as num_participants,
+                     COUNT(m.ROWID) as num_messages,
+                     MIN(m.date) as first_date,
+                     MAX(m.date) as last_date
+                 FROM
+                     {CHAT} as c
+                     LEFT JOIN {CHAT_MESSAGE_JOIN} as j ON c.ROWID = j.chat_id
+                     LEFT JOIN {MESSAGE} as m ON j.message_id = m.ROWID
+                 GROUP BY
+                     c.ROWID
+                 ORDER BY
+                     c.ROWID"
+            ))
+            .map_err(TableError::Chat)?;
+
+        let rows = statement
+            .query_map([], |row| {
+                let first: Option<i64> = row.get("first_date")?;
+                let last: Option<i64> = row.get("last_date")?;
+                Ok(ChatSummary {
+                    chat: Chat::from_row(row)?,
+                    num_participants: row.get("num_participants")?,
+                    num_messages: row.get("num_messages")?,
+                    date_span: first.zip(last).and_then(|(first, last)| {
+                        get_local_time(&first, offset)
+                            .ok()
+                            .zip(get_local_time(&last, offset).ok())
+                    }),
+                })
+            })
+            .map_err(TableError::Chat)?;
+
+        rows.map(|summary| summary.map_err(TableError::Chat))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        tables::{
+            chat::Chat,
+            test_utils::{TestDatabase, TestMessage},
+        },
+        util::dates::{get_local_time, get_offset},
+    };
+
+    #[test]
+    fn can_get_summaries() {
+        let fixture = TestDatabase::new();
+        let person = fixture.add_handle("+15558675309");
+        let other = fixture.add_handle("+15551234567");
+        let chat = fixture.add_chat("chat123", "iMessage", &[person, other]);
+        fixture.add_chat("+15551234567", "SMS", &[other]);
+
+        for (guid, date) in [
+            ("A44CE9D7-AAAA-BBBB-CCCC-000000000001", 674526582885055488),
+            ("A44CE9D7-AAAA-BBBB-CCCC-000000000002", 674530231992568192),
+        ] {
+            fixture.add_message(
+                Some(chat),
+                &TestMessage {
+                    guid,
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let offset = get_offset();
+        let summaries = Chat::summaries(fixture.connection(), &offset).unwrap();
+        assert_eq!(summaries.len(), 2);
+
+        assert_eq!(summaries[0].chat.chat_identifier, "chat123");
+        assert_eq!(summaries[0].num_participants, 2);
+        assert_eq!(summaries[0].num_messages, 2);
+        assert_eq!(
+            summaries[0].date_span,
+            Some((
+                get_local_time(&674526582885055488, &offset).unwrap(),
+                get_local_time(&674530231992568192, &offset).unwrap()
+            ))
+        );
+
+        assert_eq!(summaries[1].num_participants, 1);
+        assert_eq!(summaries[1].num_messages, 0);
+        assert_eq!(summaries[1].date_span, None);
+    }
+
+    #[test]
+    fn can_get_participant_handles() {
+        let fixture = TestDatabase::new();
+        let person = fixture.add_handle("+15558675309");
+        let other = fixture.add_handle("+15551234567");
+        let email = fixture.add_handle("person@example.com");
+        let same_person = fixture.add_handle("+15558675309");
+        let chat = fixture.add_chat("chat123", "iMessage", &[person, email, other, same_person]);
+        let empty = fixture.add_chat("chat456", "iMessage", &[]);
+
+        let db = fixture.connection();
+        assert_eq!(
+            Chat::participant_handles(db, chat).unwrap(),
+            ["+15551234567", "+15558675309", "person@example.com"]
+        );
+        assert!(Chat::participant_handles(db, empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn can_get_ids_by_recent_activity() {
+        let fixture = TestDatabase::new();
+        let old = fixture.add_chat("chat1", "iMessage", &[]);
+        let empty = fixture.add_chat("chat2", "iMessage", &[]);
+        let recent = fixture.add_chat("chat3", "iMessage", &[]);
+        let other_empty = fixture.add_chat("chat4", "iMessage", &[]);
+
+        for (chat, date) in [(old, 1), (recent, 2), (old, 3), (recent, 5)] {
+            fixture.add_message(
+                Some(chat),
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{date:012}"),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        assert_eq!(
+            Chat::ids_by_recent_activity(fixture.connection()).unwrap(),
+            [recent, old, empty, other_empty]
+        );
+    }
 }
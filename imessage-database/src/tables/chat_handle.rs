@@ -35,7 +35,8 @@ impl Table for ChatToHandle {
     fn extract(chat_to_handle: Result<Result<Self, Error>, Error>) -> Result<Self, TableError> {
         match chat_to_handle {
             Ok(Ok(chat_to_handle)) => Ok(chat_to_handle),
-            Err(why) | Ok(Err(why)) => Err(TableError::ChatToHandle(why)),
+            Ok(Err(why)) => Err(TableError::CorruptRow("chat handle", why)),
+            Err(why) => Err(TableError::ChatToHandle(why)),
         }
     }
 }
@@ -11,6 +11,86 @@ use crate::{
     util::output::{done_processing, processing},
 };
 
+/// Country calling codes mapped to the ISO 3166-1 alpha-2 code of their most common region
+///
+/// Codes shared by several regions, like `+1`, map to the region with the most subscribers.
+const COUNTRY_CODES: [(&str, &str); 72] = [
+    ("1", "US"),
+    ("7", "RU"),
+    ("20", "EG"),
+    ("27", "ZA"),
+    ("30", "GR"),
+    ("31", "NL"),
+    ("32", "BE"),
+    ("33", "FR"),
+    ("34", "ES"),
+    ("36", "HU"),
+    ("39", "IT"),
+    ("40", "RO"),
+    ("41", "CH"),
+    ("43", "AT"),
+    ("44", "GB"),
+    ("45", "DK"),
+    ("46", "SE"),
+    ("47", "NO"),
+    ("48", "PL"),
+    ("49", "DE"),
+    ("51", "PE"),
+    ("52", "MX"),
+    ("53", "CU"),
+    ("54", "AR"),
+    ("55", "BR"),
+    ("56", "CL"),
+    ("57", "CO"),
+    ("58", "VE"),
+    ("60", "MY"),
+    ("61", "AU"),
+    ("62", "ID"),
+    ("63", "PH"),
+    ("64", "NZ"),
+    ("65", "SG"),
+    ("66", "TH"),
+    ("81", "JP"),
+    ("82", "KR"),
+    ("84", "VN"),
+    ("86", "CN"),
+    ("90", "TR"),
+    ("91", "IN"),
+    ("92", "PK"),
+    ("93", "AF"),
+    ("94", "LK"),
+    ("95", "MM"),
+    ("98", "IR"),
+    ("212", "MA"),
+    ("213", "DZ"),
+    ("234", "NG"),
+    ("254", "KE"),
+    ("351", "PT"),
+    ("352", "LU"),
+    ("353", "IE"),
+    ("354", "IS"),
+    ("358", "FI"),
+    ("370", "LT"),
+    ("371", "LV"),
+    ("372", "EE"),
+    ("380", "UA"),
+    ("420", "CZ"),
+    ("421", "SK"),
+    ("852", "HK"),
+    ("853", "MO"),
+    ("880", "BD"),
+    ("886", "TW"),
+    ("961", "LB"),
+    ("962", "JO"),
+    ("965", "KW"),
+    ("966", "SA"),
+    ("971", "AE"),
+    ("972", "IL"),
+    ("974", "QA"),
+];
+/// The longest country calling code, in digits
+const MAX_COUNTRY_CODE_LEN: usize = 3;
+
 /// Represents a single row in the `handle` table.
 #[derive(Debug)]
 pub struct Handle {
@@ -36,7 +116,8 @@ impl Table for Handle {
     fn extract(handle: Result<Result<Self, Error>, Error>) -> Result<Self, TableError> {
         match handle {
             Ok(Ok(handle)) => Ok(handle),
-            Err(why) | Ok(Err(why)) => Err(TableError::Handle(why)),
+            Ok(Err(why)) => Err(TableError::CorruptRow("handle", why)),
+            Err(why) => Err(TableError::Handle(why)),
         }
     }
 }
@@ -243,9 +324,47 @@ impl Handle {
     }
 }
 
+/// Infer the region of a handle from its country calling code, i.e. `+15558675309` yields `US`
+///
+/// This is a best-effort lookup: only phone numbers in international format (with a leading `+`)
+/// are resolved, and codes shared by several regions resolve to the most populous one. Emails,
+/// short codes, and numbers in a local format yield `None`.
+///
+/// # Example:
+///
+/// ```
+/// use imessage_database::tables::handle::handle_region;
+///
+/// assert_eq!(handle_region("+44 20 7946 0958"), Some("GB".to_string()));
+/// assert_eq!(handle_region("person@example.com"), None);
+/// ```
+pub fn handle_region(handle: &str) -> Option<String> {
+    let number = handle.trim().strip_prefix('+')?;
+    let digits: String = number
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '(' | ')' | '.'))
+        .collect();
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    // Country calling codes are prefix-free, so check the longest candidates first
+    (1..=MAX_COUNTRY_CODE_LEN).rev().find_map(|len| {
+        let prefix = digits.get(..len)?;
+        COUNTRY_CODES
+            .iter()
+            .find(|(code, _)| *code == prefix)
+            .map(|(_, region)| region.to_string())
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::tables::{handle::Handle, table::Deduplicate};
+    use crate::tables::{
+        handle::{handle_region, Handle},
+        table::Deduplicate,
+    };
     use std::collections::{HashMap, HashSet};
 
     #[test]
@@ -308,4 +427,34 @@ mod tests {
         assert_eq!(output_1, output_3);
         assert_eq!(output_2, output_3);
     }
+
+    #[test]
+    fn can_get_region_single_digit_code() {
+        assert_eq!(handle_region("+15558675309"), Some("US".to_string()));
+    }
+
+    #[test]
+    fn can_get_region_three_digit_code() {
+        assert_eq!(handle_region("+353 1 234 5678"), Some("IE".to_string()));
+    }
+
+    #[test]
+    fn can_get_region_formatted() {
+        assert_eq!(handle_region("+44 (20) 7946-0958"), Some("GB".to_string()));
+    }
+
+    #[test]
+    fn cant_get_region_email() {
+        assert_eq!(handle_region("person@example.com"), None);
+    }
+
+    #[test]
+    fn cant_get_region_local_number() {
+        assert_eq!(handle_region("5558675309"), None);
+    }
+
+    #[test]
+    fn cant_get_region_unknown_code() {
+        assert_eq!(handle_region("+999123456"), None);
+    }
 }
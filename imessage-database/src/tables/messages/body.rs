@@ -11,18 +11,21 @@ use crate::{
 };
 
 /// Character found in message body text that indicates attachment position
-const ATTACHMENT_CHAR: char = '\u{FFFC}';
+pub(crate) const ATTACHMENT_CHAR: char = '\u{FFFC}';
 /// Character found in message body text that indicates app message position
-const APP_CHAR: char = '\u{FFFD}';
+pub(crate) const APP_CHAR: char = '\u{FFFD}';
 /// A collection of characters that represent non-text content within body text
-const REPLACEMENT_CHARS: [char; 2] = [ATTACHMENT_CHAR, APP_CHAR];
+pub(crate) const REPLACEMENT_CHARS: [char; 2] = [ATTACHMENT_CHAR, APP_CHAR];
 
 pub enum BubbleResult<'a> {
-    New(BubbleComponent<'a>),
+    /// An attachment, numbered by [`parse_body_typedstream()`] in the order it appears
+    Attachment,
     Continuation(TextAttributes<'a>),
 }
 
 /// Logic to use deserialized typedstream data to parse the message body
+///
+/// Like [`parse_body_legacy()`], ranges that contain only whitespace do not produce their own [`BubbleComponent::Text`] bubbles.
 pub(crate) fn parse_body_typedstream(message: &Message) -> Option<Vec<BubbleComponent>> {
     // Create the output data
     let mut out_v = vec![];
@@ -33,15 +36,11 @@ pub(crate) fn parse_body_typedstream(message: &Message) -> Option<Vec<BubbleComp
         let mut idx = 1;
         let mut current_start;
         let mut current_end = 0;
+        let mut num_attachments = 0;
 
         // We want to index into the message text, so we need a table to align
-        // Apple's indexes with the actual chars, not the bytes
-        let char_index_table: Vec<usize> = message
-            .text
-            .as_ref()?
-            .char_indices()
-            .map(|(a, _)| a)
-            .collect();
+        // Apple's UTF-16 indexes with the actual chars, not the bytes
+        let char_index_table = utf16_index_table(message.text.as_ref()?);
 
         while idx < components.len() {
             // The first part of the range sometimes indicates the part number, but not always
@@ -75,9 +74,14 @@ pub(crate) fn parse_body_typedstream(message: &Message) -> Option<Vec<BubbleComp
                 &char_index_table,
             ) {
                 match bubble {
-                    BubbleResult::New(item) => out_v.push(item),
+                    BubbleResult::Attachment => {
+                        out_v.push(BubbleComponent::Attachment(num_attachments));
+                        num_attachments += 1;
+                    }
                     BubbleResult::Continuation(effect) => match out_v.last_mut() {
                         Some(BubbleComponent::Text(attrs)) => attrs.push(effect),
+                        // Do not start a new bubble for a range that only contains whitespace
+                        _ if is_blank(message.text.as_ref()?, effect.start, effect.end) => {}
                         _ => out_v.push(BubbleComponent::Text(vec![effect])),
                     },
                 }
@@ -116,7 +120,24 @@ fn get_range(component: &Archivable) -> Option<(&i64, &u64)> {
     None
 }
 
-/// Given the attributedBody range idxes, get the substring from the Rust representations `char_indices()`
+/// Build a table that maps each UTF-16 code unit offset in `text` to the UTF-8 byte index it represents
+///
+/// `NSAttributedString` ranges count UTF-16 code units, but Rust strings are indexed by UTF-8 bytes, so
+/// characters outside the Basic Multilingual Plane (like most emoji) take up two entries. An offset that
+/// lands between the halves of a surrogate pair maps to the end of that character, so slicing with the
+/// table never splits a character. Offsets past the end of the table belong to the end of the text.
+fn utf16_index_table(text: &str) -> Vec<usize> {
+    let mut table = Vec::with_capacity(text.len());
+    for (byte_idx, c) in text.char_indices() {
+        table.push(byte_idx);
+        if c.len_utf16() == 2 {
+            table.push(byte_idx + c.len_utf8());
+        }
+    }
+    table
+}
+
+/// Given the attributedBody range idxes, get the byte index from the table built by [`utf16_index_table()`]
 fn get_char_idx(text: &str, idx: usize, char_indices: &[usize]) -> usize {
     char_indices.get(idx).map_or(text.len(), |i| *i)
 }
@@ -165,9 +186,7 @@ fn get_bubble_type<'a>(
         // In the future, we will detect TextEffects as well
         if let Some(key_name) = key.deserialize_as_nsstring() {
             match key_name {
-                "__kIMFileTransferGUIDAttributeName" => {
-                    return Some(BubbleResult::New(BubbleComponent::Attachment))
-                }
+                "__kIMFileTransferGUIDAttributeName" => return Some(BubbleResult::Attachment),
                 "__kIMMentionConfirmedMention" => {
                     return Some(BubbleResult::Continuation(TextAttributes::new(
                         range_start,
@@ -218,6 +237,8 @@ fn get_bubble_type<'a>(
 }
 
 /// Fallback logic to parse the body from the message string content
+///
+/// Segments of text that contain only whitespace are dropped instead of producing empty [`BubbleComponent::Text`] bubbles.
 pub(crate) fn parse_body_legacy(message: &Message) -> Vec<BubbleComponent> {
     let mut out_v = vec![];
     // Naive logic for when `typedstream` component parsing fails
@@ -225,10 +246,11 @@ pub(crate) fn parse_body_legacy(message: &Message) -> Vec<BubbleComponent> {
         Some(text) => {
             let mut start: usize = 0;
             let mut end: usize = 0;
+            let mut num_attachments = 0;
 
             for (idx, char) in text.char_indices() {
                 if REPLACEMENT_CHARS.contains(&char) {
-                    if start < end {
+                    if start < end && !is_blank(text, start, idx) {
                         out_v.push(BubbleComponent::Text(vec![TextAttributes::new(
                             start,
                             idx,
@@ -238,7 +260,10 @@ pub(crate) fn parse_body_legacy(message: &Message) -> Vec<BubbleComponent> {
                     start = idx + 1;
                     end = idx;
                     match char {
-                        ATTACHMENT_CHAR => out_v.push(BubbleComponent::Attachment),
+                        ATTACHMENT_CHAR => {
+                            out_v.push(BubbleComponent::Attachment(num_attachments));
+                            num_attachments += 1;
+                        }
                         APP_CHAR => out_v.push(BubbleComponent::App),
                         _ => {}
                     };
@@ -249,7 +274,7 @@ pub(crate) fn parse_body_legacy(message: &Message) -> Vec<BubbleComponent> {
                     end = idx;
                 }
             }
-            if start <= end && start < text.len() {
+            if start <= end && start < text.len() && !is_blank(text, start, text.len()) {
                 out_v.push(BubbleComponent::Text(vec![TextAttributes::new(
                     start,
                     text.len(),
@@ -262,6 +287,57 @@ pub(crate) fn parse_body_legacy(message: &Message) -> Vec<BubbleComponent> {
     }
 }
 
+/// Parse the body from the message string content without dropping any text
+///
+/// Unlike [`parse_body_legacy()`], whitespace-only segments are kept, and two directly adjacent placeholders are
+/// separated by a zero-length [`BubbleComponent::Text`], so the text ranges and placeholders reconstruct the
+/// source text exactly.
+pub(crate) fn parse_body_raw(message: &Message) -> Vec<BubbleComponent<'_>> {
+    let mut out_v = vec![];
+    let Some(text) = &message.text else {
+        return out_v;
+    };
+
+    let mut start: usize = 0;
+    let mut num_attachments = 0;
+    let mut after_placeholder = false;
+    for (idx, char) in text.char_indices() {
+        if REPLACEMENT_CHARS.contains(&char) {
+            if start < idx || after_placeholder {
+                out_v.push(BubbleComponent::Text(vec![TextAttributes::new(
+                    start,
+                    idx,
+                    TextEffect::Default,
+                )]));
+            }
+            match char {
+                ATTACHMENT_CHAR => {
+                    out_v.push(BubbleComponent::Attachment(num_attachments));
+                    num_attachments += 1;
+                }
+                APP_CHAR => out_v.push(BubbleComponent::App),
+                _ => {}
+            };
+            start = idx + char.len_utf8();
+            after_placeholder = true;
+        }
+    }
+    if start < text.len() {
+        out_v.push(BubbleComponent::Text(vec![TextAttributes::new(
+            start,
+            text.len(),
+            TextEffect::Default,
+        )]));
+    }
+    out_v
+}
+
+/// `true` if the text in the byte range `start..end` contains only whitespace, else `false`
+fn is_blank(text: &str, start: usize, end: usize) -> bool {
+    text.get(start..end)
+        .is_some_and(|segment| segment.trim().is_empty())
+}
+
 #[cfg(test)]
 mod typedstream_tests {
     use std::{env::current_dir, fs::File, io::Read};
@@ -359,7 +435,7 @@ mod typedstream_tests {
 
         assert_eq!(
             parse_body_typedstream(&m).unwrap(),
-            vec![BubbleComponent::Attachment]
+            vec![BubbleComponent::Attachment(0)]
         );
     }
 
@@ -408,11 +484,11 @@ mod typedstream_tests {
         assert_eq!(
             parse_body_typedstream(&m).unwrap(),
             vec![
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(0),
                 BubbleComponent::Text(vec![TextAttributes::new(3, 9, TextEffect::Default)]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(1),
                 BubbleComponent::Text(vec![TextAttributes::new(12, 19, TextEffect::Default)]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(2),
                 BubbleComponent::Text(vec![TextAttributes::new(22, 28, TextEffect::Default)]),
             ]
         );
@@ -440,7 +516,7 @@ mod typedstream_tests {
             parse_body_typedstream(&m).unwrap(),
             vec![
                 BubbleComponent::Text(vec![TextAttributes::new(0, 28, TextEffect::Default)]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(0),
                 BubbleComponent::Text(vec![TextAttributes::new(31, 63, TextEffect::Default)]),
             ]
         );
@@ -496,7 +572,7 @@ mod typedstream_tests {
             parse_body_typedstream(&m).unwrap(),
             vec![
                 BubbleComponent::Text(vec![TextAttributes::new(0, 28, TextEffect::Default)]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(0),
                 BubbleComponent::Text(vec![TextAttributes::new(31, 63, TextEffect::Default)]),
                 BubbleComponent::Retracted,
             ]
@@ -525,12 +601,34 @@ mod typedstream_tests {
         assert_eq!(
             parse_body_typedstream(&m).unwrap(),
             vec![
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(0),
                 BubbleComponent::Text(vec![TextAttributes::new(3, 80, TextEffect::Default)]),
             ]
         );
     }
 
+    #[test]
+    fn can_get_message_body_attachment_blank_text() {
+        let mut m = blank();
+        m.text = Some(format!("\u{FFFC}{}", " ".repeat(77)));
+
+        let typedstream_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/typedstream/Attachment");
+        let mut file = File::open(typedstream_path).unwrap();
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes).unwrap();
+
+        let mut parser = TypedStreamReader::from(&bytes);
+        m.components = parser.parse().ok();
+
+        assert_eq!(
+            parse_body_typedstream(&m).unwrap(),
+            vec![BubbleComponent::Attachment(0)]
+        );
+    }
+
     #[test]
     fn can_get_message_body_attachment_i16() {
         let mut m = blank();
@@ -549,7 +647,7 @@ mod typedstream_tests {
 
         assert_eq!(
             parse_body_typedstream(&m).unwrap(),
-            vec![BubbleComponent::Attachment]
+            vec![BubbleComponent::Attachment(0)]
         );
     }
 
@@ -772,7 +870,7 @@ mod legacy_tests {
     use crate::{
         message_types::text_effects::TextEffect,
         tables::messages::{
-            body::parse_body_legacy,
+            body::{parse_body_legacy, parse_body_raw},
             models::{BubbleComponent, TextAttributes},
         },
     };
@@ -826,7 +924,7 @@ mod legacy_tests {
         assert_eq!(
             parse_body_legacy(&m),
             vec![
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(0),
                 BubbleComponent::Text(vec![TextAttributes::new(3, 14, TextEffect::Default),])
             ]
         );
@@ -854,11 +952,11 @@ mod legacy_tests {
             vec![
                 BubbleComponent::Text(vec![TextAttributes::new(0, 3, TextEffect::Default),]),
                 BubbleComponent::App,
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(0),
                 BubbleComponent::Text(vec![TextAttributes::new(9, 12, TextEffect::Default),]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(1),
                 BubbleComponent::Text(vec![TextAttributes::new(15, 20, TextEffect::Default),]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(2),
                 BubbleComponent::Text(vec![TextAttributes::new(23, 27, TextEffect::Default),]),
             ]
         );
@@ -872,12 +970,161 @@ mod legacy_tests {
             parse_body_legacy(&m),
             vec![
                 BubbleComponent::App,
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(0),
                 BubbleComponent::Text(vec![TextAttributes::new(6, 9, TextEffect::Default),]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(1),
                 BubbleComponent::Text(vec![TextAttributes::new(12, 17, TextEffect::Default),]),
-                BubbleComponent::Attachment,
+                BubbleComponent::Attachment(2),
             ]
         );
     }
+
+    #[test]
+    fn can_get_message_body_whitespace_only() {
+        let mut m = blank();
+        m.text = Some("   ".to_string());
+        assert_eq!(parse_body_legacy(&m), vec![]);
+    }
+
+    #[test]
+    fn can_get_message_body_whitespace_between_attachments() {
+        let mut m = blank();
+        m.text = Some("\u{FFFC} \n\u{FFFC}\t".to_string());
+        assert_eq!(
+            parse_body_legacy(&m),
+            vec![
+                BubbleComponent::Attachment(0),
+                BubbleComponent::Attachment(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn can_get_message_body_attachment_padded_text() {
+        let mut m = blank();
+        m.text = Some("\u{FFFC} Hi ".to_string());
+        assert_eq!(
+            parse_body_legacy(&m),
+            vec![
+                BubbleComponent::Attachment(0),
+                BubbleComponent::Text(vec![TextAttributes::new(3, 7, TextEffect::Default),])
+            ]
+        );
+    }
+
+    #[test]
+    fn can_get_message_body_raw_adjacent_attachments() {
+        let mut m = blank();
+        m.text = Some("A\u{FFFC}\u{FFFC}B".to_string());
+        assert_eq!(
+            parse_body_raw(&m),
+            vec![
+                BubbleComponent::Text(vec![TextAttributes::new(0, 1, TextEffect::Default)]),
+                BubbleComponent::Attachment(0),
+                BubbleComponent::Text(vec![TextAttributes::new(4, 4, TextEffect::Default)]),
+                BubbleComponent::Attachment(1),
+                BubbleComponent::Text(vec![TextAttributes::new(7, 8, TextEffect::Default)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_get_message_body_raw_whitespace() {
+        let mut m = blank();
+        m.text = Some(" \u{FFFC}\n".to_string());
+        assert_eq!(
+            parse_body_raw(&m),
+            vec![
+                BubbleComponent::Text(vec![TextAttributes::new(0, 1, TextEffect::Default)]),
+                BubbleComponent::Attachment(0),
+                BubbleComponent::Text(vec![TextAttributes::new(4, 5, TextEffect::Default)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_get_message_body_raw_leading_placeholders() {
+        let mut m = blank();
+        m.text = Some("\u{FFFC}\u{FFFD}".to_string());
+        assert_eq!(
+            parse_body_raw(&m),
+            vec![
+                BubbleComponent::Attachment(0),
+                BubbleComponent::Text(vec![TextAttributes::new(3, 3, TextEffect::Default)]),
+                BubbleComponent::App,
+            ]
+        );
+    }
+
+    #[test]
+    fn can_get_message_body_raw_empty() {
+        let mut m = blank();
+        assert_eq!(parse_body_raw(&m), vec![]);
+        m.text = Some(String::new());
+        assert_eq!(parse_body_raw(&m), vec![]);
+    }
+}
+
+#[cfg(test)]
+mod utf16_tests {
+    use crate::tables::messages::body::{get_char_idx, utf16_index_table};
+
+    #[test]
+    fn can_get_utf16_table_ascii() {
+        let text = "Hello";
+        assert_eq!(utf16_index_table(text), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn can_get_utf16_table_multibyte_bmp() {
+        // `é` is 2 UTF-8 bytes but a single UTF-16 code unit
+        let text = "café!";
+        assert_eq!(utf16_index_table(text), vec![0, 1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn can_get_utf16_table_surrogate_pair() {
+        // `🙈` is 4 UTF-8 bytes and a surrogate pair of 2 UTF-16 code units
+        let text = "a🙈b";
+        assert_eq!(utf16_index_table(text), vec![0, 1, 5, 5]);
+    }
+
+    #[test]
+    fn can_slice_after_emoji() {
+        // Apple's range for `Bob` starts after the 2 code units of the emoji and the space
+        let text = "🙈 Bob";
+        let table = utf16_index_table(text);
+        let start = get_char_idx(text, 3, &table);
+        let end = get_char_idx(text, 6, &table);
+        assert_eq!(&text[start..end], "Bob");
+    }
+
+    #[test]
+    fn can_slice_inside_surrogate_pair() {
+        // An offset between the halves of a surrogate pair never splits the character
+        let text = "🙈🙉";
+        let table = utf16_index_table(text);
+        let mid = get_char_idx(text, 1, &table);
+        assert_eq!(&text[..mid], "🙈");
+        assert_eq!(&text[mid..], "🙉");
+    }
+
+    #[test]
+    fn can_slice_zwj_sequence() {
+        // `👨‍👩‍👧` is 3 surrogate pairs joined by 2 zero width joiners, 8 UTF-16 code units
+        let text = "👨‍👩‍👧 hi";
+        let table = utf16_index_table(text);
+        let start = get_char_idx(text, 9, &table);
+        let end = get_char_idx(text, 11, &table);
+        assert_eq!(&text[start..end], "hi");
+        assert_eq!(&text[..get_char_idx(text, 8, &table)], "👨‍👩‍👧");
+    }
+
+    #[test]
+    fn can_get_char_idx_past_end() {
+        let text = "a🙈";
+        let table = utf16_index_table(text);
+        assert_eq!(get_char_idx(text, 3, &table), text.len());
+        assert_eq!(get_char_idx(text, 100, &table), text.len());
+    }
 }
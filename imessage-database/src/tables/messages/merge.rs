@@ -0,0 +1,484 @@
+/*!
+ Merge message streams from multiple databases into a single timeline.
+
+ Each device that syncs with iCloud has its own `chat.db`, and the same message can be stored
+ in more than one of them. [`MergedMessages`] performs a streaming k-way merge on the message
+ `date` across one message stream per database, deduplicating messages by their globally
+ unique `guid`.
+
+ Row IDs, such as [`Message::chat_id`] and [`Message::handle_id`], are only meaningful in the
+ database a message was read from, so each merged message is yielded with the index of its source.
+*/
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet, VecDeque},
+};
+
+use crate::{error::table::TableError, tables::messages::Message};
+
+/// A streaming, date-ordered merge of message streams from multiple databases
+///
+/// Only the next message from each source is held in memory, along with the GUIDs of the
+/// messages that were already yielded. Each source must be ordered by date, as produced by
+/// [`Message::stream_rows()`].
+///
+/// When more than one source contains a message with the same `guid` and `date`, the copy with the
+/// most complete data is yielded: a copy with a body is preferred, then the copy with more attachments, then the
+/// copy with more metadata, like read receipts. If copies of a message have different dates, the first one is yielded.
+///
+/// Newer databases may only store the body of a message in the `attributedBody` column, which is not read until
+/// [`Message::generate_text()`] is called. To compare those copies correctly, generate the text of each message
+/// before it is passed to the merge, as in the example below.
+///
+/// If a source yields an error, the error is yielded and that source keeps being read, so no messages
+/// are dropped from any source.
+///
+/// # Example:
+///
+/// ```no_run
+/// use std::path::PathBuf;
+///
+/// use imessage_database::tables::messages::{merge::MergedMessages, Message};
+/// use imessage_database::tables::table::{get_connection, Table};
+/// use imessage_database::util::query_context::QueryContext;
+///
+/// let paths = [PathBuf::from("phone/chat.db"), PathBuf::from("laptop/chat.db")];
+/// let connections: Vec<_> = paths
+///     .iter()
+///     .map(|path| get_connection(path).unwrap())
+///     .collect();
+///
+/// let context = QueryContext::default();
+/// let mut statements: Vec<_> = connections
+///     .iter()
+///     .map(|db| Message::stream_rows(db, &context).unwrap())
+///     .collect();
+/// let streams: Vec<_> = statements
+///     .iter_mut()
+///     .zip(&connections)
+///     .map(|(statement, db)| {
+///         statement
+///             .query_map([], |row| Ok(Message::from_row(row)))
+///             .unwrap()
+///             .map(Message::extract)
+///             .map(move |message| {
+///                 message.map(|mut message| {
+///                     let _ = message.generate_text(db);
+///                     message
+///                 })
+///             })
+///     })
+///     .collect();
+///
+/// for item in MergedMessages::new(streams) {
+///     let (source, message) = item.unwrap();
+///     println!("{source}: {}", message.guid);
+/// }
+/// ```
+pub struct MergedMessages<I> {
+    /// The message streams, one per database
+    sources: Vec<I>,
+    /// The next message from each source that has not been merged yet
+    heads: Vec<Option<Message>>,
+    /// The `date` and source index of each head, smallest first
+    queue: BinaryHeap<Reverse<(i64, usize)>>,
+    /// Messages with the same date that are ready to be yielded
+    pending: VecDeque<(usize, Message)>,
+    /// GUIDs of messages that were already yielded
+    seen: HashSet<String>,
+    /// Errors read from the sources that have not been yielded yet
+    errors: VecDeque<TableError>,
+    /// Whether the first message of each source has been read
+    started: bool,
+}
+
+impl<I> MergedMessages<I>
+where
+    I: Iterator<Item = Result<Message, TableError>>,
+{
+    /// Create a merged stream from one date-ordered message stream per database
+    pub fn new(sources: Vec<I>) -> Self {
+        let heads = sources.iter().map(|_| None).collect();
+        Self {
+            sources,
+            heads,
+            queue: BinaryHeap::new(),
+            pending: VecDeque::new(),
+            seen: HashSet::new(),
+            errors: VecDeque::new(),
+            started: false,
+        }
+    }
+
+    /// Read the next message from a source into its head
+    ///
+    /// Errors are queued to be yielded later instead of stopping the merge, and the source keeps being read
+    /// until it yields a message or ends, so one bad row does not drop the rest of its source.
+    fn advance(&mut self, source: usize) {
+        for item in self.sources[source].by_ref() {
+            match item {
+                Ok(message) => {
+                    self.queue.push(Reverse((message.date, source)));
+                    self.heads[source] = Some(message);
+                    return;
+                }
+                Err(why) => self.errors.push_back(why),
+            }
+        }
+    }
+
+    /// Rank how much data a copy of a message contains, used to pick between duplicates
+    ///
+    /// Copies are compared by whether they have a body, then by their number of attachments, then by how many
+    /// other optional fields are set.
+    fn completeness(message: &Message) -> (bool, i32, usize) {
+        let has_body = message.text.is_some() || message.components.is_some();
+        let metadata = [
+            message.subject.is_some(),
+            message.chat_id.is_some(),
+            message.date_read != 0,
+            message.date_delivered != 0,
+        ]
+        .iter()
+        .filter(|present| **present)
+        .count();
+        (has_body, message.num_attachments, metadata)
+    }
+
+    /// Take every head with the smallest date, keeping the most complete copy of each GUID
+    fn merge_next_date(&mut self) {
+        let Some(Reverse((date, _))) = self.queue.peek().copied() else {
+            return;
+        };
+
+        let mut group: Vec<(usize, Message)> = vec![];
+        while let Some(Reverse((next, source))) = self.queue.peek().copied() {
+            if next != date {
+                break;
+            }
+            self.queue.pop();
+            if let Some(message) = self.heads[source].take() {
+                match group
+                    .iter_mut()
+                    .find(|(_, other)| other.guid == message.guid)
+                {
+                    Some(existing) => {
+                        if Self::completeness(&message) > Self::completeness(&existing.1) {
+                            *existing = (source, message);
+                        }
+                    }
+                    None => group.push((source, message)),
+                }
+            }
+            self.advance(source);
+        }
+
+        for (source, message) in group {
+            if self.seen.insert(message.guid.clone()) {
+                self.pending.push_back((source, message));
+            }
+        }
+    }
+}
+
+impl<I> Iterator for MergedMessages<I>
+where
+    I: Iterator<Item = Result<Message, TableError>>,
+{
+    type Item = Result<(usize, Message), TableError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            for source in 0..self.sources.len() {
+                self.advance(source);
+            }
+            self.started = true;
+        }
+
+        loop {
+            if let Some(why) = self.errors.pop_front() {
+                return Some(Err(why));
+            }
+            if let Some(item) = self.pending.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.queue.is_empty() {
+                return None;
+            }
+            self.merge_next_date();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::table::TableError,
+        tables::{
+            messages::{merge::MergedMessages, Message},
+            table::Table,
+            test_utils::{TestDatabase, TestMessage},
+        },
+        util::query_context::QueryContext,
+    };
+
+    fn guid(n: usize) -> String {
+        format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}")
+    }
+
+    fn merge(fixtures: &[TestDatabase]) -> Vec<(usize, Message)> {
+        let context = QueryContext::default();
+        let mut statements: Vec<_> = fixtures
+            .iter()
+            .map(|fixture| Message::stream_rows(fixture.connection(), &context).unwrap())
+            .collect();
+        let streams: Vec<_> = statements
+            .iter_mut()
+            .map(|statement| {
+                statement
+                    .query_map([], |row| Ok(Message::from_row(row)))
+                    .unwrap()
+                    .map(Message::extract)
+            })
+            .collect();
+        MergedMessages::new(streams).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn can_merge_in_date_order() {
+        let phone = TestDatabase::new();
+        let laptop = TestDatabase::new();
+        for (fixture, dates) in [(&phone, [1, 4, 5]), (&laptop, [2, 3, 6])] {
+            for date in dates {
+                fixture.add_message(
+                    None,
+                    &TestMessage {
+                        guid: &guid(date as usize),
+                        date,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        let merged = merge(&[phone, laptop]);
+        let dates: Vec<i64> = merged.iter().map(|(_, message)| message.date).collect();
+        let sources: Vec<usize> = merged.iter().map(|(source, _)| *source).collect();
+        assert_eq!(dates, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(sources, [0, 1, 1, 0, 0, 1]);
+    }
+
+    #[test]
+    fn can_dedupe_by_guid() {
+        let phone = TestDatabase::new();
+        let laptop = TestDatabase::new();
+        phone.add_message(
+            None,
+            &TestMessage {
+                guid: &guid(1),
+                date: 1,
+                ..Default::default()
+            },
+        );
+        laptop.add_message(
+            None,
+            &TestMessage {
+                guid: &guid(1),
+                text: Some("Hello"),
+                date: 1,
+                ..Default::default()
+            },
+        );
+        for fixture in [&phone, &laptop] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &guid(2),
+                    text: Some("World"),
+                    date: 2,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let merged = merge(&[phone, laptop]);
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged[0].0, 1);
+        assert_eq!(merged[0].1.text.as_deref(), Some("Hello"));
+        assert_eq!(merged[1].0, 0);
+        assert_eq!(merged[1].1.guid, guid(2));
+    }
+
+    #[test]
+    fn can_prefer_copy_with_body() {
+        let phone = TestDatabase::new();
+        let laptop = TestDatabase::new();
+        // The first copy has more metadata, but only the second copy has a body
+        phone.add_message(
+            None,
+            &TestMessage {
+                guid: &guid(1),
+                date: 1,
+                date_read: 2,
+                date_delivered: 2,
+                subject: Some("Re:"),
+                ..Default::default()
+            },
+        );
+        laptop.add_message(
+            None,
+            &TestMessage {
+                guid: &guid(1),
+                date: 1,
+                ..Default::default()
+            },
+        );
+        laptop
+            .connection()
+            .execute(
+                "UPDATE message SET attributedBody = ?1",
+                [std::fs::read(
+                    std::env::current_dir()
+                        .unwrap()
+                        .join("test_data/typedstream/AttributedBodyTextOnly"),
+                )
+                .unwrap()],
+            )
+            .unwrap();
+
+        let context = QueryContext::default();
+        let mut statements: Vec<_> = [&phone, &laptop]
+            .iter()
+            .map(|fixture| Message::stream_rows(fixture.connection(), &context).unwrap())
+            .collect();
+        let streams: Vec<_> = statements
+            .iter_mut()
+            .zip([&phone, &laptop])
+            .map(|(statement, fixture)| {
+                statement
+                    .query_map([], |row| Ok(Message::from_row(row)))
+                    .unwrap()
+                    .map(Message::extract)
+                    .map(move |message| {
+                        message.map(|mut message| {
+                            let _ = message.generate_text(fixture.connection());
+                            message
+                        })
+                    })
+            })
+            .collect();
+        let merged: Vec<_> = MergedMessages::new(streams).map(Result::unwrap).collect();
+
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].0, 1);
+        assert_eq!(merged[0].1.text.as_deref(), Some("Noter test"));
+    }
+
+    #[test]
+    fn can_prefer_copy_with_more_attachments() {
+        let phone = TestDatabase::new();
+        let laptop = TestDatabase::new();
+        for fixture in [&phone, &laptop] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &guid(1),
+                    text: Some("\u{FFFC}"),
+                    date: 1,
+                    date_read: 2,
+                    ..Default::default()
+                },
+            );
+        }
+        laptop.add_attachment(1, "~/Library/Messages/Attachments/a.png", "image/png");
+
+        let merged = merge(&[phone, laptop]);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].0, 1);
+        assert_eq!(merged[0].1.num_attachments, 1);
+    }
+
+    #[test]
+    fn can_dedupe_with_different_dates() {
+        let phone = TestDatabase::new();
+        let laptop = TestDatabase::new();
+        phone.add_message(
+            None,
+            &TestMessage {
+                guid: &guid(1),
+                date: 1,
+                ..Default::default()
+            },
+        );
+        laptop.add_message(
+            None,
+            &TestMessage {
+                guid: &guid(1),
+                date: 2,
+                ..Default::default()
+            },
+        );
+
+        let merged = merge(&[phone, laptop]);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].1.date, 1);
+    }
+
+    fn messages(fixture: &TestDatabase) -> Vec<Message> {
+        let context = QueryContext::default();
+        let mut statement = Message::stream_rows(fixture.connection(), &context).unwrap();
+        statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap())
+            .collect()
+    }
+
+    fn error() -> TableError {
+        TableError::Messages(rusqlite::Error::InvalidQuery)
+    }
+
+    #[test]
+    fn can_merge_with_errors() {
+        let fixture = TestDatabase::new();
+        for date in 1..=5 {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &guid(date as usize),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+        let mut all = messages(&fixture).into_iter();
+        let (one, two, three, four, five) = (
+            all.next().unwrap(),
+            all.next().unwrap(),
+            all.next().unwrap(),
+            all.next().unwrap(),
+            all.next().unwrap(),
+        );
+
+        // The first source fails while priming, the second fails in the middle of the stream
+        let sources = vec![
+            vec![Err(error()), Ok(one), Ok(four)].into_iter(),
+            vec![Ok(two), Err(error()), Ok(three), Ok(five)].into_iter(),
+        ];
+        let items: Vec<_> = MergedMessages::new(sources).collect();
+
+        assert_eq!(items.iter().filter(|item| item.is_err()).count(), 2);
+        let dates: Vec<i64> = items
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|(_, message)| message.date)
+            .collect();
+        assert_eq!(dates, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn can_merge_no_sources() {
+        assert!(merge(&[]).is_empty());
+    }
+}
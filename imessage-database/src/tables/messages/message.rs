@@ -2,32 +2,56 @@
  This module represents common (but not all) columns in the `message` table.
 */
 
-use std::{collections::HashMap, io::Read};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::Read,
+    ops::Range,
+};
 
-use chrono::{offset::Local, DateTime};
+use chrono::{offset::Local, DateTime, Duration, TimeZone};
 use plist::Value;
-use rusqlite::{blob::Blob, Connection, Error, Result, Row, Statement};
+use rusqlite::{
+    blob::Blob, params, params_from_iter, Connection, Error, OptionalExtension, Params, Result,
+    Row, Statement,
+};
+use sha1::{Digest, Sha1};
 
 use crate::{
-    error::{message::MessageError, table::TableError},
+    error::{message::MessageError, plist::PlistParseError, table::TableError},
     message_types::{
         edited::{EditStatus, EditedMessage},
         expressives::{BubbleEffect, Expressive, ScreenEffect},
-        variants::{Announcement, BalloonProvider, CustomBalloon, Reaction, Variant},
+        music::MusicMessage,
+        text_effects::TextEffect,
+        url::URLMessage,
+        variants::{Announcement, BalloonProvider, CustomBalloon, Reaction, URLOverride, Variant},
     },
     tables::{
+        attachment::{Attachment, MediaType},
+        chat::Chat,
         messages::{
-            body::{parse_body_legacy, parse_body_typedstream},
-            models::{BubbleComponent, Service},
+            body::{
+                parse_body_legacy, parse_body_raw, parse_body_typedstream, APP_CHAR,
+                ATTACHMENT_CHAR, REPLACEMENT_CHARS,
+            },
+            models::{
+                BubbleComponent, DateSpan, MessageCursor, PayloadData, Placeholders, PreviewImage,
+                ReadInfo, RecipientKind, Service, BUSINESS_ID_PREFIX,
+            },
         },
         table::{
-            Cacheable, Diagnostic, Table, ATTRIBUTED_BODY, CHAT_MESSAGE_JOIN, MESSAGE,
-            MESSAGE_ATTACHMENT_JOIN, MESSAGE_PAYLOAD, MESSAGE_SUMMARY_INFO, RECENTLY_DELETED,
+            Cacheable, Diagnostic, Table, ATTRIBUTED_BODY, CHAT, CHAT_HANDLE_JOIN,
+            CHAT_MESSAGE_JOIN, HANDLE, MESSAGE, MESSAGE_ATTACHMENT_JOIN, MESSAGE_PAYLOAD,
+            MESSAGE_SUMMARY_INFO, RECENTLY_DELETED,
         },
     },
     util::{
-        dates::{get_local_time, readable_diff},
+        dates::{
+            get_local_time, get_offset, get_time_in, readable_duration, to_timestamp,
+            TimestampScale,
+        },
         output::{done_processing, processing},
+        plist::parse_plist,
         query_context::QueryContext,
         streamtyped,
         typedstream::{models::Archivable, parser::TypedStreamReader},
@@ -37,6 +61,50 @@ use crate::{
 /// The required columns, interpolated into the most recent schema due to performance considerations
 const COLS: &str = "rowid, guid, text, service, handle_id, destination_caller_id, subject, date, date_read, date_delivered, is_from_me, is_read, item_type, other_handle, share_status, share_direction, group_title, group_action_type, associated_message_guid, associated_message_type, balloon_bundle_id, expressive_send_style_id, thread_originator_guid, thread_originator_part, date_edited, chat_id";
 
+/// The text of an `attributedBody` and its [`typedstream`](crate::util::typedstream) components, if they deserialized
+type ParsedBody = (String, Option<Vec<Archivable>>);
+
+/// The maximum number of parameters bound to a single query, the lowest default `SQLITE_MAX_VARIABLE_NUMBER`
+const MAX_QUERY_PARAMS: usize = 999;
+
+/// The maximum number of characters in a [`Message::thread_name()`], excluding the trailing ellipsis
+pub const THREAD_NAME_LENGTH: usize = 50;
+
+/// Words that indicate a message contains a one-time code, used by [`Message::looks_like_otp()`]
+pub const OTP_KEYWORDS: [&str; 7] = [
+    "code",
+    "verification",
+    "verify",
+    "passcode",
+    "one-time",
+    "otp",
+    "2fa",
+];
+
+/// SQL predicate that matches tapbacks and their removals, on a `message` table aliased as `m`
+pub const TAPBACK_PREDICATE: &str = "(
+    IFNULL(m.associated_message_type, 0) BETWEEN 2000 AND 2005
+    OR IFNULL(m.associated_message_type, 0) BETWEEN 3000 AND 3005
+)";
+
+/// SQL predicate that matches stickers placed on another message, on a `message` table aliased as `m`
+///
+/// Standalone stickers are not matched, since they are rendered like normal messages.
+pub const STICKER_PREDICATE: &str =
+    "(m.associated_message_type = 1000 AND m.associated_message_guid IS NOT NULL)";
+
+/// SQL predicate that matches the rows [`Message::is_reaction()`] is `true` for, on a `message` table aliased as `m`
+///
+/// This is the union of [`TAPBACK_PREDICATE`] and [`STICKER_PREDICATE`].
+pub const REACTION_PREDICATE: &str = "(
+    IFNULL(m.associated_message_type, 0) BETWEEN 2000 AND 2005
+    OR IFNULL(m.associated_message_type, 0) BETWEEN 3000 AND 3005
+    OR (m.associated_message_type = 1000 AND m.associated_message_guid IS NOT NULL)
+)";
+
+/// The number of rows to process between calls to the progress callback in [`Message::cache_with_progress()`]
+const PROGRESS_INTERVAL: usize = 1000;
+
 /// Represents a single row in the `message` table.
 #[derive(Debug)]
 #[allow(non_snake_case)]
@@ -144,7 +212,7 @@ impl Table for Message {
     fn get(db: &Connection) -> Result<Statement, TableError> {
         // If the database has `chat_recoverable_message_join`, we can restore some deleted messages.
         // If database has `thread_originator_guid`, we can parse replies, otherwise default to 0
-        Ok(db.prepare(&format!(
+        db.prepare(&format!(
             // macOS Ventura+ and i0S 16+ schema, interpolated with required columns for performance
             "SELECT
                  {COLS},
@@ -173,7 +241,7 @@ impl Table for Message {
                  m.date;
             "
         )))
-        .unwrap_or(db.prepare(&format!(
+        .or_else(|_| db.prepare(&format!(
             // macOS Catalina, iOS 13 and older 
             "SELECT
                  *,
@@ -187,14 +255,16 @@ impl Table for Message {
              ORDER BY
                  m.date;
             "
-        )).map_err(TableError::Messages)?)
-    )
+        )))
+        .or_else(|why| Self::prepare_without_chat_join(db, "ORDER BY m.date;", why))
+        .map_err(TableError::Messages)
     }
 
     fn extract(message: Result<Result<Self, Error>, Error>) -> Result<Self, TableError> {
         match message {
             Ok(Ok(message)) => Ok(message),
-            Err(why) | Ok(Err(why)) => Err(TableError::Messages(why)),
+            Ok(Err(why)) => Err(TableError::CorruptRow("messages", why)),
+            Err(why) => Err(TableError::Messages(why)),
         }
     }
 }
@@ -269,6 +339,16 @@ impl Diagnostic for Message {
 
         let total_messages: i64 = messages_count.query_row([], |r| r.get(0)).unwrap_or(0);
 
+        let mut unknown_types: Vec<i32> = Self::unknown_association_types(db)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        unknown_types.sort_unstable();
+
+        let undecodable_payloads = Self::undecodable_payloads(db)
+            .map(|rowids| rowids.len())
+            .unwrap_or(0);
+
         done_processing();
 
         println!("Message diagnostic data:");
@@ -281,6 +361,12 @@ impl Diagnostic for Message {
                 "    Messages belonging to more than one chat: {messages_in_more_than_one_chat}"
             );
         }
+        if !unknown_types.is_empty() {
+            println!("    Unknown associated message types: {unknown_types:?}");
+        }
+        if undecodable_payloads > 0 {
+            println!("    Messages with undecodable payload data: {undecodable_payloads}");
+        }
         Ok(())
     }
 }
@@ -288,7 +374,9 @@ impl Diagnostic for Message {
 impl Cacheable for Message {
     type K = String;
     type V = HashMap<usize, Vec<Self>>;
-    /// Used for reactions that do not exist in a foreign key table
+    /// Used for tapbacks that do not exist in a foreign key table
+    ///
+    /// Stickers placed on messages are cached separately by [`Message::cache_stickers()`].
     ///
     /// Builds a map like:
     ///
@@ -301,10 +389,43 @@ impl Cacheable for Message {
     /// }
     /// ```
     ///
-    /// Where the `0` and `1` are the reaction indexes in the body of the message mapped by `message_guid`
+    /// Where the `0` and `1` are the tapback indexes in the body of the message mapped by `message_guid`
     fn cache(db: &Connection) -> Result<HashMap<Self::K, Self::V>, TableError> {
+        Self::cache_with_progress(db, &mut |_, _| {})
+    }
+}
+
+impl Message {
+    /// Build the same cache as [`Cacheable::cache()`], invoking `progress` periodically with
+    /// the number of rows processed and the total number of rows to process.
+    ///
+    /// This allows callers to display progress while the reaction cache builds, which can be slow on large databases.
+    pub fn cache_with_progress(
+        db: &Connection,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<HashMap<String, HashMap<usize, Vec<Self>>>, TableError> {
+        Self::cache_associated(db, TAPBACK_PREDICATE, progress)
+    }
+
+    /// Build a map of stickers placed on other messages, in the same shape as [`Cacheable::cache()`]
+    ///
+    /// Stickers are overlaid on the message component they are placed on instead of being listed as
+    /// tapbacks, so they are cached separately. Standalone stickers are rendered like normal messages
+    /// and are not included.
+    pub fn cache_stickers(
+        db: &Connection,
+    ) -> Result<HashMap<String, HashMap<usize, Vec<Self>>>, TableError> {
+        Self::cache_associated(db, STICKER_PREDICATE, &mut |_, _| {})
+    }
+
+    /// Build a map of message GUID to component index to the messages matching `predicate` that target that component
+    fn cache_associated(
+        db: &Connection,
+        predicate: &str,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Result<HashMap<String, HashMap<usize, Vec<Self>>>, TableError> {
         // Create cache for user IDs
-        let mut map: HashMap<Self::K, Self::V> = HashMap::new();
+        let mut map: HashMap<String, HashMap<usize, Vec<Self>>> = HashMap::new();
 
         // Create query, independent of table schema
         let statement = db.prepare(&format!(
@@ -316,10 +437,19 @@ impl Cacheable for Message {
              FROM 
                  message as m 
                  LEFT JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
-             WHERE m.associated_message_guid NOT NULL
+             WHERE {predicate}
             "
         ));
 
+        // Seed the progress callback with the number of rows we will iterate over
+        let total: usize = db
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {MESSAGE} as m WHERE {predicate}"),
+                [],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
         if let Ok(mut statement) = statement {
             // Execute query to build the Handles
             let messages = statement
@@ -327,7 +457,10 @@ impl Cacheable for Message {
                 .map_err(TableError::Messages)?;
 
             // Iterate over the messages and update the map
-            for reaction in messages {
+            for (processed, reaction) in messages.enumerate() {
+                if processed % PROGRESS_INTERVAL == 0 {
+                    progress(processed, total);
+                }
                 let reaction = Self::extract(reaction)?;
                 if reaction.is_reaction() {
                     if let Some((idx, reaction_target_guid)) = reaction.clean_associated_guid() {
@@ -352,33 +485,38 @@ impl Cacheable for Message {
             }
         }
 
+        progress(total, total);
+
         Ok(map)
     }
-}
 
-impl Message {
     /// Generate the text of a message, deserializing it as [`typedstream`](crate::util::typedstream) (and falling back to [`streamtyped`]) data if necessary.
+    ///
+    /// When a message has both a `text` column and an `attributedBody`, the two usually match, but the `text` column is
+    /// occasionally truncated. The precedence is:
+    ///
+    /// - If the `attributedBody` text is the same as `text`, `text` is kept and the `attributedBody` formatting is used
+    /// - If the `attributedBody` text differs and is longer than `text`, the `attributedBody` text and formatting are used
+    /// - Otherwise, `text` is kept without formatting, since the formatting ranges would not match it
+    ///
+    /// Use [`Self::has_divergent_text()`] to find messages where the two differ.
     pub fn generate_text<'a>(&'a mut self, db: &'a Connection) -> Result<&'a str, MessageError> {
-        if self.text.is_none() {
-            // Grab the body data from the table
-            if let Some(body) = self.attributed_body(db) {
-                // Attempt to deserialize the typedstream data
-                let mut typedstream = TypedStreamReader::from(&body);
-                self.components = typedstream.parse().ok();
-
-                // If we deserialize the typedstream, use that data
-                self.text = self
-                    .components
-                    .as_ref()
-                    .and_then(|items| items.first())
-                    .and_then(|item| item.deserialize_as_nsstring())
-                    .map(String::from);
-
-                // If the above parsing failed, fall back to the legacy parser instead
-                if self.text.is_none() {
-                    self.text = Some(
-                        streamtyped::parse(body).map_err(MessageError::StreamTypedParseError)?,
-                    );
+        match &self.text {
+            None => {
+                if let Some((text, components)) = self.parse_attributed_body(db)? {
+                    self.text = Some(text);
+                    self.components = components;
+                }
+            }
+            Some(text) => {
+                // The `text` column is usable on its own, so only use the `attributedBody` if it parses
+                if let Ok(Some((body_text, components))) = self.parse_attributed_body(db) {
+                    if body_text == *text {
+                        self.components = components;
+                    } else if body_text.len() > text.len() {
+                        self.text = Some(body_text);
+                        self.components = components;
+                    }
                 }
             }
         }
@@ -398,6 +536,53 @@ impl Message {
         }
     }
 
+    /// Deserialize a message's `attributedBody` into its text and [`typedstream`](crate::util::typedstream) components
+    ///
+    /// Returns `None` if the message has no `attributedBody`.
+    fn parse_attributed_body(&self, db: &Connection) -> Result<Option<ParsedBody>, MessageError> {
+        let Some(body) = self.attributed_body(db) else {
+            return Ok(None);
+        };
+
+        // Attempt to deserialize the typedstream data
+        let mut typedstream = TypedStreamReader::from(&body);
+        let components = typedstream.parse().ok();
+
+        // If we deserialize the typedstream, use that data
+        let text = components
+            .as_ref()
+            .and_then(|items| items.first())
+            .and_then(|item| item.deserialize_as_nsstring())
+            .map(String::from);
+
+        // If the above parsing failed, fall back to the legacy parser instead
+        let text = match text {
+            Some(text) => text,
+            None => streamtyped::parse(body).map_err(MessageError::StreamTypedParseError)?,
+        };
+        Ok(Some((text, components)))
+    }
+
+    /// `true` if the message's `text` column and `attributedBody` both contain text but disagree, else `false`
+    ///
+    /// This reads both values from the table, so it does not depend on whether [`Self::generate_text()`] was called.
+    /// It is meant for diagnosing messages that render with truncated or outdated text.
+    pub fn has_divergent_text(&self, db: &Connection) -> bool {
+        let text: Option<String> = db
+            .query_row(
+                &format!("SELECT text FROM {MESSAGE} WHERE ROWID = ?1"),
+                params![self.rowid],
+                |row| row.get(0),
+            )
+            .ok()
+            .flatten();
+
+        match (text, self.parse_attributed_body(db)) {
+            (Some(text), Ok(Some((body_text, _)))) => text != body_text,
+            _ => false,
+        }
+    }
+
     /// Get a vector of a message body's components. If the text has not been captured with [`Self::generate_text()`], the vector will be empty.
     ///
     /// # Parsing
@@ -421,7 +606,7 @@ impl Message {
     /// use imessage_database::tables::messages::models::{TextAttributes, BubbleComponent};
     ///  
     /// let result = vec![
-    ///     BubbleComponent::Attachment,
+    ///     BubbleComponent::Attachment(0),
     ///     BubbleComponent::Text(vec![TextAttributes::new(3, 24, TextEffect::Default)]), // `Check out this photo!`
     /// ];
     /// ```
@@ -448,6 +633,138 @@ impl Message {
         parse_body_legacy(self)
     }
 
+    /// Get the components of the message by splitting its text at each placeholder character, keeping all text
+    ///
+    /// [`Self::body()`] is meant for display: it uses the `typedstream` formatting ranges when available, drops
+    /// segments that contain only whitespace, and represents app messages as a single [`BubbleComponent::App`].
+    /// This method only reads the text, so every character belongs to exactly one component: whitespace is kept
+    /// in unstyled [`BubbleComponent::Text`] bubbles, and two directly adjacent placeholders are separated by a
+    /// zero-length text bubble. The number and order of components, along with their ranges, reconstruct the
+    /// source text exactly.
+    ///
+    /// The text must be populated with [`Self::generate_text()`] first.
+    pub fn body_raw(&self) -> Vec<BubbleComponent<'_>> {
+        parse_body_raw(self)
+    }
+
+    /// Get the byte range in [`text`](Self::text) that each component of [`Self::body()`] corresponds to
+    ///
+    /// The result is parallel to [`Self::body()`]: the `n`th range belongs to the `n`th component.
+    ///
+    /// - [`BubbleComponent::Text`] spans from the start of its first attribute to the end of its last attribute
+    /// - [`BubbleComponent::Attachment`] and [`BubbleComponent::App`] span the `U+FFFC` or `U+FFFD` placeholder they replace
+    /// - [`BubbleComponent::Retracted`] is `None`, since unsent text is removed from the message
+    ///
+    /// Placeholders are matched in order, so the `n`th attachment component spans the `n`th `U+FFFC` character.
+    /// Components whose placeholder is missing from the text are `None`. The text must be populated with
+    /// [`Self::generate_text()`] first.
+    pub fn body_ranges(&self) -> Vec<Option<Range<usize>>> {
+        let text = self.text.as_deref().unwrap_or_default();
+        let mut cursor = 0;
+
+        // Find the next placeholder character at or after the cursor
+        let placeholder = |cursor: &mut usize, target: char| {
+            let start = *cursor + text.get(*cursor..)?.find(target)?;
+            *cursor = start + target.len_utf8();
+            Some(start..*cursor)
+        };
+
+        self.body()
+            .iter()
+            .map(|component| match component {
+                BubbleComponent::Text(attrs) => {
+                    let start = attrs.iter().map(|attr| attr.start).min()?;
+                    let end = attrs.iter().map(|attr| attr.end).max()?;
+                    cursor = cursor.max(end);
+                    Some(start..end)
+                }
+                BubbleComponent::Attachment(_) => placeholder(&mut cursor, ATTACHMENT_CHAR),
+                BubbleComponent::App => placeholder(&mut cursor, APP_CHAR),
+                BubbleComponent::Retracted => None,
+            })
+            .collect()
+    }
+
+    /// Get the message's text without the attachment and app message placeholder characters
+    ///
+    /// The text must be populated with [`Self::generate_text()`] first. Use [`Self::clean_text_with()`] to replace
+    /// the placeholders instead of removing them.
+    pub fn clean_text(&self) -> Option<String> {
+        self.clean_text_with(&Placeholders::default())
+    }
+
+    /// Get the message's text with the attachment and app message placeholder characters replaced
+    ///
+    /// For example, [`Placeholders`] with an `attachment` of `[attachment]` renders `\u{FFFC}Look!` as `[attachment]Look!`.
+    /// The text must be populated with [`Self::generate_text()`] first.
+    pub fn clean_text_with(&self, placeholders: &Placeholders) -> Option<String> {
+        let text = self.text.as_deref()?;
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                ATTACHMENT_CHAR => out.push_str(placeholders.attachment),
+                APP_CHAR => out.push_str(placeholders.app),
+                c => out.push(c),
+            }
+        }
+        Some(out)
+    }
+
+    /// Get a fingerprint of the message's content, used to detect messages that changed between exports
+    ///
+    /// The [`guid`](Self::guid) identifies a message, but does not change when a message is edited or unsent. This
+    /// hashes the `text`, `subject`, `date`, `date_edited`, `is_from_me`, and `num_attachments` fields with SHA-1
+    /// and returns the first 8 bytes of the digest as a big-endian integer. Each field is written with a fixed
+    /// width or a length prefix, so the fingerprint is the same on every platform and for every run.
+    ///
+    /// The text must be populated with [`Self::generate_text()`] first.
+    pub fn content_fingerprint(&self) -> u64 {
+        let mut hasher = Sha1::new();
+        for field in [self.text.as_deref(), self.subject.as_deref()] {
+            match field {
+                Some(value) => {
+                    hasher.update([1]);
+                    hasher.update((value.len() as u64).to_be_bytes());
+                    hasher.update(value.as_bytes());
+                }
+                None => hasher.update([0]),
+            }
+        }
+        hasher.update(self.date.to_be_bytes());
+        hasher.update(self.date_edited.to_be_bytes());
+        hasher.update([u8::from(self.is_from_me)]);
+        hasher.update(self.num_attachments.to_be_bytes());
+
+        let digest = hasher.finalize();
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        u64::from_be_bytes(bytes)
+    }
+
+    /// Get the length of the message's text in bytes, or `0` if there is no text
+    ///
+    /// The text must be populated with [`Self::generate_text()`] first.
+    pub fn text_byte_len(&self) -> usize {
+        self.text.as_ref().map_or(0, |text| text.len())
+    }
+
+    /// `true` if the message's text is populated, even if it is empty, else `false`
+    ///
+    /// Unlike [`Self::text_byte_len()`], this distinguishes empty text from text that is missing.
+    pub fn has_text(&self) -> bool {
+        self.text.is_some()
+    }
+
+    /// Get the content of the message's Subject field, if it is set.
+    ///
+    /// The subject is rendered before the message body, but it is not a part of [`Self::body()`]
+    /// because reactions and replies refer to body components by their index.
+    pub fn subject(&self) -> Option<&str> {
+        self.subject
+            .as_deref()
+            .filter(|subject| !subject.is_empty())
+    }
+
     /// Calculates the date a message was written to the database.
     ///
     /// This field is stored as a unix timestamp with an epoch of `2001-01-01 00:00:00` in the local time zone
@@ -476,27 +793,93 @@ impl Message {
         get_local_time(&self.date_edited, offset)
     }
 
+    /// Calculates the date a message was written to the database in the time zone `tz`
+    ///
+    /// See [`get_time_in()`] for details.
+    pub fn date_in_zone<Tz: TimeZone>(
+        &self,
+        offset: &i64,
+        tz: &Tz,
+    ) -> Result<DateTime<Tz>, MessageError> {
+        get_time_in(&self.date, offset, tz)
+    }
+
+    /// Calculates the date a message was marked as delivered in the time zone `tz`
+    ///
+    /// See [`get_time_in()`] for details.
+    pub fn date_delivered_in_zone<Tz: TimeZone>(
+        &self,
+        offset: &i64,
+        tz: &Tz,
+    ) -> Result<DateTime<Tz>, MessageError> {
+        get_time_in(&self.date_delivered, offset, tz)
+    }
+
+    /// Calculates the date a message was marked as read in the time zone `tz`
+    ///
+    /// See [`get_time_in()`] for details.
+    pub fn date_read_in_zone<Tz: TimeZone>(
+        &self,
+        offset: &i64,
+        tz: &Tz,
+    ) -> Result<DateTime<Tz>, MessageError> {
+        get_time_in(&self.date_read, offset, tz)
+    }
+
     /// Gets the time until the message was read. This can happen in two ways:
     ///
     /// - You received a message, then waited to read it
     /// - You sent a message, and the recipient waited to read it
     ///
     /// In the former case, this subtracts the date read column (`date_read`) from the date received column (`date`).
+    /// If `date_read` precedes `date`, i.e. due to clock skew, the date delivered column (`date_delivered`) is used instead.
     /// In the latter case, this subtracts the date delivered column (`date_delivered`) from the date received column (`date`).
     ///
     /// Not all messages get tagged with the read properties.
     /// If more than one message has been sent in a thread before getting read,
     /// only the most recent message will get the tag.
+    ///
+    /// Messages that were read at the same time they were sent produce `0 seconds`,
+    /// see [`readable_duration()`] for details.
     pub fn time_until_read(&self, offset: &i64) -> Option<String> {
-        // Message we received
-        if !self.is_from_me && self.date_read != 0 && self.date != 0 {
-            return readable_diff(self.date(offset), self.date_read(offset));
+        readable_duration(self.time_until_read_duration(offset)?)
+    }
+
+    /// Gets the time until the message was read as a [`Duration`], using the same rules as [`Self::time_until_read()`].
+    ///
+    /// This is useful for computing aggregates, like the average time it takes to read a message.
+    pub fn time_until_read_duration(&self, offset: &i64) -> Option<Duration> {
+        if self.date == 0 {
+            return None;
         }
-        // Message we sent
-        else if self.is_from_me && self.date_delivered != 0 && self.date != 0 {
-            return readable_diff(self.date(offset), self.date_delivered(offset));
+        let date = self.date(offset).ok()?;
+
+        // Messages we received may have a `date_read` that precedes `date` due to clock skew,
+        // so fall back to `date_delivered` to avoid treating the message as unread
+        let candidates: &[i64] = if self.is_from_me {
+            &[self.date_delivered]
+        } else {
+            &[self.date_read, self.date_delivered]
+        };
+
+        candidates
+            .iter()
+            .filter(|stamp| **stamp != 0)
+            .filter_map(|stamp| get_local_time(stamp, offset).ok())
+            .map(|read| read - date)
+            .find(|diff| *diff >= Duration::zero())
+    }
+
+    /// Gets the time until the message was read, labeled with the direction the message was sent in
+    ///
+    /// This uses the same rules as [`Self::time_until_read_duration()`], returning [`ReadInfo::Unknown`] where
+    /// that returns `None`.
+    pub fn read_info(&self, offset: &i64) -> ReadInfo {
+        match self.time_until_read_duration(offset) {
+            Some(duration) if self.is_from_me => ReadInfo::SentReadAfter(duration),
+            Some(duration) => ReadInfo::ReceivedReadAfter(duration),
+            None => ReadInfo::Unknown,
         }
-        None
     }
 
     /// `true` if the message is a response to a thread, else `false`
@@ -504,6 +887,57 @@ impl Message {
         self.thread_originator_guid.is_some()
     }
 
+    /// Get the GUID of the message this reply belongs under, if the message is a reply
+    ///
+    /// The `message` table does not store the immediate parent of a reply, only the first message of the thread in
+    /// `thread_originator_guid`. Replying to a reply adds to the same thread, so threads are flat: every reply is a
+    /// direct child of the originator, and this is an alias for [`thread_originator_guid`](Self::thread_originator_guid).
+    /// Exporters that build reply trees should nest replies one level deep, ordered by date.
+    pub fn parent_guid(&self) -> Option<&str> {
+        self.thread_originator_guid.as_deref()
+    }
+
+    /// Detect an inline reply sent over SMS, returning the quoted text and the new text
+    ///
+    /// SMS has no threading, so clients that reply inline over SMS quote the original message
+    /// in the body instead of setting `thread_originator_guid`. This detects bodies where the
+    /// first line is a quote and the following lines are the reply, in either form:
+    ///
+    /// ```txt
+    /// > quoted text
+    /// reply text
+    /// ```
+    ///
+    /// ```txt
+    /// "quoted text"
+    /// reply text
+    /// ```
+    ///
+    /// Curly quotes (`“` and `”`) are also accepted. This is a best-effort heuristic: it requires
+    /// [`Self::generate_text()`] to have populated the text and always returns `None` for
+    /// messages that are not sent over SMS, since iMessage uses proper threading.
+    pub fn sms_quoted_reply(&self) -> Option<(&str, &str)> {
+        if !matches!(self.service(), Service::SMS | Service::MMS) || self.is_reply() {
+            return None;
+        }
+
+        let (first_line, reply) = self.text.as_deref()?.split_once('\n')?;
+        let first_line = first_line.trim();
+        let quoted = match first_line.strip_prefix('>') {
+            Some(quoted) => quoted,
+            None => first_line
+                .strip_prefix(['"', '“'])?
+                .strip_suffix(['"', '”'])?,
+        }
+        .trim();
+        let reply = reply.trim();
+
+        if quoted.is_empty() || reply.is_empty() {
+            return None;
+        }
+        Some((quoted, reply))
+    }
+
     /// `true` if the message renames a thread, else `false`
     pub fn is_announcement(&self) -> bool {
         self.group_title.is_some() || self.group_action_type != 0 || self.is_fully_unsent()
@@ -520,16 +954,101 @@ impl Message {
         matches!(self.variant(), Variant::Sticker(_))
     }
 
+    /// `true` if the message is a sticker without any text of its own, else `false`
+    ///
+    /// Attachment and app placeholder characters do not count as text, so a sticker whose text
+    /// is only its attachment placeholder is standalone. The text must be populated with
+    /// [`Self::generate_text()`] first.
+    pub fn is_standalone_sticker(&self) -> bool {
+        if !self.is_sticker() {
+            return false;
+        }
+        match &self.text {
+            Some(text) => text
+                .chars()
+                .all(|c| c.is_whitespace() || REPLACEMENT_CHARS.contains(&c)),
+            None => true,
+        }
+    }
+
     /// `true` if the message has an expressive presentation, else `false`
     pub fn is_expressive(&self) -> bool {
         self.expressive_send_style_id.is_some()
     }
 
+    /// `true` if the message has an expressive and was sent over a service that displays it, else `false`
+    ///
+    /// Expressives are an iMessage feature. When a message with an expressive is sent to an SMS contact, the
+    /// `expressive_send_style_id` is still stored but the recipient only receives plain text. Use this with
+    /// [`Self::is_expressive()`] to report an effect that was present but not delivered.
+    pub fn effect_delivered(&self) -> bool {
+        self.is_expressive() && matches!(self.service(), Service::iMessage)
+    }
+
+    /// `true` if the message looks like a one-time code or verification message, else `false`
+    ///
+    /// This is a heuristic meant for optional filtering, so false positives are acceptable. A message matches when:
+    /// - iMessage marked part of the body as a [`TextEffect::OTP`], or
+    /// - the text contains one of [`OTP_KEYWORDS`] (case insensitive) and a standalone run of 4 to 8 digits,
+    ///   i.e. `Your verification code is 123456` or `G-482913 is your Google code`
+    ///
+    /// The text must be populated with [`Self::generate_text()`] first.
+    pub fn looks_like_otp(&self) -> bool {
+        let text = match &self.text {
+            Some(text) => text,
+            None => return false,
+        };
+
+        let marked = self.body().iter().any(|component| match component {
+            BubbleComponent::Text(attrs) => attrs
+                .iter()
+                .any(|attr| matches!(attr.effect, TextEffect::OTP)),
+            _ => false,
+        });
+        if marked {
+            return true;
+        }
+
+        let lowercase = text.to_lowercase();
+        OTP_KEYWORDS
+            .iter()
+            .any(|keyword| lowercase.contains(keyword))
+            && text
+                .split(|c: char| !c.is_ascii_digit())
+                .any(|digits| (4..=8).contains(&digits.len()))
+    }
+
     /// `true` if the message has a URL preview, else `false`
     pub fn is_url(&self) -> bool {
         matches!(self.variant(), Variant::App(CustomBalloon::URL))
     }
 
+    /// `true` if the message was sent by a personal safety feature, else `false`
+    ///
+    /// Safety messages, like [Check In](https://support.apple.com/guide/iphone/use-check-in-iphc143bb7e9/ios) timers
+    /// and the alerts sent when one expires, use the `com.apple.SafetyMonitorApp.SafetyMonitorMessages` bundle ID and are
+    /// parsed as [`CustomBalloon::CheckIn`].
+    ///
+    /// Emergency SOS notifications sent to emergency contacts are regular SMS text messages without a bundle ID, so they
+    /// cannot be told apart from other messages and are not detected.
+    pub fn is_sos(&self) -> bool {
+        matches!(self.variant(), Variant::App(CustomBalloon::CheckIn))
+    }
+
+    /// `true` if the message is a [`URL`](CustomBalloon::URL) preview whose text is only the link, else `false`
+    ///
+    /// A [`URL`](CustomBalloon::URL) message's [`body()`](Self::body) is always a single [`BubbleComponent::App`], so
+    /// this checks the message text instead: it must be empty or a single token without whitespace. Exporters can show
+    /// only the preview card for these messages, and show the text as well when there is more than the link.
+    ///
+    /// The text must be populated with [`Self::generate_text()`] first.
+    pub fn is_bare_url(&self) -> bool {
+        self.is_url()
+            && !self
+                .clean_text()
+                .is_some_and(|text| text.trim().contains(char::is_whitespace))
+    }
+
     /// `true` if the message was edited, else `false`
     pub fn is_edited(&self) -> bool {
         self.date_edited != 0
@@ -564,6 +1083,34 @@ impl Message {
         self.num_replies > 0
     }
 
+    /// `true` if the message is an ephemeral indicator with no content, such as a typing indicator, else `false`
+    ///
+    /// Typing indicators and similar ephemeral states are sent over the network but are not written to the
+    /// `message` table by any known version of Messages, so there is no `item_type` or
+    /// `associated_message_type` combination that identifies them and this always returns `false`.
+    /// Exporters can still call it to skip such rows, should a future schema start storing them.
+    pub fn is_ephemeral(&self) -> bool {
+        false
+    }
+
+    /// `true` if the message is a non-conversational row that exporters usually render as centered status text, else `false`
+    ///
+    /// This covers:
+    ///
+    /// - [Announcements](Self::is_announcement): group renames, group photo changes, and fully unsent messages
+    /// - Group membership changes: participants being added to, removed from, or leaving a group (`item_type` `1` and `3`)
+    /// - Location sharing starting or stopping (`item_type` `4`)
+    /// - [Ephemeral](Self::is_ephemeral) indicators
+    ///
+    /// [SharePlay](Self::is_shareplay) messages are not included, since they are rendered as message bubbles.
+    pub fn is_system_message(&self) -> bool {
+        self.is_announcement()
+            || matches!(self.item_type, 1 | 3)
+            || self.started_sharing_location()
+            || self.stopped_sharing_location()
+            || self.is_ephemeral()
+    }
+
     /// `true` if the message is a SharePlay/FaceTime message, else `false`
     pub fn is_shareplay(&self) -> bool {
         self.item_type == 6
@@ -626,10 +1173,10 @@ impl Message {
     /// Message::get_count(&conn, &context);
     /// ```
     pub fn get_count(db: &Connection, context: &QueryContext) -> Result<u64, TableError> {
-        let mut statement = if context.has_filters() {
+        let mut statement = if context.has_filters() || !context.include_reactions {
             db.prepare(&format!(
                 "SELECT COUNT(*) FROM {MESSAGE} as m {}",
-                context.generate_filter_statement("m.date")
+                Self::generate_filter_statement(context)
             ))
             .map_err(TableError::Messages)?
         } else {
@@ -641,8 +1188,185 @@ impl Message {
         Ok(count)
     }
 
+    /// Infer the [`TimestampScale`] of a database from the first message with a date
+    ///
+    /// Detect the scale once and pass it to [`Self::normalize_dates()`] for each message. Defaults to
+    /// [`TimestampScale::Nanoseconds`] if there are no dated messages or the scale cannot be determined.
+    pub fn timestamp_scale(db: &Connection) -> Result<TimestampScale, TableError> {
+        let first: Option<i64> = db
+            .query_row(
+                &format!("SELECT date FROM {MESSAGE} WHERE date > 0 ORDER BY ROWID LIMIT 1"),
+                [],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(TableError::Messages)?;
+        Ok(first.and_then(TimestampScale::detect).unwrap_or_default())
+    }
+
+    /// Convert the message's dates from the given [`TimestampScale`] to nanoseconds, the scale expected by the date methods
+    pub fn normalize_dates(&mut self, scale: TimestampScale) {
+        for stamp in [
+            &mut self.date,
+            &mut self.date_read,
+            &mut self.date_delivered,
+            &mut self.date_edited,
+        ] {
+            *stamp = scale.to_nanoseconds(*stamp);
+        }
+    }
+
+    /// Get the number of messages for each handle, sorted from most to fewest messages
+    ///
+    /// Messages received from a contact are counted under that contact's `handle_id`. Messages sent by the
+    /// database owner (`is_from_me`) are all counted under handle `0`, since the `handle_id` of a sent message
+    /// is the recipient in direct messages but `0` in group chats. Reactions are not counted.
+    ///
+    /// Combine this with the [`Handle`](crate::tables::handle::Handle) cache to build a list of top contacts.
+    pub fn message_counts_by_handle(db: &Connection) -> Result<Vec<(i32, u64)>, TableError> {
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT
+                     CASE WHEN m.is_from_me THEN 0 ELSE IFNULL(m.handle_id, 0) END as sender,
+                     COUNT(*) as num_messages
+                 FROM {MESSAGE} as m
+                 WHERE NOT {REACTION_PREDICATE}
+                 GROUP BY sender
+                 ORDER BY num_messages DESC, sender"
+            ))
+            .map_err(TableError::Messages)?;
+
+        let counts = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(TableError::Messages)?
+            .collect::<Result<Vec<(i32, u64)>, _>>()
+            .map_err(TableError::Messages)?;
+        Ok(counts)
+    }
+
+    /// Get the dates of the first and last messages in a chat
+    ///
+    /// Returns `None` if the chat has no messages.
+    pub fn chat_date_span(
+        db: &Connection,
+        chat_id: i32,
+        offset: &i64,
+    ) -> Result<Option<DateSpan>, TableError> {
+        let (first, last): (Option<i64>, Option<i64>) = db
+            .query_row(
+                &format!(
+                    "SELECT MIN(m.date), MAX(m.date)
+                     FROM {MESSAGE} as m
+                         INNER JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+                     WHERE c.chat_id = ?1"
+                ),
+                params![chat_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(TableError::Messages)?;
+
+        match (first, last) {
+            (Some(first), Some(last)) => Ok(get_local_time(&first, offset)
+                .ok()
+                .zip(get_local_time(&last, offset).ok())),
+            _ => Ok(None),
+        }
+    }
+
+    /// Prepare a statement that selects every message like [`Table::get()`], but without counting
+    /// attachments and replies
+    ///
+    /// The correlated subqueries that count each message's attachments and replies dominate the cost of a
+    /// full table scan, so this is much faster when the counts are not needed. In messages from this statement,
+    /// `num_attachments` and `num_replies` are always `0` and `deleted_from` is always `None`, so
+    /// [`Self::has_attachments()`], [`Self::has_replies()`], and [`Self::is_deleted()`] are unreliable.
+    pub fn get_minimal(db: &Connection) -> Result<Statement<'_>, TableError> {
+        db.prepare(&format!(
+            // macOS Ventura+ and i0S 16+ schema, interpolated with required columns for performance
+            "SELECT
+                 {COLS},
+                 0 as num_attachments,
+                 NULL as deleted_from,
+                 0 as num_replies
+             FROM
+                 message as m
+                 LEFT JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+             ORDER BY
+                 m.date;
+            "
+        ))
+        .or_else(|_| {
+            // Older schemas that do not have all of the required columns
+            db.prepare(&format!(
+                "SELECT
+                     *,
+                     c.chat_id,
+                     0 as num_attachments,
+                     NULL as deleted_from,
+                     0 as num_replies
+                 FROM
+                     message as m
+                     LEFT JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+                 ORDER BY
+                     m.date;
+                "
+            ))
+        })
+        .map_err(TableError::Messages)
+    }
+
+    /// Get the set of `associated_message_type` values in the table that [`Self::variant()`]
+    /// does not recognize and reports as [`Variant::Unknown`]
+    ///
+    /// This is useful for reporting new message types added in future versions of iMessage.
+    pub fn unknown_association_types(db: &Connection) -> Result<HashSet<i32>, TableError> {
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT DISTINCT associated_message_type FROM {MESSAGE} WHERE associated_message_type IS NOT NULL"
+            ))
+            .map_err(TableError::Messages)?;
+
+        let types = statement
+            .query_map([], |row| row.get::<_, i32>(0))
+            .map_err(TableError::Messages)?;
+
+        let mut unknown = HashSet::new();
+        for association_type in types {
+            let association_type = association_type.map_err(TableError::Messages)?;
+            if !Self::is_known_association_type(association_type) {
+                unknown.insert(association_type);
+            }
+        }
+        Ok(unknown)
+    }
+
+    /// `true` if [`Self::variant()`] handles the `associated_message_type`, else `false`
+    fn is_known_association_type(association_type: i32) -> bool {
+        matches!(
+            association_type,
+            0 | 2 | 3 | 1000 | 2000..=2005 | 3000..=3005
+        )
+    }
+
+    /// Generate the `WHERE` clause for the filters in a [`QueryContext`], including the reaction filter
+    fn generate_filter_statement(context: &QueryContext) -> String {
+        let filters = context.generate_filter_statement("m.date");
+        if context.include_reactions {
+            return filters;
+        }
+
+        if filters.is_empty() {
+            format!(" WHERE NOT {REACTION_PREDICATE}")
+        } else {
+            format!("{filters} AND NOT {REACTION_PREDICATE}")
+        }
+    }
+
     /// Stream messages from the database with optional filters
     ///
+    /// If [`QueryContext::include_reactions`] is `false`, reactions and stickers placed on other messages are
+    /// excluded from the stream, since they are rendered with the message they target.
+    ///
     /// # Example:
     ///
     /// ```
@@ -660,14 +1384,14 @@ impl Message {
         db: &'a Connection,
         context: &'a QueryContext,
     ) -> Result<Statement<'a>, TableError> {
-        if !context.has_filters() {
+        if !context.has_filters() && context.include_reactions {
             return Self::get(db);
         }
 
-        let filters = context.generate_filter_statement("m.date");
+        let filters = Self::generate_filter_statement(context);
 
         // If database has `thread_originator_guid`, we can parse replies, otherwise default to 0
-        Ok(db.prepare(&format!(
+        db.prepare(&format!(
                 "SELECT
                      *,
                      c.chat_id,
@@ -682,7 +1406,7 @@ impl Message {
                      m.date;
                 "
             ))
-            .unwrap_or(db.prepare(&format!(
+            .or_else(|_| db.prepare(&format!(
                 "SELECT
                      *,
                      c.chat_id,
@@ -696,78 +1420,553 @@ impl Message {
                  ORDER BY
                      m.date;
                 "
-            )).map_err(TableError::Messages)?))
-    }
-
-    /// See [`Reaction`] for details on this data.
-    fn clean_associated_guid(&self) -> Option<(usize, &str)> {
-        if let Some(guid) = &self.associated_message_guid {
-            if guid.starts_with("p:") {
-                let mut split = guid.split('/');
-                let index_str = split.next()?;
-                let message_id = split.next()?;
-                let index = str::parse::<usize>(&index_str.replace("p:", "")).unwrap_or(0);
-                return Some((index, message_id.get(0..36)?));
-            } else if guid.starts_with("bp:") {
-                return Some((0, guid.get(3..39)?));
-            }
-
-            return Some((0, guid.get(0..36)?));
-        }
-        None
+            )))
+            .or_else(|why| {
+                Self::prepare_without_chat_join(db, &format!("{filters} ORDER BY m.date;"), why)
+            })
+            .map_err(TableError::Messages)
     }
 
-    /// Parse the index of a reaction from it's associated GUID field
-    fn reaction_index(&self) -> usize {
-        match self.clean_associated_guid() {
-            Some((x, _)) => x,
-            None => 0,
-        }
-    }
-
-    /// Build a `HashMap` of message component index to messages that react to that component
-    pub fn get_reactions(
-        &self,
-        db: &Connection,
-        reactions: &HashMap<String, Vec<String>>,
-    ) -> Result<HashMap<usize, Vec<Self>>, TableError> {
-        let mut out_h: HashMap<usize, Vec<Self>> = HashMap::new();
-        if let Some(rxs) = reactions.get(&self.guid) {
-            let filter: Vec<String> = rxs.iter().map(|guid| format!("\"{guid}\"")).collect();
-            // Create query
-            let mut statement = db.prepare(&format!(
-                "SELECT 
-                        *, 
-                        c.chat_id, 
-                        (SELECT COUNT(*) FROM {MESSAGE_ATTACHMENT_JOIN} a WHERE m.ROWID = a.message_id) as num_attachments,
-                        (SELECT COUNT(*) FROM {MESSAGE} m2 WHERE m2.thread_originator_guid = m.guid) as num_replies
-                    FROM 
-                        message as m 
-                        LEFT JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
-                    WHERE m.guid IN ({})
-                    ORDER BY 
-                        m.date;
-                    ",
-                filter.join(",")
-            )).map_err(TableError::Messages)?;
+    /// Prepare a statement that selects messages with the same columns as [`Self::stream_rows()`], followed by
+    /// the provided `WHERE`, `ORDER BY`, and `LIMIT` clauses.
+    ///
+    /// Any parameters referenced in `clauses` must be bound by the caller when executing the statement.
+    fn prepare_with_clauses<'a>(
+        db: &'a Connection,
+        clauses: &str,
+    ) -> Result<Statement<'a>, TableError> {
+        // If database has `thread_originator_guid`, we can parse replies, otherwise default to 0
+        db.prepare(&format!(
+            "SELECT
+                 *,
+                 c.chat_id,
+                 (SELECT COUNT(*) FROM {MESSAGE_ATTACHMENT_JOIN} a WHERE m.ROWID = a.message_id) as num_attachments,
+                 (SELECT b.chat_id FROM {RECENTLY_DELETED} b WHERE m.ROWID = b.message_id) as deleted_from,
+                 (SELECT COUNT(*) FROM {MESSAGE} m2 WHERE m2.thread_originator_guid = m.guid) as num_replies
+             FROM
+                 message as m
+                 LEFT JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+             {clauses}
+            "
+        ))
+        .or_else(|_| {
+            db.prepare(&format!(
+                "SELECT
+                     *,
+                     c.chat_id,
+                     (SELECT COUNT(*) FROM {MESSAGE_ATTACHMENT_JOIN} a WHERE m.ROWID = a.message_id) as num_attachments,
+                     (SELECT NULL) as deleted_from,
+                     (SELECT 0) as num_replies
+                 FROM
+                     message as m
+                     LEFT JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+                 {clauses}
+                "
+            ))
+        })
+        .or_else(|why| Self::prepare_without_chat_join(db, clauses, why))
+        .map_err(TableError::Messages)
+    }
+
+    /// Prepare a statement for databases that do not have the `chat_message_join` table, such as
+    /// partial backups or databases that were modified by third-party tools.
+    ///
+    /// Without the join table, messages cannot be associated with a chat, so `chat_id` is always `NULL`.
+    /// The join is replaced with an empty stand-in table so `clauses` that reference `c.chat_id` still compile.
+    ///
+    /// `why` is the error from preparing the query with the join. The fallback is only used if that error reports
+    /// that the `chat_message_join` table is missing; any other error is returned as-is.
+    fn prepare_without_chat_join<'a>(
+        db: &'a Connection,
+        clauses: &str,
+        why: Error,
+    ) -> Result<Statement<'a>, Error> {
+        let missing_join = matches!(
+            &why,
+            Error::SqliteFailure(_, Some(message))
+                if message.strip_prefix("no such table: ") == Some(CHAT_MESSAGE_JOIN)
+        );
+        if !missing_join {
+            return Err(why);
+        }
+
+        // If database has `thread_originator_guid`, we can parse replies, otherwise default to 0
+        db.prepare(&format!(
+            "SELECT
+                 *,
+                 c.chat_id,
+                 (SELECT COUNT(*) FROM {MESSAGE_ATTACHMENT_JOIN} a WHERE m.ROWID = a.message_id) as num_attachments,
+                 NULL as deleted_from,
+                 (SELECT COUNT(*) FROM {MESSAGE} m2 WHERE m2.thread_originator_guid = m.guid) as num_replies
+             FROM
+                 message as m
+                 LEFT JOIN (SELECT NULL as chat_id, NULL as message_id) as c ON 0
+             {clauses}
+            "
+        ))
+        .or_else(|_| {
+            db.prepare(&format!(
+                "SELECT
+                     *,
+                     c.chat_id,
+                     (SELECT COUNT(*) FROM {MESSAGE_ATTACHMENT_JOIN} a WHERE m.ROWID = a.message_id) as num_attachments,
+                     NULL as deleted_from,
+                     0 as num_replies
+                 FROM
+                     message as m
+                     LEFT JOIN (SELECT NULL as chat_id, NULL as message_id) as c ON 0
+                 {clauses}
+                "
+            ))
+        })
+    }
+
+    /// Get a statement that streams only the messages that have attachments, ordered by date
+    ///
+    /// Messages without attachments are skipped by the query, so media-only exports do not read every text-only row.
+    /// Use [`Self::attachments()`] to get the attachments of each message.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::{Table, get_connection};
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let mut statement = Message::get_with_attachments(&conn).unwrap();
+    /// let messages = statement.query_map([], |row| Ok(Message::from_row(row))).unwrap();
+    /// ```
+    pub fn get_with_attachments(db: &Connection) -> Result<Statement<'_>, TableError> {
+        Self::prepare_with_clauses(
+            db,
+            &format!(
+                "WHERE EXISTS (SELECT 1 FROM {MESSAGE_ATTACHMENT_JOIN} a WHERE m.ROWID = a.message_id)
+                 ORDER BY m.date"
+            ),
+        )
+    }
+
+    /// Get a statement that streams only the messages in the chat with ROWID `chat_id`, ordered by date
+    ///
+    /// Returns the statement along with its parameters, which must be bound when the statement is executed.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::{Table, get_connection};
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let (mut statement, params) = Message::get_by_chat(&conn, 1).unwrap();
+    /// let messages = statement.query_map(params, |row| Ok(Message::from_row(row))).unwrap();
+    /// ```
+    pub fn get_by_chat(
+        db: &Connection,
+        chat_id: i32,
+    ) -> Result<(Statement<'_>, [i32; 1]), TableError> {
+        let statement = Self::prepare_with_clauses(
+            db,
+            "WHERE c.chat_id = ?1
+             ORDER BY m.date",
+        )?;
+        Ok((statement, [chat_id]))
+    }
+
+    /// Get a statement that streams the messages sent between `start` and `end`, inclusive, ordered by date
+    ///
+    /// The dates are converted to the database's Apple epoch timestamps with [`get_offset()`], in the unit found by
+    /// [`Self::timestamp_scale()`], so callers do not need to do the epoch math themselves. Fractions of a second are
+    /// kept, down to the precision of the database. Returns the statement along with the converted timestamps, which
+    /// must be bound as its parameters when the statement is executed.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use chrono::{Duration, Local};
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::{Table, get_connection};
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let end = Local::now();
+    /// let start = end - Duration::days(7);
+    /// let (mut statement, params) = Message::get_in_range(&conn, &start, &end).unwrap();
+    /// let messages = statement.query_map(params, |row| Ok(Message::from_row(row))).unwrap();
+    /// ```
+    pub fn get_in_range<'a>(
+        db: &'a Connection,
+        start: &DateTime<Local>,
+        end: &DateTime<Local>,
+    ) -> Result<(Statement<'a>, [i64; 2]), TableError> {
+        let offset = get_offset();
+        let scale = Self::timestamp_scale(db)?;
+        let statement = Self::prepare_with_clauses(
+            db,
+            "WHERE m.date BETWEEN ?1 AND ?2
+             ORDER BY m.date",
+        )?;
+        Ok((
+            statement,
+            [start, end].map(|date| {
+                let stamp = to_timestamp(date, &offset) + i64::from(date.timestamp_subsec_nanos());
+                scale.from_nanoseconds(stamp)
+            }),
+        ))
+    }
+
+    /// Get a page of at most `limit` messages that come after the `after` cursor, ordered by date
+    ///
+    /// This uses keyset pagination on `date` and `ROWID` instead of `OFFSET`, so each page is equally
+    /// fast to fetch regardless of how deep into the table it is. Pass `None` to get the first page, then
+    /// pass the returned cursor to get the next page. The returned cursor is `None` when there are no more pages.
+    pub fn get_page(
+        db: &Connection,
+        after: Option<&MessageCursor>,
+        limit: usize,
+    ) -> Result<(Vec<Self>, Option<MessageCursor>), TableError> {
+        // The first page has no lower bound; later pages compare the row value so SQLite can seek the `date` index
+        let messages = match after {
+            None => {
+                let mut statement = Self::prepare_with_clauses(
+                    db,
+                    "ORDER BY
+                         m.date, m.ROWID
+                     LIMIT ?1",
+                )?;
+                Self::collect_page(&mut statement, params![limit as i64])?
+            }
+            Some(cursor) => {
+                let mut statement = Self::prepare_with_clauses(
+                    db,
+                    "WHERE (m.date, m.ROWID) > (?1, ?2)
+                     ORDER BY
+                         m.date, m.ROWID
+                     LIMIT ?3",
+                )?;
+                Self::collect_page(
+                    &mut statement,
+                    params![cursor.date, cursor.rowid, limit as i64],
+                )?
+            }
+        };
+
+        // A short page means we reached the end of the table
+        let cursor = match messages.last() {
+            Some(last) if messages.len() == limit => Some(MessageCursor {
+                date: last.date,
+                rowid: last.rowid,
+            }),
+            _ => None,
+        };
+
+        Ok((messages, cursor))
+    }
+
+    /// Get a statement that streams at most `limit` messages, skipping the first `offset`, ordered by date
+    ///
+    /// Messages are ordered by `date`, then `ROWID`, so pages do not overlap or skip rows as long as the table does
+    /// not change between calls. Combine with [`Self::get_count()`] to compute the total number of pages.
+    /// Returns the statement along with its parameters, which must be bound when the statement is executed.
+    ///
+    /// SQLite still reads every skipped row, so deep pages get slower as `offset` grows; prefer [`Self::get_page()`]
+    /// when iterating over the whole table.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::{Table, get_connection};
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let (mut statement, params) = Message::get_page_offset(&conn, 50, 100).unwrap();
+    /// let messages = statement.query_map(params, |row| Ok(Message::from_row(row))).unwrap();
+    /// ```
+    pub fn get_page_offset(
+        db: &Connection,
+        limit: u32,
+        offset: u32,
+    ) -> Result<(Statement<'_>, [u32; 2]), TableError> {
+        let statement = Self::prepare_with_clauses(
+            db,
+            "ORDER BY
+                 m.date, m.ROWID
+             LIMIT ?1 OFFSET ?2",
+        )?;
+        Ok((statement, [limit, offset]))
+    }
+
+    /// Get a statement that streams the messages whose `text` column contains `term`, ordered by date
+    ///
+    /// Returns the statement along with its parameters, which contain the escaped `term` and must be bound when
+    /// the statement is executed.
+    ///
+    /// Matching uses SQLite's `LIKE`, so it is case-insensitive for ASCII characters. `%` and `_` in `term` are
+    /// matched literally, and an empty `term` matches every message with text.
+    ///
+    /// Messages with a `NULL` `text` column never match. Newer databases may only store the body of a message
+    /// in `attributedBody`, so those messages are not found until [`Self::generate_text()`] is called on them.
+    ///
+    /// # Example:
+    ///
+    /// ```no_run
+    /// use imessage_database::util::dirs::default_db_path;
+    /// use imessage_database::tables::table::{Table, get_connection};
+    /// use imessage_database::tables::messages::Message;
+    ///
+    /// let db_path = default_db_path();
+    /// let conn = get_connection(&db_path).unwrap();
+    /// let (mut statement, params) = Message::search(&conn, "dinner").unwrap();
+    /// let messages = statement.query_map(params, |row| Ok(Message::from_row(row))).unwrap();
+    /// ```
+    pub fn search<'a>(
+        db: &'a Connection,
+        term: &str,
+    ) -> Result<(Statement<'a>, [String; 1]), TableError> {
+        let statement = Self::prepare_with_clauses(
+            db,
+            "WHERE m.text IS NOT NULL AND m.text LIKE '%' || ?1 || '%' ESCAPE '\\'
+             ORDER BY m.date",
+        )?;
+
+        let escaped = term
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        Ok((statement, [escaped]))
+    }
+
+    /// Get the `n` most recent messages in the database, in chronological order
+    ///
+    /// The newest rows are selected with `ORDER BY m.date DESC LIMIT n` and reversed, so only `n`
+    /// messages are read instead of streaming the whole table.
+    pub fn get_recent(db: &Connection, n: usize) -> Result<Vec<Self>, TableError> {
+        let mut statement = Self::prepare_with_clauses(
+            db,
+            "ORDER BY
+                 m.date DESC, m.ROWID DESC
+             LIMIT ?1",
+        )?;
+        Self::collect_recent(&mut statement, params![n as i64])
+    }
+
+    /// Get the `n` most recent messages in the chat with ROWID `chat_id`, in chronological order
+    ///
+    /// See [`Self::get_recent()`].
+    pub fn get_recent_for_chat(
+        db: &Connection,
+        chat_id: i32,
+        n: usize,
+    ) -> Result<Vec<Self>, TableError> {
+        let mut statement = Self::prepare_with_clauses(
+            db,
+            "WHERE c.chat_id = ?1
+             ORDER BY
+                 m.date DESC, m.ROWID DESC
+             LIMIT ?2",
+        )?;
+        Self::collect_recent(&mut statement, params![chat_id, n as i64])
+    }
+
+    /// Get the messages that are new or changed since a previous export, in chronological order
+    ///
+    /// `known` maps the [`guid`](Self::guid) of each previously exported message to its
+    /// [`content_fingerprint()`](Self::content_fingerprint). Messages whose GUID is missing from `known`, or whose
+    /// fingerprint differs, are returned with their text already generated.
+    ///
+    /// This reads every message in the table, so it is slower than [`Self::get_since_date()`], but it cannot miss
+    /// messages: older messages that were backfilled into the database, i.e. by an iCloud sync after the previous
+    /// export, are still found.
+    pub fn get_since(
+        db: &Connection,
+        known: &HashMap<String, u64>,
+    ) -> Result<Vec<Self>, TableError> {
+        let mut statement = Self::prepare_with_clauses(db, "ORDER BY m.date")?;
+        let rows = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(TableError::Messages)?;
+
+        let mut messages = vec![];
+        for message in rows {
+            let mut message = Self::extract(message)?;
+            let _ = message.generate_text(db);
+            if known.get(&message.guid) != Some(&message.content_fingerprint()) {
+                messages.push(message);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Get the messages that were sent or edited after the `watermark` date, in chronological order
+    ///
+    /// `watermark` is a raw `date` value, usually the largest [`date`](Self::date) or
+    /// [`date_edited`](Self::date_edited) seen in the previous export. Only the matching rows are read, so this
+    /// is much faster than [`Self::get_since()`] for nightly incremental exports. However, messages that are
+    /// added to the database with an older date, i.e. when an iCloud sync backfills history, are missed.
+    pub fn get_since_date(db: &Connection, watermark: i64) -> Result<Vec<Self>, TableError> {
+        let mut statement = Self::prepare_with_clauses(
+            db,
+            "WHERE m.date > ?1 OR m.date_edited > ?1
+             ORDER BY m.date",
+        )?;
+        let rows = statement
+            .query_map(params![watermark], |row| Ok(Message::from_row(row)))
+            .map_err(TableError::Messages)?;
+
+        rows.map(Self::extract).collect()
+    }
+
+    /// Read every row of a statement in order
+    fn collect_page<P: Params>(
+        statement: &mut Statement<'_>,
+        params: P,
+    ) -> Result<Vec<Self>, TableError> {
+        let rows = statement
+            .query_map(params, |row| Ok(Message::from_row(row)))
+            .map_err(TableError::Messages)?;
+
+        rows.map(Self::extract).collect()
+    }
+
+    /// Read the newest-first rows of a statement and return them in chronological order
+    fn collect_recent<P: Params>(
+        statement: &mut Statement<'_>,
+        params: P,
+    ) -> Result<Vec<Self>, TableError> {
+        let mut messages = Self::collect_page(statement, params)?;
+        messages.reverse();
+        Ok(messages)
+    }
+
+    /// Parse the component index and the GUID of the message that a reaction or sticker targets
+    ///
+    /// All observed `associated_message_guid` forms are handled uniformly:
+    ///
+    /// | Form          | Index | Meaning                                          |
+    /// |---------------|-------|--------------------------------------------------|
+    /// | `p:N/GUID`    | `N`   | The `N`th component of a normal message          |
+    /// | `bp:GUID`     | `0`   | An app (balloon plugin) message                  |
+    /// | `p:N/bp:GUID` | `N`   | Both prefixes together, the index is kept        |
+    /// | `GUID`        | `0`   | The message as a whole                           |
+    ///
+    /// App messages are rendered as a single [`BubbleComponent::App`], so a `bp:` target without a part index
+    /// always points to component `0`. An index that fails to parse also falls back to `0`. Returns `None` if
+    /// the GUID is shorter than 36 characters.
+    ///
+    /// See [`Reaction`] for details on this data.
+    fn clean_associated_guid(&self) -> Option<(usize, &str)> {
+        let guid = self.associated_message_guid.as_deref()?;
+
+        let (index, guid) = match guid.strip_prefix("p:") {
+            Some(rest) => {
+                let (index, guid) = rest.split_once('/')?;
+                (index.parse::<usize>().unwrap_or(0), guid)
+            }
+            None => (0, guid),
+        };
+        let guid = guid.strip_prefix("bp:").unwrap_or(guid);
+
+        Some((index, guid.get(0..36)?))
+    }
+
+    /// Parse the index of a reaction from it's associated GUID field
+    fn reaction_index(&self) -> usize {
+        match self.clean_associated_guid() {
+            Some((x, _)) => x,
+            None => 0,
+        }
+    }
+
+    /// Get the component of a message `body` that this reaction or sticker targets
+    ///
+    /// The `body` should be the [`body()`](Self::body) of the message this reaction is associated with.
+    /// This allows exporters to determine if a reaction was placed on an attachment, text, or app bubble.
+    ///
+    /// Returns `None` if this message is not a reaction or if the index it targets does not exist in `body`.
+    pub fn reaction_target<'a, 'b>(
+        &self,
+        body: &'a [BubbleComponent<'b>],
+    ) -> Option<&'a BubbleComponent<'b>> {
+        if !self.is_reaction() {
+            return None;
+        }
+        body.get(self.reaction_index())
+    }
+
+    /// Build a `HashMap` of message component index to tapbacks on that component
+    ///
+    /// Stickers placed on the message are not included, see [`Self::cache_stickers()`].
+    pub fn get_reactions(
+        &self,
+        db: &Connection,
+        reactions: &HashMap<String, Vec<String>>,
+    ) -> Result<HashMap<usize, Vec<Self>>, TableError> {
+        // GUIDs are bound as query parameters, so they never need to be quoted
+        Ok(Self::get_reactions_bulk(db, &[&self.guid], reactions)?
+            .remove(&self.guid)
+            .unwrap_or_default())
+    }
+
+    /// Build the same data as [`Self::get_reactions()`] for many messages at once
+    ///
+    /// Instead of running one query per message, this fetches the reactions to all of the messages in `guids`
+    /// with a single parameterized query, split into batches when there are too many to bind at once.
+    /// The result is keyed by the GUID of the message the reactions belong to; messages without reactions are omitted.
+    pub fn get_reactions_bulk(
+        db: &Connection,
+        guids: &[&str],
+        reactions: &HashMap<String, Vec<String>>,
+    ) -> Result<HashMap<String, HashMap<usize, Vec<Self>>>, TableError> {
+        let mut out_h: HashMap<String, HashMap<usize, Vec<Self>>> = HashMap::new();
+
+        // Map each reaction's GUID back to the GUID of the message it reacts to
+        let targets: HashMap<&str, &str> = guids
+            .iter()
+            .filter_map(|guid| Some((*guid, reactions.get(*guid)?)))
+            .flat_map(|(guid, rxs)| rxs.iter().map(move |rx| (rx.as_str(), guid)))
+            .collect();
+        let reaction_guids: Vec<&str> = targets.keys().copied().collect();
+
+        for chunk in reaction_guids.chunks(MAX_QUERY_PARAMS) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let mut statement = Self::prepare_with_clauses(
+                db,
+                &format!("WHERE m.guid IN ({placeholders}) ORDER BY m.date"),
+            )?;
 
-            // Execute query to build the Handles
             let messages = statement
-                .query_map([], |row| Ok(Message::from_row(row)))
+                .query_map(params_from_iter(chunk), |row| Ok(Message::from_row(row)))
                 .map_err(TableError::Messages)?;
 
             for message in messages {
                 let msg = Message::extract(message)?;
-                if let Variant::Reaction(idx, _, _) | Variant::Sticker(idx) = msg.variant() {
-                    match out_h.get_mut(&idx) {
-                        Some(body_part) => body_part.push(msg),
-                        None => {
-                            out_h.insert(idx, vec![msg]);
-                        }
+                if let Variant::Reaction(idx, _, _) = msg.variant() {
+                    if let Some(target) = targets.get(msg.guid.as_str()) {
+                        out_h
+                            .entry(target.to_string())
+                            .or_default()
+                            .entry(idx)
+                            .or_default()
+                            .push(msg);
                     }
                 }
             }
         }
+
+        // Each chunk is sorted by date, so restore the order across chunks
+        if reaction_guids.len() > MAX_QUERY_PARAMS {
+            out_h
+                .values_mut()
+                .flat_map(HashMap::values_mut)
+                .for_each(|msgs| msgs.sort_by_key(|msg| msg.date));
+        }
+
         Ok(out_h)
     }
 
@@ -812,44 +2011,224 @@ impl Message {
         Ok(out_h)
     }
 
-    /// Parse the App's Bundle ID out of the Balloon's Bundle ID
+    /// Get the fully populated [`Attachment`]s for this message
     ///
-    /// For example, a Bundle ID like `com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.SafetyMonitorApp.SafetyMonitorMessages`
-    /// should get parsed into `com.apple.SafetyMonitorApp.SafetyMonitorMessages`.
-    fn parse_balloon_bundle_id(&self) -> Option<&str> {
-        if let Some(bundle_id) = &self.balloon_bundle_id {
-            let mut parts = bundle_id.split(':');
-            let bundle_id = parts.next();
-            // If there is only one part, use that, otherwise get the third part
-            if parts.next().is_none() {
-                bundle_id
-            } else {
-                // Will be None if there is no third part
-                parts.next()
+    /// Attachments are returned in the same order as the [`BubbleComponent::Attachment`]s
+    /// in the message's [`body()`](Self::body), so the `n`th attachment bubble corresponds to the `n`th item.
+    pub fn attachments(&self, db: &Connection) -> Result<Vec<Attachment>, TableError> {
+        Attachment::from_message(db, self)
+    }
+
+    /// Get the message with the given `guid`, if it exists
+    pub fn get_by_guid(db: &Connection, guid: &str) -> Result<Option<Self>, TableError> {
+        let mut statement = Self::prepare_with_clauses(db, "WHERE m.guid = ?1")?;
+        statement
+            .query_row(params![guid], Message::from_row)
+            .optional()
+            .map_err(TableError::Messages)
+    }
+
+    /// Get the handles that were members of the message's chat when the message was sent
+    ///
+    /// The `message` table does not store a snapshot of group membership: the `cache_roomnames` column only
+    /// contains the identifier of the group chat. Instead, this starts from the chat's current participants
+    /// in `chat_handle_join` and undoes every membership change announced in the chat after the message was
+    /// sent, newest first.
+    ///
+    /// Membership changes that are missing from the database, such as those from before the database owner
+    /// joined the group or whose announcements were deleted, cannot be undone, so the result falls back to
+    /// the current membership for those handles. Messages that do not belong to a chat return an empty set.
+    pub fn group_members(&self, db: &Connection) -> Result<BTreeSet<i32>, TableError> {
+        let Some(chat_id) = self.chat_id else {
+            return Ok(BTreeSet::new());
+        };
+
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT handle_id FROM {CHAT_HANDLE_JOIN} WHERE chat_id = ?1"
+            ))
+            .map_err(TableError::Messages)?;
+        let mut members = statement
+            .query_map(params![chat_id], |row| row.get(0))
+            .map_err(TableError::Messages)?
+            .collect::<Result<BTreeSet<i32>, _>>()
+            .map_err(TableError::Messages)?;
+
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT m.item_type, m.group_action_type, m.handle_id, m.other_handle
+                 FROM {MESSAGE} as m
+                 JOIN {CHAT_MESSAGE_JOIN} as c ON m.ROWID = c.message_id
+                 WHERE c.chat_id = ?1
+                     AND m.date > (SELECT date FROM {MESSAGE} WHERE ROWID = ?2)
+                     AND m.item_type IN (1, 3)
+                 ORDER BY m.date DESC"
+            ))
+            .map_err(TableError::Messages)?;
+        // Compare against the stored date so the result does not depend on whether this message was normalized
+        let changes = statement
+            .query_map(params![chat_id, self.rowid], |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, i32>(1)?,
+                    row.get::<_, Option<i32>>(2)?.unwrap_or_default(),
+                    row.get::<_, Option<i32>>(3)?.unwrap_or_default(),
+                ))
+            })
+            .map_err(TableError::Messages)?;
+
+        for change in changes {
+            match change.map_err(TableError::Messages)? {
+                // A participant was added after the message was sent
+                (1, 0, _, added) if added != 0 => {
+                    members.remove(&added);
+                }
+                // A participant was removed after the message was sent
+                (1, 1, _, removed) if removed != 0 => {
+                    members.insert(removed);
+                }
+                // A participant left after the message was sent
+                (3, 0, left, _) if left != 0 => {
+                    members.insert(left);
+                }
+                _ => {}
             }
-        } else {
-            None
         }
+
+        Ok(members)
     }
 
-    /// Get the variant of a message, see [`variants`](crate::message_types::variants) for detail.
-    pub fn variant(&self) -> Variant {
-        // Check if a message was edited first as those have special properties
-        if self.is_edited() {
-            return Variant::Edited;
+    /// Determine if the message belongs to a "Note to Self" chat, where the database owner messages their own number or email address
+    ///
+    /// The database does not mark the owner's handles, so they must be provided in `owner_handles`. The handle a message was
+    /// sent from or received by is stored in [`destination_caller_id`](Self::destination_caller_id), so collecting the distinct
+    /// values of that column is a reliable way to find them. A chat is a note to self when it has participants and every one of
+    /// them is an owner handle. Messages that do not belong to a chat return `false`.
+    pub fn is_note_to_self(&self, db: &Connection, owner_handles: &[&str]) -> bool {
+        let Some(chat_id) = self.chat_id else {
+            return false;
+        };
+        match Chat::participant_handles(db, chat_id) {
+            Ok(participants) => {
+                !participants.is_empty()
+                    && participants
+                        .iter()
+                        .all(|participant| owner_handles.contains(&participant.as_str()))
+            }
+            Err(_) => false,
         }
+    }
 
-        // Handle different types of bundle IDs next, as those are most common
-        if let Some(associated_message_type) = self.associated_message_type {
-            return match associated_message_type {
-                // Standard iMessages with either text or a message payload
-                0 | 2 | 3 => match self.parse_balloon_bundle_id() {
-                    Some(bundle_id) => match bundle_id {
-                        "com.apple.messages.URLBalloonProvider" => Variant::App(CustomBalloon::URL),
-                        "com.apple.Handwriting.HandwritingProvider" => {
-                            Variant::App(CustomBalloon::Handwriting)
-                        }
-                        "com.apple.PassbookUIService.PeerPaymentMessagesExtension" => {
+    /// Get the message a sticker is placed on
+    ///
+    /// Use [`Self::reaction_target()`] with the returned message's [`body()`](Self::body) to find the
+    /// component, such as an attachment, that the sticker overlays.
+    ///
+    /// Returns `None` if this message is not a sticker placed on another message, or if that message
+    /// cannot be found.
+    pub fn sticker_target(&self, db: &Connection) -> Option<Self> {
+        if !self.is_sticker() {
+            return None;
+        }
+        let (_, guid) = self.clean_associated_guid()?;
+        Self::get_by_guid(db, guid).ok().flatten()
+    }
+
+    /// Get the message that started the thread this reply belongs to
+    ///
+    /// The originator is looked up by its GUID across the whole database, so replies resolve even when an export
+    /// only fetched the messages of a single chat. This may cross chat boundaries: a reply can point at a message
+    /// that is stored in another chat, i.e. when the same conversation is split between SMS and iMessage. Pass
+    /// `false` for `cross_chat` to only return an originator that belongs to the same chat as the reply.
+    ///
+    /// Returns `None` if the message is not a reply, or if the originator cannot be found.
+    pub fn reply_originator(
+        &self,
+        db: &Connection,
+        cross_chat: bool,
+    ) -> Result<Option<Self>, TableError> {
+        let Some(guid) = self.parent_guid() else {
+            return Ok(None);
+        };
+        Ok(Self::get_by_guid(db, guid)?
+            .filter(|originator| cross_chat || originator.chat_id == self.chat_id))
+    }
+
+    /// Get a name for the thread a reply belongs to, generated from the text of the thread's originator message
+    ///
+    /// The name is the first line of the originator's text without attachment placeholders, shortened to
+    /// at most [`THREAD_NAME_LENGTH`] characters. This
+    /// allows exporters to group replies under a readable heading.
+    ///
+    /// Returns `None` if the message is not a reply, or if the originator is missing or has no text.
+    pub fn thread_name(&self, db: &Connection) -> Result<Option<String>, TableError> {
+        let originator_guid = match &self.thread_originator_guid {
+            Some(guid) => guid,
+            None => return Ok(None),
+        };
+
+        let mut originator = match Self::get_by_guid(db, originator_guid)? {
+            Some(originator) => originator,
+            None => return Ok(None),
+        };
+
+        let name = match originator.generate_text(db) {
+            Ok(_) => originator.clean_text().and_then(|text| {
+                text.lines()
+                    .map(|line| line.trim().to_string())
+                    .find(|line| !line.is_empty())
+            }),
+            Err(_) => None,
+        };
+
+        Ok(name.map(|name| {
+            if name.chars().count() > THREAD_NAME_LENGTH {
+                let mut short: String = name.chars().take(THREAD_NAME_LENGTH).collect();
+                short.push('…');
+                short
+            } else {
+                name
+            }
+        }))
+    }
+
+    /// Parse the App's Bundle ID out of the Balloon's Bundle ID
+    ///
+    /// For example, a Bundle ID like `com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.SafetyMonitorApp.SafetyMonitorMessages`
+    /// should get parsed into `com.apple.SafetyMonitorApp.SafetyMonitorMessages`.
+    ///
+    /// The app's Bundle ID is always the last `:`-separated part, so Bundle IDs with one part (`bundle_id`),
+    /// two parts (`prefix:bundle_id`), or three parts (`plugin:team_id:bundle_id`) are all supported.
+    fn parse_balloon_bundle_id(&self) -> Option<&str> {
+        self.balloon_bundle_id
+            .as_deref()?
+            .rsplit(':')
+            .next()
+            .filter(|bundle_id| !bundle_id.is_empty())
+    }
+
+    /// Get the variant of a message, see [`variants`](crate::message_types::variants) for detail.
+    ///
+    /// Messages that were edited or had parts unsent are always [`Variant::Edited`], even though their
+    /// `associated_message_type` still describes the original message. Use [`Self::is_part_edited()`] and
+    /// [`Self::is_fully_unsent()`] to tell edits and unsends apart.
+    pub fn variant(&self) -> Variant {
+        // Check if a message was edited first as those have special properties
+        if self.is_edited() {
+            return Variant::Edited;
+        }
+
+        // Handle different types of bundle IDs next, as those are most common
+        if let Some(associated_message_type) = self.associated_message_type {
+            return match associated_message_type {
+                // Standard iMessages with either text or a message payload
+                0 | 2 | 3 => match self.parse_balloon_bundle_id() {
+                    Some(bundle_id) => match bundle_id {
+                        "com.apple.messages.URLBalloonProvider" => Variant::App(CustomBalloon::URL),
+                        "com.apple.Handwriting.HandwritingProvider" => {
+                            Variant::App(CustomBalloon::Handwriting)
+                        }
+                        "com.apple.PassbookUIService.PeerPaymentMessagesExtension" => {
                             Variant::App(CustomBalloon::ApplePay)
                         }
                         "com.apple.ActivityMessagesApp.MessagesExtension" => {
@@ -862,6 +2241,13 @@ impl Message {
                             Variant::App(CustomBalloon::CheckIn)
                         }
                         "com.apple.findmy.FindMyMessagesApp" => Variant::App(CustomBalloon::FindMy),
+                        "com.apple.DigitalTouchBalloonProvider" => {
+                            Variant::App(CustomBalloon::DigitalTouch)
+                        }
+                        "com.apple.Jellyfish.Animoji" => Variant::App(CustomBalloon::Animoji),
+                        "com.apple.icloud.apps.messages.business.extension" => {
+                            Variant::App(CustomBalloon::Business)
+                        }
                         _ => Variant::App(CustomBalloon::Application(bundle_id)),
                     },
                     // This is the most common case
@@ -898,6 +2284,43 @@ impl Message {
         Variant::Normal
     }
 
+    /// Get a human readable label for the raw `associated_message_type` of a message
+    ///
+    /// Unlike [`Self::variant()`], this only considers `associated_message_type`, so edited messages and
+    /// app messages are labeled by the code they were stored with. This is intended for debugging and
+    /// bug reports about unhandled codes.
+    ///
+    /// | Code | Label |
+    /// |-----------|-----------------------------|
+    /// | `None`, `0`, `2`, `3` | `Normal` |
+    /// | `1000` | `Sticker` |
+    /// | `2000`-`2005` | `Reaction: Loved (added)` |
+    /// | `3000`-`3005` | `Reaction: Loved (removed)` |
+    /// | Any other `N` | `Unknown (N)` |
+    pub fn association_type_label(&self) -> String {
+        let code = match self.associated_message_type {
+            None | Some(0 | 2 | 3) => return String::from("Normal"),
+            Some(1000) => return String::from("Sticker"),
+            Some(code) => code,
+        };
+
+        let action = match code / 1000 {
+            2 => "added",
+            3 => "removed",
+            _ => return format!("Unknown ({code})"),
+        };
+        let reaction = match code % 1000 {
+            0 => Reaction::Loved,
+            1 => Reaction::Liked,
+            2 => Reaction::Disliked,
+            3 => Reaction::Laughed,
+            4 => Reaction::Emphasized,
+            5 => Reaction::Questioned,
+            _ => return format!("Unknown ({code})"),
+        };
+        format!("Reaction: {reaction:?} ({action})")
+    }
+
     /// Determine the type of announcement a message contains, if it contains one
     pub fn get_announcement(&self) -> Option<Announcement> {
         if let Some(name) = &self.group_title {
@@ -916,13 +2339,76 @@ impl Message {
     }
 
     /// Determine the service the message was sent from, i.e. iMessage, SMS, IRC, etc.
+    ///
+    /// Messages from the carrier network are stored with the `SMS` service even when they carry media, so
+    /// `SMS` messages with attachments are reported as [`Service::MMS`].
     pub fn service(&self) -> Service {
-        match self.service.as_deref() {
-            Some("iMessage") => Service::iMessage,
-            Some("SMS") => Service::SMS,
-            Some(service_name) => Service::Other(service_name),
-            None => Service::Unknown,
+        match Service::from_name(self.service.as_deref()) {
+            Service::SMS if self.has_attachments() => Service::MMS,
+            service => service,
+        }
+    }
+
+    /// Get the service the message was sent with, falling back to the service of its chat when the message's
+    /// `service` column is empty
+    ///
+    /// Some rows in group MMS conversations have no service even though the chat is clearly SMS, so this
+    /// infers the service from the chat instead of reporting [`Service::Unknown`].
+    pub fn effective_service<'a>(&'a self, chat_service: Service<'a>) -> Service<'a> {
+        match self.service() {
+            Service::Unknown | Service::Other("") => chat_service,
+            service => service,
+        }
+    }
+
+    /// Determine whether the message was addressed to a single person, a group, or a business
+    ///
+    /// Business conversations are detected by the [`BUSINESS_ID_PREFIX`] on the chat or sender handle identifier,
+    /// or by the presence of a [`Business`](CustomBalloon::Business) balloon. Group conversations have more than
+    /// one participant in the `chat_handle_join` table. Messages that do not belong to a chat are treated as
+    /// [`Individual`](RecipientKind::Individual) messages.
+    pub fn recipient_kind(&self, db: &Connection) -> Result<RecipientKind, TableError> {
+        if matches!(self.variant(), Variant::App(CustomBalloon::Business)) {
+            return Ok(RecipientKind::Business);
+        }
+
+        if let Some(chat_id) = self.chat_id {
+            let chat: Option<(String, usize)> = db
+                .query_row(
+                    &format!(
+                        "SELECT
+                             c.chat_identifier,
+                             (SELECT COUNT(*) FROM {CHAT_HANDLE_JOIN} j WHERE j.chat_id = c.ROWID)
+                         FROM {CHAT} as c
+                         WHERE c.ROWID = ?1"
+                    ),
+                    [chat_id],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()
+                .map_err(TableError::Messages)?;
+
+            if let Some((chat_identifier, num_participants)) = chat {
+                return Ok(RecipientKind::from_chat(&chat_identifier, num_participants));
+            }
+        }
+
+        if let Some(handle_id) = self.handle_id {
+            let handle: Option<String> = db
+                .query_row(
+                    &format!("SELECT id FROM {HANDLE} WHERE ROWID = ?1"),
+                    [handle_id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(TableError::Messages)?;
+
+            if handle.is_some_and(|id| id.starts_with(BUSINESS_ID_PREFIX)) {
+                return Ok(RecipientKind::Business);
+            }
         }
+
+        Ok(RecipientKind::Individual)
     }
 
     /// Extract a blob of data that belongs to a single message from a given column
@@ -949,6 +2435,173 @@ impl Message {
         Value::from_reader(self.get_blob(db, MESSAGE_PAYLOAD)?).ok()
     }
 
+    /// Get a message's data from the `payload_data` BLOB column, keeping data that is not a plist
+    ///
+    /// Unlike [`Self::payload_data()`], this does not discard payloads that fail to decode as a plist:
+    /// [`typedstream`](crate::util::typedstream) payloads are returned as [`PayloadData::TypedStream`]
+    /// and any other data is returned as [`PayloadData::Unknown`]. `None` indicates there is no payload.
+    ///
+    /// Calling this hits the database, so it is expensive and should
+    /// only get invoked when needed.
+    pub fn payload_data_typed(&self, db: &Connection) -> Option<PayloadData> {
+        PayloadData::from_bytes(self.payload_bytes(db).ok()??)
+    }
+
+    /// Get a message's data from the `payload_data` BLOB column, reporting why a payload could not be decoded
+    ///
+    /// [`Self::payload_data()`] returns `None` both when there is no payload and when the payload is corrupt or
+    /// only partially written, which hides why an app message renders blank. This returns `Ok(None)` only when
+    /// there is no payload, and [`PlistParseError::UndecodablePayload`] when the payload is neither a plist nor
+    /// [`typedstream`](crate::util::typedstream) data.
+    ///
+    /// Calling this hits the database, so it is expensive and should
+    /// only get invoked when needed.
+    pub fn try_payload_data(
+        &self,
+        db: &Connection,
+    ) -> Result<Option<PayloadData>, PlistParseError> {
+        match self
+            .payload_bytes(db)
+            .map_err(|_| PlistParseError::NoPayload)?
+        {
+            Some(bytes) => PayloadData::try_from_bytes(bytes),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the `ROWID`s of messages whose `payload_data` is present but cannot be decoded
+    ///
+    /// See [`Self::try_payload_data()`] to get the decode error for one of these messages.
+    pub fn undecodable_payloads(db: &Connection) -> Result<Vec<i32>, TableError> {
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT ROWID, payload_data FROM {MESSAGE} WHERE length(payload_data) > 0 ORDER BY ROWID"
+            ))
+            .map_err(TableError::Messages)?;
+
+        let payloads = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, i32>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(TableError::Messages)?;
+
+        let mut undecodable = vec![];
+        for payload in payloads {
+            let (rowid, bytes) = payload.map_err(TableError::Messages)?;
+            if PayloadData::try_from_bytes(bytes).is_err() {
+                undecodable.push(rowid);
+            }
+        }
+        Ok(undecodable)
+    }
+
+    /// Count the messages that use each app bundle ID that [`Self::variant()`] does not recognize
+    ///
+    /// The keys are the bundle IDs in [`CustomBalloon::Application`], which are rendered as generic app bubbles,
+    /// and the values are the number of messages that use them. This is useful for finding which apps to support next.
+    pub fn unhandled_bundle_ids(db: &Connection) -> Result<HashMap<String, u64>, TableError> {
+        let mut statement =
+            Self::prepare_with_clauses(db, "WHERE m.balloon_bundle_id IS NOT NULL")?;
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .map_err(TableError::Messages)?;
+
+        let mut counts = HashMap::new();
+        for message in messages {
+            let message = Self::extract(message)?;
+            if let Variant::App(CustomBalloon::Application(bundle_id)) = message.variant() {
+                *counts.entry(bundle_id.to_string()).or_insert(0) += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Get the raw bytes of a message's `payload_data` BLOB column without attempting to decode them
+    ///
+    /// Calling this hits the database, so it is expensive and should
+    /// only get invoked when needed.
+    ///
+    /// This is useful for archiving or externally analyzing payloads. `Ok(None)` indicates there is no payload,
+    /// while an error means the column could not be read.
+    pub fn payload_bytes(&self, db: &Connection) -> Result<Option<Vec<u8>>, TableError> {
+        let bytes: Option<Vec<u8>> = db
+            .query_row(
+                &format!("SELECT {MESSAGE_PAYLOAD} FROM {MESSAGE} WHERE ROWID = ?1"),
+                params![self.rowid],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(TableError::Messages)?
+            .flatten();
+        Ok(bytes.filter(|bytes| !bytes.is_empty()))
+    }
+
+    /// Get the preview image of a [`URL`](CustomBalloon::URL) message, including Apple Music messages
+    ///
+    /// Calling this hits the database, so it is expensive and should
+    /// only get invoked when needed.
+    ///
+    /// Images that iMessage downloaded when the link was sent are stored as attachments and are preferred.
+    /// Otherwise, this falls back to the first image URL in the payload. Apple Music artwork is only
+    /// available as an attachment. Returns `None` if the message is not a link or has no preview image, and an
+    /// error if the attachments could not be read.
+    pub fn preview_image(&self, db: &Connection) -> Result<Option<PreviewImage>, TableError> {
+        if !self.is_url() {
+            return Ok(None);
+        }
+
+        if self.has_attachments() {
+            if let Some(attachment) = Attachment::from_message(db, self)?
+                .into_iter()
+                .find(|attachment| matches!(attachment.mime_type(), MediaType::Image(_)))
+            {
+                return Ok(Some(PreviewImage::Attachment(attachment)));
+            }
+        }
+
+        let Some(payload) = self
+            .payload_data(db)
+            .and_then(|payload| parse_plist(&payload).ok())
+        else {
+            return Ok(None);
+        };
+        Ok(match URLMessage::get_url_message_override(&payload) {
+            Ok(URLOverride::Normal(balloon)) => balloon
+                .images
+                .first()
+                .map(|url| PreviewImage::URL(url.to_string())),
+            _ => None,
+        })
+    }
+
+    /// Get the Apple Music track metadata from a [`URL`](CustomBalloon::URL) message's parsed payload
+    ///
+    /// Since [`MusicMessage`] borrows from the payload, the caller retrieves it first:
+    ///
+    /// ```no_run
+    /// use imessage_database::{
+    ///     tables::{messages::Message, table::get_connection},
+    ///     util::{dirs::default_db_path, plist::parse_plist},
+    /// };
+    ///
+    /// let db = get_connection(&default_db_path()).unwrap();
+    /// # let message: Message = unimplemented!();
+    /// if let Some(payload) = message.payload_data(&db) {
+    ///     let parsed = parse_plist(&payload).unwrap();
+    ///     if let Some(music) = message.music(&parsed) {
+    ///         println!("{:?} by {:?}", music.track_name, music.artist);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// Returns `None` if the message is not a link or the payload does not describe a song.
+    pub fn music<'a>(&self, payload: &'a Value) -> Option<MusicMessage<'a>> {
+        if !self.is_url() {
+            return None;
+        }
+        MusicMessage::from_map(payload).ok()
+    }
+
     /// Get a message's plist from the `message_summary_info` BLOB column
     ///
     /// Calling this hits the database, so it is expensive and should
@@ -959,6 +2612,31 @@ impl Message {
         Value::from_reader(self.get_blob(db, MESSAGE_SUMMARY_INFO)?).ok()
     }
 
+    /// Get every version of an edited message's text, oldest first, from the `message_summary_info` BLOB column
+    ///
+    /// For a message edited twice, this contains the original text, the first edit, and the current text. When more
+    /// than one part of a message was edited, the versions of all parts are ordered by the date they were written.
+    ///
+    /// Returns `None` if the message was not [edited](Self::is_edited) or if no edits are recorded. Unsent messages also
+    /// set [`date_edited`](Self::date_edited) but have no text history, so this tells edited messages apart from
+    /// unsent ones; use [`Self::is_fully_unsent()`] to detect the latter. Calling this hits the database, so it is
+    /// expensive and should only get invoked when needed.
+    pub fn edit_history(&self, db: &Connection) -> Option<Vec<String>> {
+        if !self.is_edited() {
+            return None;
+        }
+        let edited = EditedMessage::from_map(&self.message_summary_info(db)?).ok()?;
+
+        let mut events: Vec<_> = edited
+            .parts
+            .iter()
+            .flat_map(|part| &part.edit_history)
+            .collect();
+        events.sort_by_key(|event| event.date);
+        let history: Vec<String> = events.into_iter().map(|event| event.text.clone()).collect();
+        (!history.is_empty()).then_some(history)
+    }
+
     /// Get a message's plist from the `attributedBody` BLOB column
     ///
     /// Calling this hits the database, so it is expensive and should
@@ -1019,16 +2697,172 @@ impl Message {
     }
 }
 
+/// Tally the reactions in a reaction cache built by [`Message::cache()`](crate::tables::table::Cacheable::cache)
+///
+/// Returns two maps of [`Reaction`] to count: the first for reactions given (`is_from_me`),
+/// the second for reactions received. Removed reactions subtract from the count of their
+/// [`Reaction`], since only the latest status of a reaction is stored in the table.
+pub fn tally_reactions(
+    reactions: &HashMap<String, HashMap<usize, Vec<Message>>>,
+) -> (HashMap<Reaction, i64>, HashMap<Reaction, i64>) {
+    let mut given: HashMap<Reaction, i64> = HashMap::new();
+    let mut received: HashMap<Reaction, i64> = HashMap::new();
+
+    reactions
+        .values()
+        .flat_map(HashMap::values)
+        .flatten()
+        .for_each(|message| {
+            if let Variant::Reaction(_, added, reaction) = message.variant() {
+                let tally = if message.is_from_me() {
+                    &mut given
+                } else {
+                    &mut received
+                };
+                *tally.entry(reaction).or_insert(0) += if added { 1 } else { -1 };
+            }
+        });
+
+    (given, received)
+}
+
+/// Find the longest silence in a conversation
+///
+/// `messages` must be ordered by date, as returned by [`Message::stream_rows()`]. Returns the length of the
+/// largest gap between consecutive messages along with the raw `date` values that bound it. Reactions are
+/// ignored, so a tapback on an old message does not break up a silence.
+///
+/// Returns `None` if there are fewer than two messages that are not reactions.
+pub fn longest_gap(messages: &[Message]) -> Option<(Duration, i64, i64)> {
+    let dates: Vec<i64> = messages
+        .iter()
+        .filter(|message| !message.is_reaction())
+        .map(|message| message.date)
+        .collect();
+
+    dates
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0], pair[0], pair[1]))
+        .reduce(|longest, gap| if gap.0 > longest.0 { gap } else { longest })
+        .map(|(gap, start, end)| (Duration::nanoseconds(gap), start, end))
+}
+
+/// `true` if `cur` is the first of a run of consecutive messages from the same sender, else `false`
+///
+/// `prev` is the message before `cur` in the same chat, or `None` if `cur` is the first message. Messages are from the
+/// same sender if both were sent by the database owner, or both were received from the same `handle_id`. Transcripts
+/// use this to only show the sender's name or avatar at the start of each run.
+pub fn starts_sender_run(prev: Option<&Message>, cur: &Message) -> bool {
+    match prev {
+        Some(prev) => {
+            prev.is_from_me != cur.is_from_me
+                || (!cur.is_from_me && prev.handle_id != cur.handle_id)
+        }
+        None => true,
+    }
+}
+
+/// Serialize the raw columns of a message alongside the values derived from them
+///
+/// Field names are stable. Each timestamp is serialized twice: as the raw integer, and as a human-readable
+/// `*_formatted` string that is `null` when the raw value is unset. The results of [`Message::variant()`],
+/// [`Message::service()`], and [`Message::get_expressive()`] are included as `variant`, `service_type`, and
+/// `expressive`. The parsed `components` and `edited_parts` are not serialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Message {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use crate::util::dates::format_opt;
+        use serde::ser::SerializeStruct;
+
+        let offset = get_offset();
+        let formatted = |stamp: i64, date: Result<DateTime<Local>, MessageError>| {
+            format_opt(&date.ok().filter(|_| stamp != 0))
+        };
+
+        let mut state = serializer.serialize_struct("Message", 36)?;
+        state.serialize_field("rowid", &self.rowid)?;
+        state.serialize_field("guid", &self.guid)?;
+        state.serialize_field("text", &self.text)?;
+        state.serialize_field("service", &self.service)?;
+        state.serialize_field("handle_id", &self.handle_id)?;
+        state.serialize_field("destination_caller_id", &self.destination_caller_id)?;
+        state.serialize_field("subject", &self.subject)?;
+        state.serialize_field("date", &self.date)?;
+        state.serialize_field("date_formatted", &formatted(self.date, self.date(&offset)))?;
+        state.serialize_field("date_read", &self.date_read)?;
+        state.serialize_field(
+            "date_read_formatted",
+            &formatted(self.date_read, self.date_read(&offset)),
+        )?;
+        state.serialize_field("date_delivered", &self.date_delivered)?;
+        state.serialize_field(
+            "date_delivered_formatted",
+            &formatted(self.date_delivered, self.date_delivered(&offset)),
+        )?;
+        state.serialize_field("is_from_me", &self.is_from_me)?;
+        state.serialize_field("is_read", &self.is_read)?;
+        state.serialize_field("item_type", &self.item_type)?;
+        state.serialize_field("other_handle", &self.other_handle)?;
+        state.serialize_field("share_status", &self.share_status)?;
+        state.serialize_field("share_direction", &self.share_direction)?;
+        state.serialize_field("group_title", &self.group_title)?;
+        state.serialize_field("group_action_type", &self.group_action_type)?;
+        state.serialize_field("associated_message_guid", &self.associated_message_guid)?;
+        state.serialize_field("associated_message_type", &self.associated_message_type)?;
+        state.serialize_field("balloon_bundle_id", &self.balloon_bundle_id)?;
+        state.serialize_field("expressive_send_style_id", &self.expressive_send_style_id)?;
+        state.serialize_field("thread_originator_guid", &self.thread_originator_guid)?;
+        state.serialize_field("thread_originator_part", &self.thread_originator_part)?;
+        state.serialize_field("date_edited", &self.date_edited)?;
+        state.serialize_field(
+            "date_edited_formatted",
+            &formatted(self.date_edited, self.date_edited(&offset)),
+        )?;
+        state.serialize_field("chat_id", &self.chat_id)?;
+        state.serialize_field("num_attachments", &self.num_attachments)?;
+        state.serialize_field("deleted_from", &self.deleted_from)?;
+        state.serialize_field("num_replies", &self.num_replies)?;
+        state.serialize_field("variant", &self.variant())?;
+        state.serialize_field("service_type", &self.service())?;
+        state.serialize_field("expressive", &self.get_expressive())?;
+        state.end()
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{collections::HashMap, env::current_dir, fs::File, io::Read};
+
+    use chrono::{DateTime, Datelike, Duration, FixedOffset, Utc};
+    use plist::Value;
+
     use crate::{
+        error::plist::PlistParseError,
         message_types::{
             edited::{EditStatus, EditedMessage, EditedMessagePart},
             expressives,
-            variants::{CustomBalloon, Variant},
+            variants::{CustomBalloon, Reaction, Variant},
+        },
+        tables::{
+            messages::{
+                message::{
+                    longest_gap, starts_sender_run, tally_reactions, REACTION_PREDICATE,
+                    STICKER_PREDICATE, TAPBACK_PREDICATE, THREAD_NAME_LENGTH,
+                },
+                models::{
+                    BubbleComponent, PayloadData, Placeholders, PreviewImage, ReadInfo,
+                    RecipientKind, Service,
+                },
+                Message,
+            },
+            table::{Cacheable, Table},
+            test_utils::{TestDatabase, TestMessage},
+        },
+        util::{
+            dates::{get_local_time, get_offset, TimestampScale, TIMESTAMP_FACTOR},
+            plist::parse_plist,
+            query_context::QueryContext,
         },
-        tables::messages::Message,
-        util::dates::get_offset,
     };
 
     fn blank() -> Message {
@@ -1072,6 +2906,112 @@ mod tests {
         blank();
     }
 
+    #[test]
+    fn can_get_dates_in_zone() {
+        let offset = get_offset();
+        let tz = FixedOffset::west_opt(4 * 3600).unwrap();
+
+        let mut message = blank();
+        // May 18, 2022  12:29:42 AM UTC
+        message.date = 674526582885055488;
+        // May 18, 2022  1:30:31 AM UTC
+        message.date_read = 674530231992568192;
+
+        let format = |date: DateTime<FixedOffset>| date.format("%Y-%m-%d %H:%M:%S").to_string();
+        assert_eq!(
+            format(message.date_in_zone(&offset, &tz).unwrap()),
+            "2022-05-17 20:29:42"
+        );
+        assert_eq!(
+            format(message.date_read_in_zone(&offset, &tz).unwrap()),
+            "2022-05-17 21:30:31"
+        );
+        assert_eq!(
+            message.date_in_zone(&offset, &Utc).unwrap(),
+            message.date(&offset).unwrap()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn can_serialize_message() {
+        let mut m = blank();
+        m.text = Some("Hello".to_string());
+
+        let value = serde_json::to_value(&m).unwrap();
+        let fields: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut expected = vec![
+            "rowid",
+            "guid",
+            "text",
+            "service",
+            "handle_id",
+            "destination_caller_id",
+            "subject",
+            "date",
+            "date_formatted",
+            "date_read",
+            "date_read_formatted",
+            "date_delivered",
+            "date_delivered_formatted",
+            "is_from_me",
+            "is_read",
+            "item_type",
+            "other_handle",
+            "share_status",
+            "share_direction",
+            "group_title",
+            "group_action_type",
+            "associated_message_guid",
+            "associated_message_type",
+            "balloon_bundle_id",
+            "expressive_send_style_id",
+            "thread_originator_guid",
+            "thread_originator_part",
+            "date_edited",
+            "date_edited_formatted",
+            "chat_id",
+            "num_attachments",
+            "deleted_from",
+            "num_replies",
+            "variant",
+            "service_type",
+            "expressive",
+        ];
+        expected.sort_unstable();
+        assert_eq!(fields, expected);
+
+        assert_eq!(value["text"], "Hello");
+        assert_eq!(value["service_type"], "iMessage");
+        assert!(value["date_formatted"].is_null());
+        assert!(value["date_read_formatted"].is_null());
+
+        m.date = 674526582885055488;
+        let value = serde_json::to_value(&m).unwrap();
+        assert!(value["date_formatted"].is_string());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn can_serialize_body() {
+        let mut m = blank();
+        m.text = Some("\u{FFFC}Hi".to_string());
+
+        let value = serde_json::to_value(m.body()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!([
+                {"Attachment": 0},
+                {"Text": [{"start": 3, "end": 5, "effect": "Default"}]}
+            ])
+        );
+    }
+
     #[test]
     fn can_get_time_date_read_after_date() {
         // Get offset
@@ -1087,277 +3027,2925 @@ mod tests {
         message.date_read = 674530231992568192;
 
         assert_eq!(
-            message.time_until_read(&offset),
-            Some("1 hour, 49 seconds".to_string())
+            message.time_until_read(&offset),
+            Some("1 hour, 49 seconds".to_string())
+        );
+    }
+
+    #[test]
+    fn can_get_time_date_read_before_date() {
+        // Get offset
+        let offset = get_offset();
+
+        // Create message
+        let mut message = blank();
+        // May 17, 2022  9:30:31 PM
+        message.date = 674530231992568192;
+        // May 17, 2022  9:30:31 PM
+        message.date_delivered = 674530231992568192;
+        // May 17, 2022  8:29:42 PM
+        message.date_read = 674526582885055488;
+
+        assert_eq!(
+            message.time_until_read(&offset),
+            Some("0 seconds".to_string())
+        );
+    }
+
+    #[test]
+    fn can_get_time_date_read_before_date_skewed() {
+        // Get offset
+        let offset = get_offset();
+
+        // Create message
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        // May 17, 2022  9:30:31 PM
+        message.date_delivered = 674530231992568192;
+        // May 17, 2022  8:00:00 PM
+        message.date_read = 674524800000000000;
+
+        assert_eq!(
+            message.time_until_read(&offset),
+            Some("1 hour, 49 seconds".to_string())
+        );
+    }
+
+    #[test]
+    fn cant_get_time_date_read_before_date_no_delivered() {
+        // Get offset
+        let offset = get_offset();
+
+        // Create message
+        let mut message = blank();
+        // May 17, 2022  9:30:31 PM
+        message.date = 674530231992568192;
+        // May 17, 2022  8:29:42 PM
+        message.date_read = 674526582885055488;
+
+        assert_eq!(message.time_until_read(&offset), None);
+    }
+
+    #[test]
+    fn can_get_time_date_read_same_as_date() {
+        // Get offset
+        let offset = get_offset();
+
+        // Create message
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        // May 17, 2022  8:29:42 PM
+        message.date_read = 674526582885055488;
+
+        assert_eq!(
+            message.time_until_read(&offset),
+            Some("0 seconds".to_string())
+        );
+    }
+
+    #[test]
+    fn can_get_subject() {
+        let mut m = blank();
+        m.subject = Some("Dinner plans".to_string());
+        assert_eq!(m.subject(), Some("Dinner plans"));
+    }
+
+    #[test]
+    fn cant_get_subject_empty() {
+        let mut m = blank();
+        m.subject = Some(String::new());
+        assert_eq!(m.subject(), None);
+    }
+
+    #[test]
+    fn cant_get_subject_none() {
+        let m = blank();
+        assert_eq!(m.subject(), None);
+    }
+
+    #[test]
+    fn can_get_time_until_read_duration() {
+        // Get offset
+        let offset = get_offset();
+
+        // Create message
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        // May 17, 2022  9:30:31 PM
+        message.date_read = 674530231992568192;
+
+        assert_eq!(
+            message.time_until_read_duration(&offset),
+            Some(Duration::seconds(3649))
+        );
+    }
+
+    #[test]
+    fn cant_get_time_until_read_duration_unread() {
+        let offset = get_offset();
+
+        let mut message = blank();
+        message.date = 674526582885055488;
+
+        assert_eq!(message.time_until_read_duration(&offset), None);
+    }
+
+    #[test]
+    fn can_get_read_info_received() {
+        let offset = get_offset();
+
+        let mut message = blank();
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        // May 17, 2022  9:30:31 PM
+        message.date_read = 674530231992568192;
+
+        assert_eq!(
+            message.read_info(&offset),
+            ReadInfo::ReceivedReadAfter(Duration::seconds(3649))
+        );
+    }
+
+    #[test]
+    fn can_get_read_info_sent() {
+        let offset = get_offset();
+
+        let mut message = blank();
+        message.is_from_me = true;
+        // May 17, 2022  8:29:42 PM
+        message.date = 674526582885055488;
+        // May 17, 2022  8:29:42 PM
+        message.date_delivered = 674526582885055488;
+
+        assert_eq!(
+            message.read_info(&offset),
+            ReadInfo::SentReadAfter(Duration::zero())
+        );
+    }
+
+    #[test]
+    fn cant_get_read_info_unread() {
+        let offset = get_offset();
+
+        let mut message = blank();
+        message.date = 674526582885055488;
+
+        assert_eq!(message.read_info(&offset), ReadInfo::Unknown);
+    }
+
+    #[test]
+    fn can_get_message_expression_none() {
+        let m = blank();
+        assert_eq!(m.get_expressive(), expressives::Expressive::None);
+    }
+
+    #[test]
+    fn can_get_message_expression_bubble() {
+        let mut m = blank();
+        m.expressive_send_style_id = Some("com.apple.MobileSMS.expressivesend.gentle".to_string());
+        assert_eq!(
+            m.get_expressive(),
+            expressives::Expressive::Bubble(expressives::BubbleEffect::Gentle)
+        );
+    }
+
+    #[test]
+    fn can_get_message_expression_screen() {
+        let mut m = blank();
+        m.expressive_send_style_id =
+            Some("com.apple.messages.effect.CKHappyBirthdayEffect".to_string());
+        assert_eq!(
+            m.get_expressive(),
+            expressives::Expressive::Screen(expressives::ScreenEffect::Balloons)
+        );
+    }
+
+    #[test]
+    fn can_get_no_balloon_bundle_id() {
+        let m = blank();
+        assert_eq!(m.parse_balloon_bundle_id(), None);
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_os() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.Handwriting.HandwritingProvider".to_owned());
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.apple.Handwriting.HandwritingProvider")
+        );
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_url() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_owned());
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.apple.messages.URLBalloonProvider")
+        );
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_two_parts() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some(
+            "com.apple.messages.MSMessageExtensionBalloonPlugin:com.apple.findmy.FindMyMessagesApp"
+                .to_owned(),
+        );
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.apple.findmy.FindMyMessagesApp")
+        );
+        assert!(matches!(m.variant(), Variant::App(CustomBalloon::FindMy)));
+    }
+
+    #[test]
+    fn cant_get_balloon_bundle_id_trailing_separator() {
+        let mut m = blank();
+        m.balloon_bundle_id =
+            Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:".to_owned());
+        assert_eq!(m.parse_balloon_bundle_id(), None);
+    }
+
+    #[test]
+    fn cant_get_balloon_bundle_id_empty() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some(String::new());
+        assert_eq!(m.parse_balloon_bundle_id(), None);
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_apple() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.PassbookUIService.PeerPaymentMessagesExtension".to_owned());
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.apple.PassbookUIService.PeerPaymentMessagesExtension")
+        );
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_third_party() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.MSMessageExtensionBalloonPlugin:QPU8QS3E62:com.contextoptional.OpenTable.Messages".to_owned());
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.contextoptional.OpenTable.Messages")
+        );
+        assert!(matches!(
+            m.variant(),
+            Variant::App(CustomBalloon::Application(
+                "com.contextoptional.OpenTable.Messages"
+            ))
+        ));
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_digital_touch() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.DigitalTouchBalloonProvider".to_owned());
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.apple.DigitalTouchBalloonProvider")
+        );
+        assert!(matches!(
+            m.variant(),
+            Variant::App(CustomBalloon::DigitalTouch)
+        ));
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_animoji() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.Jellyfish.Animoji".to_owned());
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.apple.Jellyfish.Animoji")
+        );
+        assert!(matches!(m.variant(), Variant::App(CustomBalloon::Animoji)));
+    }
+
+    #[test]
+    fn can_get_balloon_bundle_id_business() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.icloud.apps.messages.business.extension".to_owned());
+        assert_eq!(
+            m.parse_balloon_bundle_id(),
+            Some("com.apple.icloud.apps.messages.business.extension")
+        );
+        assert!(matches!(m.variant(), Variant::App(CustomBalloon::Business)));
+    }
+
+    #[test]
+    fn can_get_valid_guid() {
+        let mut m = blank();
+        m.associated_message_guid = Some("A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+
+        assert_eq!(
+            Some((0usize, "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A")),
+            m.clean_associated_guid()
+        );
+    }
+
+    #[test]
+    fn cant_get_invalid_guid() {
+        let mut m = blank();
+        m.associated_message_guid = Some("FAKE_GUID".to_string());
+
+        assert_eq!(None, m.clean_associated_guid());
+    }
+
+    #[test]
+    fn can_get_valid_guid_p() {
+        let mut m = blank();
+        m.associated_message_guid = Some("p:1/A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+
+        assert_eq!(
+            Some((1usize, "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A")),
+            m.clean_associated_guid()
+        );
+    }
+
+    #[test]
+    fn cant_get_invalid_guid_p() {
+        let mut m = blank();
+        m.associated_message_guid = Some("p:1/FAKE_GUID".to_string());
+
+        assert_eq!(None, m.clean_associated_guid());
+    }
+
+    #[test]
+    fn can_get_valid_guid_bp() {
+        let mut m = blank();
+        m.associated_message_guid = Some("bp:A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+
+        assert_eq!(
+            Some((0usize, "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A")),
+            m.clean_associated_guid()
+        );
+    }
+
+    #[test]
+    fn can_get_valid_guid_forms() {
+        let guid = "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A";
+        for (associated_message_guid, expected) in [
+            (format!("p:0/{guid}"), Some((0, guid))),
+            (format!("p:3/{guid}"), Some((3, guid))),
+            (format!("bp:{guid}"), Some((0, guid))),
+            (format!("p:2/bp:{guid}"), Some((2, guid))),
+            (guid.to_string(), Some((0, guid))),
+            (format!("p:x/{guid}"), Some((0, guid))),
+            (format!("p:1{guid}"), None),
+            ("bp:".to_string(), None),
+        ] {
+            let mut m = blank();
+            m.associated_message_guid = Some(associated_message_guid.clone());
+
+            assert_eq!(
+                m.clean_associated_guid(),
+                expected,
+                "{associated_message_guid}"
+            );
+        }
+    }
+
+    #[test]
+    fn cant_get_malformed_guid_p() {
+        let guid = "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A";
+        for (associated_message_guid, expected) in [
+            ("p:".to_string(), None),
+            (format!("p:{guid}"), None),
+            ("p:/".to_string(), None),
+            (format!("p:/{guid}"), Some((0, guid))),
+            ("p:x/abc".to_string(), None),
+        ] {
+            let mut m = blank();
+            m.associated_message_guid = Some(associated_message_guid.clone());
+
+            assert_eq!(
+                m.clean_associated_guid(),
+                expected,
+                "{associated_message_guid}"
+            );
+            assert_eq!(m.reaction_index(), 0, "{associated_message_guid}");
+        }
+    }
+
+    #[test]
+    fn cant_get_invalid_guid_bp() {
+        let mut m = blank();
+        m.associated_message_guid = Some("bp:FAKE_GUID".to_string());
+
+        assert_eq!(None, m.clean_associated_guid());
+    }
+
+    #[test]
+    fn can_get_fully_unsent_true_single() {
+        let mut m = blank();
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![EditedMessagePart {
+                status: EditStatus::Unsent,
+                edit_history: vec![],
+            }],
+        });
+
+        assert!(m.is_fully_unsent());
+    }
+
+    #[test]
+    fn can_get_fully_unsent_true_multiple() {
+        let mut m = blank();
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![
+                EditedMessagePart {
+                    status: EditStatus::Unsent,
+                    edit_history: vec![],
+                },
+                EditedMessagePart {
+                    status: EditStatus::Unsent,
+                    edit_history: vec![],
+                },
+            ],
+        });
+
+        assert!(m.is_fully_unsent());
+    }
+
+    #[test]
+    fn can_get_fully_unsent_false() {
+        let mut m = blank();
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![EditedMessagePart {
+                status: EditStatus::Original,
+                edit_history: vec![],
+            }],
+        });
+
+        assert!(!m.is_fully_unsent());
+    }
+
+    #[test]
+    fn can_get_fully_unsent_false_multiple() {
+        let mut m = blank();
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![
+                EditedMessagePart {
+                    status: EditStatus::Unsent,
+                    edit_history: vec![],
+                },
+                EditedMessagePart {
+                    status: EditStatus::Original,
+                    edit_history: vec![],
+                },
+            ],
+        });
+
+        assert!(!m.is_fully_unsent());
+    }
+
+    #[test]
+    fn can_get_part_edited_true() {
+        let mut m = blank();
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![
+                EditedMessagePart {
+                    status: EditStatus::Edited,
+                    edit_history: vec![],
+                },
+                EditedMessagePart {
+                    status: EditStatus::Original,
+                    edit_history: vec![],
+                },
+            ],
+        });
+
+        assert!(m.is_part_edited(0));
+    }
+
+    #[test]
+    fn can_get_part_edited_false() {
+        let mut m = blank();
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![
+                EditedMessagePart {
+                    status: EditStatus::Edited,
+                    edit_history: vec![],
+                },
+                EditedMessagePart {
+                    status: EditStatus::Original,
+                    edit_history: vec![],
+                },
+            ],
+        });
+
+        assert!(!m.is_part_edited(1));
+    }
+
+    #[test]
+    fn can_get_part_edited_blank() {
+        let m = blank();
+
+        assert!(!m.is_part_edited(0));
+    }
+
+    #[test]
+    fn can_get_fully_unsent_none() {
+        let m = blank();
+
+        assert!(!m.is_fully_unsent());
+    }
+
+    #[test]
+    fn can_tally_reactions() {
+        let mut given_love = blank();
+        given_love.is_from_me = true;
+        given_love.associated_message_type = Some(2000);
+
+        let mut received_like = blank();
+        received_like.associated_message_type = Some(2001);
+
+        let mut received_love = blank();
+        received_love.associated_message_type = Some(2000);
+
+        let mut removed_love = blank();
+        removed_love.associated_message_type = Some(3000);
+
+        let cache = HashMap::from([
+            (
+                "A".to_string(),
+                HashMap::from([(0, vec![given_love, received_like])]),
+            ),
+            (
+                "B".to_string(),
+                HashMap::from([(1, vec![received_love, removed_love])]),
+            ),
+        ]);
+
+        let (given, received) = tally_reactions(&cache);
+        assert_eq!(given, HashMap::from([(Reaction::Loved, 1)]));
+        assert_eq!(
+            received,
+            HashMap::from([(Reaction::Liked, 1), (Reaction::Loved, 0)])
+        );
+    }
+
+    #[test]
+    fn can_tally_reactions_empty() {
+        let (given, received) = tally_reactions(&HashMap::new());
+        assert!(given.is_empty());
+        assert!(received.is_empty());
+    }
+
+    #[test]
+    fn can_get_longest_gap() {
+        let messages: Vec<Message> = [0, 5, 20, 22]
+            .into_iter()
+            .map(|seconds| {
+                let mut m = blank();
+                m.date = seconds * 1_000_000_000;
+                m
+            })
+            .collect();
+
+        assert_eq!(
+            longest_gap(&messages),
+            Some((Duration::seconds(15), 5_000_000_000, 20_000_000_000))
+        );
+    }
+
+    #[test]
+    fn can_get_longest_gap_ignoring_reactions() {
+        let mut messages: Vec<Message> = [0, 10, 30]
+            .into_iter()
+            .map(|seconds| {
+                let mut m = blank();
+                m.date = seconds * 1_000_000_000;
+                m
+            })
+            .collect();
+        messages[1].associated_message_type = Some(2000);
+
+        assert_eq!(
+            longest_gap(&messages),
+            Some((Duration::seconds(30), 0, 30_000_000_000))
+        );
+    }
+
+    #[test]
+    fn cant_get_longest_gap_single_message() {
+        assert_eq!(longest_gap(&[]), None);
+        assert_eq!(longest_gap(&[blank()]), None);
+
+        let mut reaction = blank();
+        reaction.associated_message_type = Some(2000);
+        assert_eq!(longest_gap(&[blank(), reaction]), None);
+    }
+
+    #[test]
+    fn can_start_sender_run_first_message() {
+        assert!(starts_sender_run(None, &blank()));
+    }
+
+    #[test]
+    fn can_start_sender_run_new_sender() {
+        let mut alice = blank();
+        alice.handle_id = Some(1);
+        let mut bob = blank();
+        bob.handle_id = Some(2);
+        let mut me = blank();
+        me.is_from_me = true;
+        me.handle_id = Some(0);
+
+        assert!(starts_sender_run(Some(&alice), &bob));
+        assert!(starts_sender_run(Some(&alice), &me));
+        assert!(starts_sender_run(Some(&me), &alice));
+    }
+
+    #[test]
+    fn cant_start_sender_run_same_sender() {
+        let mut alice = blank();
+        alice.handle_id = Some(1);
+        assert!(!starts_sender_run(Some(&alice), &alice));
+
+        // Messages sent by the database owner may store the recipient's handle
+        let mut me_to_alice = blank();
+        me_to_alice.is_from_me = true;
+        me_to_alice.handle_id = Some(1);
+        let mut me_to_bob = blank();
+        me_to_bob.is_from_me = true;
+        me_to_bob.handle_id = Some(2);
+        assert!(!starts_sender_run(Some(&me_to_alice), &me_to_bob));
+    }
+
+    #[test]
+    fn can_get_reaction_target_attachment() {
+        let mut m = blank();
+        m.associated_message_type = Some(2000);
+        m.associated_message_guid = Some("p:0/A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+
+        let body = [
+            BubbleComponent::Attachment(0),
+            BubbleComponent::Text(vec![]),
+        ];
+        assert_eq!(
+            m.reaction_target(&body),
+            Some(&BubbleComponent::Attachment(0))
+        );
+    }
+
+    #[test]
+    fn can_get_reaction_target_text() {
+        let mut m = blank();
+        m.associated_message_type = Some(2001);
+        m.associated_message_guid = Some("p:1/A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+
+        let body = [
+            BubbleComponent::Attachment(0),
+            BubbleComponent::Text(vec![]),
+        ];
+        assert_eq!(
+            m.reaction_target(&body),
+            Some(&BubbleComponent::Text(vec![]))
+        );
+    }
+
+    #[test]
+    fn can_get_reaction_target_app() {
+        let mut m = blank();
+        m.associated_message_type = Some(2003);
+        m.associated_message_guid = Some("bp:A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+
+        let body = [BubbleComponent::App];
+        assert_eq!(m.reaction_target(&body), Some(&BubbleComponent::App));
+    }
+
+    #[test]
+    fn cant_get_reaction_target_out_of_range() {
+        let mut m = blank();
+        m.associated_message_type = Some(2000);
+        m.associated_message_guid = Some("p:5/A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+
+        let body = [
+            BubbleComponent::Attachment(0),
+            BubbleComponent::Text(vec![]),
+        ];
+        assert_eq!(m.reaction_target(&body), None);
+    }
+
+    #[test]
+    fn cant_get_reaction_target_not_reaction() {
+        let m = blank();
+
+        let body = [BubbleComponent::Text(vec![])];
+        assert_eq!(m.reaction_target(&body), None);
+    }
+
+    #[test]
+    fn can_get_sms_quoted_reply_angle_bracket() {
+        let mut m = blank();
+        m.service = Some("SMS".to_string());
+        m.text = Some("> Are you coming tonight?\nYes, see you at 8".to_string());
+
+        assert_eq!(
+            m.sms_quoted_reply(),
+            Some(("Are you coming tonight?", "Yes, see you at 8"))
+        );
+    }
+
+    #[test]
+    fn can_get_sms_quoted_reply_quotes() {
+        let mut m = blank();
+        m.service = Some("SMS".to_string());
+        m.text = Some("“Are you coming tonight?”\n\nYes".to_string());
+
+        assert_eq!(
+            m.sms_quoted_reply(),
+            Some(("Are you coming tonight?", "Yes"))
+        );
+    }
+
+    #[test]
+    fn cant_get_sms_quoted_reply_imessage() {
+        let mut m = blank();
+        m.text = Some("> Are you coming tonight?\nYes".to_string());
+
+        assert_eq!(m.sms_quoted_reply(), None);
+    }
+
+    #[test]
+    fn cant_get_sms_quoted_reply_no_quote() {
+        let mut m = blank();
+        m.service = Some("SMS".to_string());
+        m.text = Some("Are you coming tonight?\nYes".to_string());
+
+        assert_eq!(m.sms_quoted_reply(), None);
+    }
+
+    #[test]
+    fn cant_get_sms_quoted_reply_no_reply() {
+        let mut m = blank();
+        m.service = Some("SMS".to_string());
+        m.text = Some("> Are you coming tonight?\n".to_string());
+
+        assert_eq!(m.sms_quoted_reply(), None);
+    }
+
+    #[test]
+    fn can_get_variant_normal() {
+        let m = blank();
+
+        assert!(matches!(m.variant(), Variant::Normal));
+    }
+
+    #[test]
+    fn can_get_variant_edited_normal() {
+        let mut m = blank();
+        m.date_edited = 674530231992568192;
+
+        assert!(matches!(m.variant(), Variant::Edited));
+    }
+
+    #[test]
+    fn can_get_variant_edited_app() {
+        let mut m = blank();
+        m.date_edited = 674530231992568192;
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_string());
+
+        assert!(matches!(m.variant(), Variant::Edited));
+    }
+
+    #[test]
+    fn can_get_variant_unsent() {
+        let mut m = blank();
+        m.date_edited = 674530231992568192;
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![EditedMessagePart {
+                status: EditStatus::Unsent,
+                edit_history: vec![],
+            }],
+        });
+
+        assert!(matches!(m.variant(), Variant::Edited));
+        assert!(m.is_fully_unsent());
+    }
+
+    fn url_fixture(plist: &str) -> (TestDatabase, i32) {
+        let fixture = TestDatabase::new();
+        let rowid = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                balloon_bundle_id: Some("com.apple.messages.URLBalloonProvider"),
+                ..Default::default()
+            },
+        );
+        let payload = std::fs::read(
+            current_dir()
+                .unwrap()
+                .as_path()
+                .join(format!("test_data/url_message/{plist}")),
+        )
+        .unwrap();
+        fixture
+            .connection()
+            .execute(
+                "UPDATE message SET payload_data = ?1 WHERE ROWID = ?2",
+                rusqlite::params![payload, rowid],
+            )
+            .unwrap();
+        (fixture, rowid)
+    }
+
+    #[test]
+    fn can_get_preview_image_attachment() {
+        let (fixture, rowid) = url_fixture("MetadataURL.plist");
+        fixture.add_attachment(
+            rowid,
+            "~/Library/Messages/Attachments/icon.txt",
+            "text/plain",
+        );
+        fixture.add_attachment(
+            rowid,
+            "~/Library/Messages/Attachments/preview.png",
+            "image/png",
+        );
+
+        let db = fixture.connection();
+        let message = Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000001")
+            .unwrap()
+            .unwrap();
+        match message.preview_image(db).unwrap() {
+            Some(PreviewImage::Attachment(attachment)) => assert_eq!(
+                attachment.filename.as_deref(),
+                Some("~/Library/Messages/Attachments/preview.png")
+            ),
+            _ => panic!("Expected an attachment preview"),
+        }
+    }
+
+    #[test]
+    fn can_get_preview_image_url() {
+        let (fixture, _) = url_fixture("MetadataURL.plist");
+
+        let db = fixture.connection();
+        let message = Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000001")
+            .unwrap()
+            .unwrap();
+        match message.preview_image(db).unwrap() {
+            Some(PreviewImage::URL(url)) => {
+                assert_eq!(url, "https://chrissardegna.com/ddc-facebook-icon.png");
+            }
+            _ => panic!("Expected a URL preview"),
+        }
+    }
+
+    #[test]
+    fn cant_get_preview_image_not_url() {
+        let fixture = TestDatabase::new();
+        assert!(blank()
+            .preview_image(fixture.connection())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn can_get_payload_bytes() {
+        let (fixture, _) = url_fixture("URL.plist");
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                text: Some("Hello"),
+                ..Default::default()
+            },
+        );
+        let empty = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000003",
+                ..Default::default()
+            },
+        );
+        fixture
+            .connection()
+            .execute(
+                "UPDATE message SET payload_data = X'' WHERE ROWID = ?1",
+                rusqlite::params![empty],
+            )
+            .unwrap();
+
+        let db = fixture.connection();
+        let expected = std::fs::read(
+            current_dir()
+                .unwrap()
+                .as_path()
+                .join("test_data/url_message/URL.plist"),
+        )
+        .unwrap();
+        let message = |guid: &str| Message::get_by_guid(db, guid).unwrap().unwrap();
+        assert_eq!(
+            message("A44CE9D7-AAAA-BBBB-CCCC-000000000001")
+                .payload_bytes(db)
+                .unwrap(),
+            Some(expected)
+        );
+        assert_eq!(
+            message("A44CE9D7-AAAA-BBBB-CCCC-000000000002")
+                .payload_bytes(db)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            message("A44CE9D7-AAAA-BBBB-CCCC-000000000003")
+                .payload_bytes(db)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn cant_get_payload_bytes_without_table() {
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        assert!(blank().payload_bytes(&db).is_err());
+    }
+
+    #[test]
+    fn can_get_music() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_string());
+
+        let plist_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/music_message/AppleMusic.plist");
+        let plist = Value::from_reader(File::open(plist_path).unwrap()).unwrap();
+        let parsed = parse_plist(&plist).unwrap();
+
+        let music = m.music(&parsed).unwrap();
+        assert_eq!(music.artist, Some("БАТЮШКА"));
+        assert_eq!(music.album, Some("Панихида"));
+        assert_eq!(music.track_name, Some("Песнь 1"));
+        assert!(music.preview.is_some());
+    }
+
+    #[test]
+    fn cant_get_music_url() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_string());
+
+        let plist_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/url_message/URL.plist");
+        let plist = Value::from_reader(File::open(plist_path).unwrap()).unwrap();
+        let parsed = parse_plist(&plist).unwrap();
+
+        assert!(m.music(&parsed).is_none());
+    }
+
+    #[test]
+    fn cant_get_music_not_url() {
+        let m = blank();
+
+        let plist_path = current_dir()
+            .unwrap()
+            .as_path()
+            .join("test_data/music_message/AppleMusic.plist");
+        let plist = Value::from_reader(File::open(plist_path).unwrap()).unwrap();
+        let parsed = parse_plist(&plist).unwrap();
+
+        assert!(m.music(&parsed).is_none());
+    }
+
+    #[test]
+    fn can_match_known_association_types_to_variant() {
+        for association_type in -1..4000 {
+            let mut m = blank();
+            m.associated_message_type = Some(association_type);
+
+            assert_eq!(
+                Message::is_known_association_type(association_type),
+                !matches!(m.variant(), Variant::Unknown(_)),
+                "{association_type}"
+            );
+        }
+    }
+
+    #[test]
+    fn can_get_minimal() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        let message = fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                text: Some("Hello world"),
+                ..Default::default()
+            },
+        );
+        fixture.add_attachment(message, "~/Library/Messages/Attachments/a.png", "image/png");
+
+        let mut statement = Message::get_minimal(fixture.connection()).unwrap();
+        let messages: Vec<Message> = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap())
+            .collect();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].text.as_deref(), Some("Hello world"));
+        assert_eq!(messages[0].chat_id, Some(chat));
+        assert_eq!(messages[0].num_attachments, 0);
+        assert_eq!(messages[0].num_replies, 0);
+    }
+
+    #[test]
+    fn can_get_thread_name() {
+        let fixture = TestDatabase::new();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                text: Some("\u{FFFC}\nDinner plans?\nLet me know"),
+                ..Default::default()
+            },
+        );
+
+        let mut m = blank();
+        m.thread_originator_guid = Some("A44CE9D7-AAAA-BBBB-CCCC-000000000001".to_string());
+
+        assert_eq!(
+            m.thread_name(fixture.connection()).unwrap(),
+            Some("Dinner plans?".to_string())
+        );
+    }
+
+    #[test]
+    fn can_get_thread_name_long() {
+        let fixture = TestDatabase::new();
+        let text = "a".repeat(THREAD_NAME_LENGTH + 10);
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                text: Some(&text),
+                ..Default::default()
+            },
+        );
+
+        let mut m = blank();
+        m.thread_originator_guid = Some("A44CE9D7-AAAA-BBBB-CCCC-000000000001".to_string());
+
+        assert_eq!(
+            m.thread_name(fixture.connection()).unwrap(),
+            Some(format!("{}…", "a".repeat(THREAD_NAME_LENGTH)))
+        );
+    }
+
+    #[test]
+    fn cant_get_thread_name_missing_originator() {
+        let fixture = TestDatabase::new();
+
+        let mut m = blank();
+        m.thread_originator_guid = Some("A44CE9D7-AAAA-BBBB-CCCC-000000000001".to_string());
+
+        assert_eq!(m.thread_name(fixture.connection()).unwrap(), None);
+    }
+
+    #[test]
+    fn cant_get_thread_name_not_reply() {
+        let fixture = TestDatabase::new();
+        let m = blank();
+
+        assert_eq!(m.thread_name(fixture.connection()).unwrap(), None);
+    }
+
+    #[test]
+    fn can_detect_otp() {
+        let mut m = blank();
+        m.text = Some("Your verification code is 123456".to_string());
+        assert!(m.looks_like_otp());
+    }
+
+    #[test]
+    fn can_detect_otp_prefixed() {
+        let mut m = blank();
+        m.text = Some("G-482913 is your Google verification code.".to_string());
+        assert!(m.looks_like_otp());
+    }
+
+    #[test]
+    fn cant_detect_otp_no_keyword() {
+        let mut m = blank();
+        m.text = Some("See you at 1830".to_string());
+        assert!(!m.looks_like_otp());
+    }
+
+    #[test]
+    fn cant_detect_otp_phone_number() {
+        let mut m = blank();
+        m.text = Some("The code to the gate is to call 5558675309".to_string());
+        assert!(!m.looks_like_otp());
+    }
+
+    #[test]
+    fn cant_detect_otp_no_text() {
+        let m = blank();
+        assert!(!m.looks_like_otp());
+    }
+
+    #[test]
+    fn can_get_standalone_sticker_no_text() {
+        let mut m = blank();
+        m.associated_message_type = Some(1000);
+
+        assert!(m.is_standalone_sticker());
+    }
+
+    #[test]
+    fn can_get_standalone_sticker_placeholder() {
+        let mut m = blank();
+        m.associated_message_type = Some(1000);
+        m.text = Some("\u{FFFC}".to_string());
+
+        assert!(m.is_standalone_sticker());
+    }
+
+    #[test]
+    fn cant_get_standalone_sticker_with_text() {
+        let mut m = blank();
+        m.associated_message_type = Some(1000);
+        m.text = Some("\u{FFFC}Nice!".to_string());
+
+        assert!(!m.is_standalone_sticker());
+    }
+
+    #[test]
+    fn cant_get_standalone_sticker_not_sticker() {
+        let m = blank();
+
+        assert!(!m.is_standalone_sticker());
+    }
+
+    #[test]
+    fn can_get_chat_date_span() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        let other_chat = fixture.add_chat("+15551234567", "iMessage", &[]);
+        for (guid, chat_id, date) in [
+            (
+                "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                chat,
+                674526582885055488,
+            ),
+            (
+                "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                chat,
+                674530231992568192,
+            ),
+            (
+                "A44CE9D7-AAAA-BBBB-CCCC-000000000003",
+                chat,
+                674528000000000000,
+            ),
+            ("A44CE9D7-AAAA-BBBB-CCCC-000000000004", other_chat, 0),
+        ] {
+            fixture.add_message(
+                Some(chat_id),
+                &TestMessage {
+                    guid,
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let offset = get_offset();
+        let (first, last) = Message::chat_date_span(fixture.connection(), chat, &offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, get_local_time(&674526582885055488, &offset).unwrap());
+        assert_eq!(last, get_local_time(&674530231992568192, &offset).unwrap());
+    }
+
+    #[test]
+    fn cant_get_chat_date_span_empty() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+
+        assert!(
+            Message::chat_date_span(fixture.connection(), chat, &get_offset())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn can_get_effective_service_own() {
+        let m = blank();
+
+        assert!(matches!(
+            m.effective_service(Service::SMS),
+            Service::iMessage
+        ));
+    }
+
+    #[test]
+    fn can_get_effective_service_fallback() {
+        let mut m = blank();
+        m.service = None;
+
+        assert!(matches!(m.effective_service(Service::SMS), Service::SMS));
+    }
+
+    #[test]
+    fn can_get_effective_service_fallback_empty() {
+        let mut m = blank();
+        m.service = Some(String::new());
+
+        assert!(matches!(m.effective_service(Service::SMS), Service::SMS));
+    }
+
+    #[test]
+    fn can_stream_with_dates_in_seconds() {
+        let fixture = TestDatabase::new();
+        let mut context = QueryContext {
+            scale: TimestampScale::Seconds,
+            ..Default::default()
+        };
+        context.set_start("2020-01-01").unwrap();
+        context.set_end("2020-02-02").unwrap();
+
+        let start = context.start.unwrap() / 1_000_000_000;
+        for (n, date) in [(1, start - 1), (2, start + 1), (3, start + 86400 * 60)] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut statement = Message::stream_rows(fixture.connection(), &context).unwrap();
+        let rowids: Vec<i32> = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap().rowid)
+            .collect();
+        assert_eq!(rowids, [2]);
+    }
+
+    #[test]
+    fn can_stream_without_reactions() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        let target = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: target,
+                text: Some("Hello"),
+                ..Default::default()
+            },
+        );
+        // Tapback
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                date: 1,
+                associated_message_guid: Some(&format!("p:0/{target}")),
+                associated_message_type: 2000,
+                ..Default::default()
+            },
+        );
+        // Sticker placed on a message
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000003",
+                date: 2,
+                associated_message_guid: Some(&format!("p:0/{target}")),
+                associated_message_type: 1000,
+                ..Default::default()
+            },
+        );
+        // Sticker sent on its own
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000004",
+                date: 3,
+                associated_message_type: 1000,
+                ..Default::default()
+            },
+        );
+
+        let db = fixture.connection();
+        let stream = |context: &QueryContext| -> Vec<Message> {
+            let mut statement = Message::stream_rows(db, context).unwrap();
+            statement
+                .query_map([], |row| Ok(Message::from_row(row)))
+                .unwrap()
+                .map(|message| Message::extract(message).unwrap())
+                .collect()
+        };
+
+        let mut context = QueryContext::default();
+        assert_eq!(stream(&context).len(), 4);
+        assert_eq!(Message::get_count(db, &context).unwrap(), 4);
+
+        context.include_reactions = false;
+        let messages = stream(&context);
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|message| !message.is_reaction()));
+        assert_eq!(Message::get_count(db, &context).unwrap(), 2);
+
+        context.start = Some(1);
+        assert_eq!(stream(&context).len(), 1);
+        assert_eq!(Message::get_count(db, &context).unwrap(), 1);
+    }
+
+    #[test]
+    fn reaction_predicate_matches_is_reaction() {
+        let fixture = TestDatabase::new();
+        let target = "A44CE9D7-AAAA-BBBB-CCCC-000000000000";
+        let associated = format!("p:0/{target}");
+        let mut guids = vec![];
+        for association_type in [0, 2, 3, 1000, 1001, 2000, 2005, 2006, 3000, 3005, 3006] {
+            for associated_message_guid in [None, Some(associated.as_str())] {
+                guids.push(format!(
+                    "A44CE9D7-AAAA-BBBB-{association_type:04}-{:012}",
+                    guids.len()
+                ));
+                fixture.add_message(
+                    None,
+                    &TestMessage {
+                        guid: guids.last().unwrap(),
+                        associated_message_guid,
+                        associated_message_type: association_type,
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+
+        let db = fixture.connection();
+        let mut statement = db
+            .prepare(&format!(
+                "SELECT m.guid, {REACTION_PREDICATE}, {TAPBACK_PREDICATE}, {STICKER_PREDICATE} FROM message as m"
+            ))
+            .unwrap();
+        let matches: HashMap<String, (bool, bool, bool)> = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, (row.get(1)?, row.get(2)?, row.get(3)?)))
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+
+        let mut statement = Message::get(db).unwrap();
+        let messages = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap());
+        for message in messages {
+            let (is_reaction, is_tapback, is_sticker) = matches[&message.guid];
+            assert_eq!(is_reaction, is_tapback || is_sticker);
+            assert_eq!(
+                is_tapback,
+                matches!(message.variant(), Variant::Reaction(..)),
+                "{:?}",
+                message.associated_message_type
+            );
+            assert_eq!(
+                is_reaction,
+                message.is_reaction(),
+                "{:?} {:?}",
+                message.associated_message_type,
+                message.associated_message_guid
+            );
+        }
+    }
+
+    #[test]
+    fn can_get_reactions_bulk_across_chunks() {
+        let fixture = TestDatabase::new();
+        let targets = [
+            "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+            "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+        ];
+        let mut lookup: HashMap<String, Vec<String>> = HashMap::new();
+
+        // More reactions than fit in one query, inserted newest first so ROWID order is the reverse of date order
+        let total = super::MAX_QUERY_PARAMS + 1;
+        for n in 0..total {
+            let target = targets[n % 2];
+            let guid = format!("A44CE9D7-DDDD-BBBB-CCCC-{n:012}");
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &guid,
+                    date: (total - n) as i64,
+                    associated_message_guid: Some(&format!("p:{}/{target}", n % 3 % 2)),
+                    associated_message_type: 2000,
+                    ..Default::default()
+                },
+            );
+            lookup.entry(target.to_string()).or_default().push(guid);
+        }
+
+        let guids = [
+            targets[0],
+            targets[1],
+            "A44CE9D7-AAAA-BBBB-CCCC-000000000003",
+        ];
+        let reactions = Message::get_reactions_bulk(fixture.connection(), &guids, &lookup).unwrap();
+
+        assert_eq!(reactions.len(), 2);
+        let mut found = 0;
+        for target in targets {
+            for (idx, messages) in &reactions[target] {
+                assert!(messages.windows(2).all(|pair| pair[0].date < pair[1].date));
+                for message in messages {
+                    assert!(lookup[target].contains(&message.guid));
+                    assert_eq!(message.clean_associated_guid(), Some((*idx, target)));
+                }
+                found += messages.len();
+            }
+        }
+        assert_eq!(found, total);
+    }
+
+    #[test]
+    fn can_cache_with_progress() {
+        let fixture = TestDatabase::new();
+        let target = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+        let total = super::PROGRESS_INTERVAL + 1;
+        for n in 0..total {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-DDDD-BBBB-CCCC-{n:012}"),
+                    date: n as i64,
+                    associated_message_guid: Some(&format!("p:0/{target}")),
+                    associated_message_type: 2000,
+                    ..Default::default()
+                },
+            );
+        }
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: target,
+                text: Some("Hello"),
+                ..Default::default()
+            },
+        );
+
+        let mut calls = vec![];
+        let cached = Message::cache_with_progress(fixture.connection(), &mut |processed, of| {
+            calls.push((processed, of));
+        })
+        .unwrap();
+        assert_eq!(calls, [(0, total), (1000, total), (total, total)]);
+
+        let guids = |cache: &HashMap<String, HashMap<usize, Vec<Message>>>| {
+            let mut guids: Vec<(String, usize, String)> = cache
+                .iter()
+                .flat_map(|(target, indexes)| {
+                    indexes.iter().flat_map(move |(idx, messages)| {
+                        messages
+                            .iter()
+                            .map(move |message| (target.clone(), *idx, message.guid.clone()))
+                    })
+                })
+                .collect();
+            guids.sort();
+            guids
+        };
+        let expected = Message::cache(fixture.connection()).unwrap();
+        assert_eq!(guids(&cached), guids(&expected));
+        assert_eq!(guids(&cached).len(), total);
+    }
+
+    #[test]
+    fn can_cache_stickers_separately() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        let target = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: target,
+                text: Some("\u{FFFC}Hello"),
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                date: 1,
+                associated_message_guid: Some(&format!("p:1/{target}")),
+                associated_message_type: 2001,
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000003",
+                date: 2,
+                associated_message_guid: Some(&format!("p:0/{target}")),
+                associated_message_type: 1000,
+                ..Default::default()
+            },
+        );
+
+        let db = fixture.connection();
+
+        let reactions = Message::cache(db).unwrap();
+        let reactions = &reactions[target];
+        assert_eq!(reactions.len(), 1);
+        assert!(matches!(
+            reactions[&1][0].variant(),
+            Variant::Reaction(1, true, Reaction::Liked)
+        ));
+
+        let stickers = Message::cache_stickers(db).unwrap();
+        let stickers = &stickers[target];
+        assert_eq!(stickers.len(), 1);
+        assert_eq!(stickers[&0].len(), 1);
+        assert!(matches!(stickers[&0][0].variant(), Variant::Sticker(0)));
+
+        let mut original = blank();
+        original.guid = target.to_string();
+        let lookup = HashMap::from([(
+            target.to_string(),
+            vec![
+                "A44CE9D7-AAAA-BBBB-CCCC-000000000002".to_string(),
+                "A44CE9D7-AAAA-BBBB-CCCC-000000000003".to_string(),
+            ],
+        )]);
+        let reactions = original.get_reactions(db, &lookup).unwrap();
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[&1].len(), 1);
+    }
+
+    #[test]
+    fn can_get_sticker_target() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        let target = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+        let photo = fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: target,
+                text: Some("\u{FFFC}"),
+                ..Default::default()
+            },
+        );
+        fixture.add_attachment(photo, "~/Library/Messages/Attachments/a.png", "image/png");
+
+        let db = fixture.connection();
+
+        let mut sticker = blank();
+        sticker.associated_message_type = Some(1000);
+        sticker.associated_message_guid = Some(format!("p:0/{target}"));
+        let found = sticker.sticker_target(db).unwrap();
+        assert_eq!(found.guid, target);
+        assert!(matches!(
+            sticker.reaction_target(&found.body()),
+            Some(BubbleComponent::Attachment(0))
+        ));
+
+        // Missing target
+        sticker.associated_message_guid =
+            Some("p:0/A44CE9D7-AAAA-BBBB-CCCC-000000000009".to_string());
+        assert!(sticker.sticker_target(db).is_none());
+
+        // Not a sticker
+        let mut reaction = blank();
+        reaction.associated_message_type = Some(2000);
+        reaction.associated_message_guid = Some(format!("p:0/{target}"));
+        assert!(reaction.sticker_target(db).is_none());
+    }
+
+    #[test]
+    fn can_get_message_counts_by_handle() {
+        let fixture = TestDatabase::new();
+        let alice = fixture.add_handle("+15558675309");
+        let bob = fixture.add_handle("bob@example.com");
+        let messages = [
+            (alice, false, 0),
+            (alice, false, 0),
+            (bob, false, 0),
+            (alice, true, 0),
+            (0, true, 0),
+            (bob, false, 2000),
+        ];
+        for (n, (handle_id, is_from_me, associated_message_type)) in
+            messages.into_iter().enumerate()
+        {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    handle_id,
+                    is_from_me,
+                    associated_message_type,
+                    associated_message_guid: (associated_message_type != 0)
+                        .then_some("p:0/A44CE9D7-AAAA-BBBB-CCCC-000000000000"),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let counts = Message::message_counts_by_handle(fixture.connection()).unwrap();
+        assert_eq!(counts, vec![(0, 2), (alice, 2), (bob, 1)]);
+    }
+
+    #[test]
+    fn can_get_timestamp_scale() {
+        let fixture = TestDatabase::new();
+        assert_eq!(
+            Message::timestamp_scale(fixture.connection()).unwrap(),
+            TimestampScale::Nanoseconds
+        );
+
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                date: 674526582,
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            Message::timestamp_scale(fixture.connection()).unwrap(),
+            TimestampScale::Seconds
+        );
+    }
+
+    #[test]
+    fn can_normalize_dates() {
+        let mut m = blank();
+        m.date = 674526582;
+        m.date_read = 674526590;
+
+        m.normalize_dates(TimestampScale::Seconds);
+        assert_eq!(m.date, 674526582000000000);
+        assert_eq!(m.date_read, 674526590000000000);
+        assert_eq!(m.date_delivered, 0);
+        assert_eq!(m.date(&get_offset()).unwrap().year(), 2022);
+    }
+
+    #[test]
+    fn can_get_association_type_label() {
+        let mut m = blank();
+        let cases = [
+            (None, "Normal"),
+            (Some(0), "Normal"),
+            (Some(3), "Normal"),
+            (Some(1000), "Sticker"),
+            (Some(2000), "Reaction: Loved (added)"),
+            (Some(2005), "Reaction: Questioned (added)"),
+            (Some(3001), "Reaction: Liked (removed)"),
+            (Some(2006), "Unknown (2006)"),
+            (Some(-1), "Unknown (-1)"),
+            (Some(4000), "Unknown (4000)"),
+        ];
+        for (code, label) in cases {
+            m.associated_message_type = code;
+            assert_eq!(m.association_type_label(), label);
+        }
+    }
+
+    #[test]
+    fn can_get_association_type_label_for_edited() {
+        let mut m = blank();
+        m.associated_message_type = Some(2002);
+        m.date_edited = 1;
+        assert_eq!(m.association_type_label(), "Reaction: Disliked (added)");
+    }
+
+    #[test]
+    fn can_get_recipient_kind() {
+        let fixture = TestDatabase::new();
+        let alice = fixture.add_handle("+15558675309");
+        let bob = fixture.add_handle("+15558675310");
+        let business = fixture.add_handle("urn:biz:00000000-0000-0000-0000-000000000000");
+        let direct = fixture.add_chat("+15558675309", "iMessage", &[alice]);
+        let group = fixture.add_chat("chat123456789", "iMessage", &[alice, bob]);
+        let business_chat = fixture.add_chat(
+            "urn:biz:00000000-0000-0000-0000-000000000000",
+            "iMessage",
+            &[business],
+        );
+
+        let cases = [
+            (Some(direct), alice, RecipientKind::Individual),
+            (Some(group), alice, RecipientKind::Group),
+            (Some(business_chat), business, RecipientKind::Business),
+            // Without a chat, the sender's handle is used
+            (None, business, RecipientKind::Business),
+            (None, bob, RecipientKind::Individual),
+        ];
+        let db = fixture.connection();
+        for (n, (chat, handle_id, expected)) in cases.into_iter().enumerate() {
+            let guid = format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}");
+            fixture.add_message(
+                chat,
+                &TestMessage {
+                    guid: &guid,
+                    text: Some("Hello"),
+                    handle_id,
+                    ..Default::default()
+                },
+            );
+            let message = Message::get_by_guid(db, &guid).unwrap().unwrap();
+            assert_eq!(message.recipient_kind(db).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn can_get_group_members_at_message_time() {
+        let fixture = TestDatabase::new();
+        let alice = fixture.add_handle("+15558675309");
+        let bob = fixture.add_handle("+15558675310");
+        let carol = fixture.add_handle("+15558675311");
+        let dave = fixture.add_handle("+15558675312");
+        // Bob and Dave are no longer in the group, Carol joined later
+        let chat = fixture.add_chat("chat123", "iMessage", &[alice, carol]);
+        let events = [
+            (1, 1, 0, 0, dave),
+            (5, 0, 0, alice, 0),
+            (10, 1, 1, 0, bob),
+            (11, 3, 0, dave, 0),
+            (12, 1, 0, 0, carol),
+        ];
+        for (date, item_type, group_action_type, handle_id, other_handle) in events {
+            fixture.add_message(
+                Some(chat),
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{date:012}"),
+                    date,
+                    item_type,
+                    group_action_type,
+                    handle_id,
+                    other_handle,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let db = fixture.connection();
+        let message = Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000005")
+            .unwrap()
+            .unwrap();
+        let members: Vec<i32> = message.group_members(db).unwrap().into_iter().collect();
+        assert_eq!(members, [alice, bob, dave]);
+    }
+
+    #[test]
+    fn can_get_group_members_normalized() {
+        let fixture = TestDatabase::new();
+        let alice = fixture.add_handle("+15558675309");
+        let bob = fixture.add_handle("+15558675310");
+        let chat = fixture.add_chat("chat123", "iMessage", &[alice]);
+        // Dates are stored in seconds, Bob was removed after the first message
+        for (n, date, item_type, group_action_type, other_handle) in
+            [(1, 674526582, 0, 0, 0), (2, 674526590, 1, 1, bob)]
+        {
+            fixture.add_message(
+                Some(chat),
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date,
+                    item_type,
+                    group_action_type,
+                    other_handle,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let db = fixture.connection();
+        let mut message = Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000001")
+            .unwrap()
+            .unwrap();
+        message.normalize_dates(Message::timestamp_scale(db).unwrap());
+        let members: Vec<i32> = message.group_members(db).unwrap().into_iter().collect();
+        assert_eq!(members, [alice, bob]);
+    }
+
+    #[test]
+    fn can_get_group_members_without_chat() {
+        let fixture = TestDatabase::new();
+        assert!(blank()
+            .group_members(fixture.connection())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn can_get_message_not_ephemeral() {
+        let mut m = blank();
+        assert!(!m.is_ephemeral());
+
+        m.item_type = 6;
+        m.text = None;
+        assert!(!m.is_ephemeral());
+    }
+
+    #[test]
+    fn can_find_undecodable_payloads() {
+        let fixture = TestDatabase::new();
+        let mut rowids = vec![];
+        for n in 1..=3 {
+            rowids.push(fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date: n,
+                    ..Default::default()
+                },
+            ));
+        }
+        let db = fixture.connection();
+
+        // A valid XML plist, a truncated binary plist, and no payload
+        let valid =
+            b"<?xml version=\"1.0\" encoding=\"UTF-8\"?><plist version=\"1.0\"><dict/></plist>";
+        let truncated = b"bplist00\xd1\x01\x02";
+        for (rowid, payload) in [(rowids[0], &valid[..]), (rowids[1], &truncated[..])] {
+            db.execute(
+                "UPDATE message SET payload_data = ?1 WHERE ROWID = ?2",
+                rusqlite::params![payload, rowid],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(Message::undecodable_payloads(db).unwrap(), [rowids[1]]);
+
+        let messages: Vec<Message> = (1..=3)
+            .map(|n| {
+                Message::get_by_guid(db, &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"))
+                    .unwrap()
+                    .unwrap()
+            })
+            .collect();
+        assert!(matches!(
+            messages[0].try_payload_data(db),
+            Ok(Some(PayloadData::Plist(_)))
+        ));
+        assert!(matches!(
+            messages[1].try_payload_data(db),
+            Err(PlistParseError::UndecodablePayload(_))
+        ));
+        assert!(matches!(messages[2].try_payload_data(db), Ok(None)));
+    }
+
+    #[test]
+    fn can_get_message_sos() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.SafetyMonitorApp.SafetyMonitorMessages".to_string());
+        assert!(m.is_sos());
+    }
+
+    #[test]
+    fn cant_get_message_sos() {
+        let mut m = blank();
+        assert!(!m.is_sos());
+
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_string());
+        assert!(!m.is_sos());
+    }
+
+    #[test]
+    fn can_get_effect_delivered() {
+        let mut m = blank();
+        m.expressive_send_style_id = Some("com.apple.MobileSMS.expressivesend.impact".to_string());
+        assert!(m.effect_delivered());
+    }
+
+    #[test]
+    fn cant_get_effect_delivered_sms() {
+        let mut m = blank();
+        m.expressive_send_style_id = Some("com.apple.MobileSMS.expressivesend.impact".to_string());
+        m.service = Some("SMS".to_string());
+        assert!(m.is_expressive());
+        assert!(!m.effect_delivered());
+    }
+
+    #[test]
+    fn cant_get_effect_delivered_no_effect() {
+        let m = blank();
+        assert!(!m.effect_delivered());
+    }
+
+    #[test]
+    fn can_get_attachments() {
+        let fixture = TestDatabase::new();
+        let with = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                text: Some("\u{FFFC}\u{FFFC}"),
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                text: Some("Hello"),
+                ..Default::default()
+            },
+        );
+        fixture.add_attachment(with, "~/Library/Messages/Attachments/b.png", "image/png");
+        fixture.add_attachment(
+            with,
+            "~/Library/Messages/Attachments/a.mov",
+            "video/quicktime",
+        );
+
+        let db = fixture.connection();
+        let message = Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000001")
+            .unwrap()
+            .unwrap();
+        let filenames: Vec<Option<String>> = message
+            .attachments(db)
+            .unwrap()
+            .into_iter()
+            .map(|attachment| attachment.filename)
+            .collect();
+        assert_eq!(
+            filenames,
+            [
+                Some("~/Library/Messages/Attachments/b.png".to_string()),
+                Some("~/Library/Messages/Attachments/a.mov".to_string()),
+            ]
+        );
+
+        let message = Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000002")
+            .unwrap()
+            .unwrap();
+        assert!(message.attachments(db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn can_get_with_attachments() {
+        let fixture = TestDatabase::new();
+        for n in 1..=4 {
+            let message = fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date: 10 - n,
+                    ..Default::default()
+                },
+            );
+            if n % 2 == 0 {
+                fixture.add_attachment(
+                    message,
+                    &format!("~/Library/Messages/Attachments/{n}.png"),
+                    "image/png",
+                );
+            }
+        }
+
+        let db = fixture.connection();
+        let mut statement = Message::get_with_attachments(db).unwrap();
+        let messages: Vec<Message> = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap())
+            .collect();
+
+        let dates: Vec<i64> = messages.iter().map(|message| message.date).collect();
+        assert_eq!(dates, [6, 8]);
+        assert!(messages.iter().all(|message| message.num_attachments == 1));
+    }
+
+    #[test]
+    fn can_get_parent_guid() {
+        let mut m = blank();
+        m.thread_originator_guid = Some("A44CE9D7-AAAA-BBBB-CCCC-000000000001".to_string());
+        assert_eq!(
+            m.parent_guid(),
+            Some("A44CE9D7-AAAA-BBBB-CCCC-000000000001")
+        );
+    }
+
+    #[test]
+    fn cant_get_parent_guid_not_reply() {
+        let m = blank();
+        assert_eq!(m.parent_guid(), None);
+    }
+
+    /// Insert a message with `text` and the `AttributedBodyTextOnly` body, which contains `Noter test`
+    fn divergent_fixture(text: Option<&str>) -> TestDatabase {
+        let fixture = TestDatabase::new();
+        let rowid = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                text,
+                ..Default::default()
+            },
+        );
+
+        let mut body = vec![];
+        File::open(
+            current_dir()
+                .unwrap()
+                .join("test_data/typedstream/AttributedBodyTextOnly"),
+        )
+        .unwrap()
+        .read_to_end(&mut body)
+        .unwrap();
+        fixture
+            .connection()
+            .execute(
+                "UPDATE message SET attributedBody = ?1 WHERE ROWID = ?2",
+                rusqlite::params![body, rowid],
+            )
+            .unwrap();
+        fixture
+    }
+
+    fn generate(fixture: &TestDatabase) -> Message {
+        let db = fixture.connection();
+        let mut message = Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000001")
+            .unwrap()
+            .unwrap();
+        message.generate_text(db).unwrap();
+        message
+    }
+
+    #[test]
+    fn can_prefer_longer_attributed_body() {
+        let fixture = divergent_fixture(Some("Noter"));
+        let message = generate(&fixture);
+
+        assert_eq!(message.text.as_deref(), Some("Noter test"));
+        assert!(message.components.is_some());
+        assert!(message.has_divergent_text(fixture.connection()));
+    }
+
+    #[test]
+    fn can_prefer_longer_text() {
+        let fixture = divergent_fixture(Some("Noter test, but longer"));
+        let message = generate(&fixture);
+
+        assert_eq!(message.text.as_deref(), Some("Noter test, but longer"));
+        assert!(message.components.is_none());
+        assert!(message.has_divergent_text(fixture.connection()));
+    }
+
+    #[test]
+    fn can_use_attributed_body_formatting() {
+        let fixture = divergent_fixture(Some("Noter test"));
+        let message = generate(&fixture);
+
+        assert_eq!(message.text.as_deref(), Some("Noter test"));
+        assert!(message.components.is_some());
+        assert!(!message.has_divergent_text(fixture.connection()));
+    }
+
+    #[test]
+    fn can_use_attributed_body_without_text() {
+        let fixture = divergent_fixture(None);
+        let message = generate(&fixture);
+
+        assert_eq!(message.text.as_deref(), Some("Noter test"));
+        assert!(!message.has_divergent_text(fixture.connection()));
+    }
+
+    #[test]
+    fn can_get_text_byte_len() {
+        let mut m = blank();
+        m.text = Some("Héllo".to_string());
+        assert_eq!(m.text_byte_len(), 6);
+        assert!(m.has_text());
+    }
+
+    #[test]
+    fn can_get_text_byte_len_empty() {
+        let mut m = blank();
+        m.text = Some(String::new());
+        assert_eq!(m.text_byte_len(), 0);
+        assert!(m.has_text());
+
+        m.text = None;
+        assert_eq!(m.text_byte_len(), 0);
+        assert!(!m.has_text());
+    }
+
+    #[test]
+    fn can_get_clean_text() {
+        let mut m = blank();
+        m.text = Some("\u{FFFC}Look\u{FFFD}!".to_string());
+        assert_eq!(m.clean_text().as_deref(), Some("Look!"));
+    }
+
+    #[test]
+    fn can_get_clean_text_with_placeholders() {
+        let mut m = blank();
+        m.text = Some("\u{FFFC}Look\u{FFFD}!".to_string());
+        let placeholders = Placeholders {
+            attachment: "[attachment]",
+            app: "[app]",
+        };
+        assert_eq!(
+            m.clean_text_with(&placeholders).as_deref(),
+            Some("[attachment]Look[app]!")
+        );
+    }
+
+    #[test]
+    fn cant_get_clean_text_missing() {
+        assert_eq!(blank().clean_text(), None);
+    }
+
+    #[test]
+    fn can_get_bare_url() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_string());
+        m.text = Some(" https://example.com/page\n".to_string());
+        assert!(m.is_bare_url());
+
+        m.text = None;
+        assert!(m.is_bare_url());
+    }
+
+    #[test]
+    fn cant_get_bare_url_with_text() {
+        let mut m = blank();
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_string());
+        m.text = Some("Look at this https://example.com/page".to_string());
+        assert!(!m.is_bare_url());
+    }
+
+    #[test]
+    fn cant_get_bare_url_not_url() {
+        let mut m = blank();
+        m.text = Some("https://example.com/page".to_string());
+        assert!(!m.is_bare_url());
+    }
+
+    #[test]
+    fn can_get_content_fingerprint_stable() {
+        let mut m = blank();
+        m.text = Some("Hello world".to_string());
+        m.date = 674526582885055488;
+
+        // Pinned so that changes to the hashed fields are caught, since exports compare fingerprints across runs
+        assert_eq!(m.content_fingerprint(), 0x827b9dc1c72f0bd6);
+    }
+
+    #[test]
+    fn can_get_content_fingerprint_changes() {
+        let mut m = blank();
+        m.text = Some("Hello world".to_string());
+        let original = m.content_fingerprint();
+
+        m.date_edited = 1;
+        assert_ne!(m.content_fingerprint(), original);
+
+        m.date_edited = 0;
+        m.text = Some("Hello world!".to_string());
+        assert_ne!(m.content_fingerprint(), original);
+
+        // Moving text between fields changes the fingerprint
+        m.text = None;
+        m.subject = Some("Hello world".to_string());
+        assert_ne!(m.content_fingerprint(), original);
+    }
+
+    #[test]
+    fn can_get_without_chat_message_join() {
+        let fixture = TestDatabase::new();
+        for n in 1..=3 {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date: n,
+                    ..Default::default()
+                },
+            );
+        }
+        let db = fixture.connection();
+        db.execute("DROP TABLE chat_message_join", []).unwrap();
+
+        let stream = |context: &QueryContext| -> Vec<Message> {
+            let mut statement = Message::stream_rows(db, context).unwrap();
+            statement
+                .query_map([], |row| Ok(Message::from_row(row)))
+                .unwrap()
+                .map(|message| Message::extract(message).unwrap())
+                .collect()
+        };
+
+        let mut context = QueryContext::default();
+        let messages = stream(&context);
+        assert_eq!(messages.len(), 3);
+        assert!(messages.iter().all(|message| message.chat_id.is_none()));
+
+        context.include_reactions = false;
+        context.start = Some(2);
+        assert_eq!(stream(&context).len(), 2);
+    }
+
+    #[test]
+    fn can_count_replies_without_chat_message_join() {
+        let fixture = TestDatabase::new();
+        let original = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: original,
+                text: Some("Hello"),
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                text: Some("World"),
+                thread_originator_guid: Some(original),
+                ..Default::default()
+            },
+        );
+        let db = fixture.connection();
+        db.execute("DROP TABLE chat_message_join", []).unwrap();
+
+        let message = Message::get_by_guid(db, original).unwrap().unwrap();
+        assert_eq!(message.num_replies, 1);
+        assert!(message.chat_id.is_none());
+    }
+
+    #[test]
+    fn cant_get_with_broken_chat_message_join() {
+        let fixture = TestDatabase::new();
+        let db = fixture.connection();
+        // The table exists, but without the column the join needs
+        db.execute_batch(
+            "DROP TABLE chat_message_join;
+             CREATE TABLE chat_message_join (chat_id INTEGER, msg_id INTEGER);",
+        )
+        .unwrap();
+
+        assert!(Message::get(db).is_err());
+        assert!(Message::stream_rows(db, &QueryContext::default()).is_err());
+        assert!(Message::get_by_guid(db, "A44CE9D7-AAAA-BBBB-CCCC-000000000001").is_err());
+    }
+
+    #[test]
+    fn can_get_recent() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        for n in 1..=5 {
+            fixture.add_message(
+                (n % 2 == 1).then_some(chat),
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date: n,
+                    ..Default::default()
+                },
+            );
+        }
+        let db = fixture.connection();
+
+        let dates = |messages: Vec<Message>| -> Vec<i64> {
+            messages.iter().map(|message| message.date).collect()
+        };
+        assert_eq!(dates(Message::get_recent(db, 3).unwrap()), [3, 4, 5]);
+        assert_eq!(dates(Message::get_recent(db, 10).unwrap()), [1, 2, 3, 4, 5]);
+        assert_eq!(
+            dates(Message::get_recent_for_chat(db, chat, 2).unwrap()),
+            [3, 5]
+        );
+        assert!(Message::get_recent(db, 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn can_get_is_note_to_self() {
+        let fixture = TestDatabase::new();
+        let me = fixture.add_handle("+15555550100");
+        let friend = fixture.add_handle("+15558675309");
+        let note = fixture.add_chat("+15555550100", "iMessage", &[me]);
+        let group = fixture.add_chat("chat0000", "iMessage", &[me, friend]);
+
+        let db = fixture.connection();
+        let message = |chat: Option<i32>, n: usize| {
+            let rowid = fixture.add_message(
+                chat,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    ..Default::default()
+                },
+            );
+            let mut message = blank();
+            message.rowid = rowid;
+            message.chat_id = chat;
+            message
+        };
+        let owner = ["+15555550100"];
+
+        assert!(message(Some(note), 1).is_note_to_self(db, &owner));
+        assert!(!message(Some(group), 2).is_note_to_self(db, &owner));
+        assert!(!message(Some(note), 3).is_note_to_self(db, &[]));
+        assert!(!message(None, 4).is_note_to_self(db, &owner));
+    }
+
+    #[test]
+    fn can_get_unhandled_bundle_ids() {
+        let fixture = TestDatabase::new();
+        let bundle_ids = [
+            Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.example.game"),
+            Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.example.game"),
+            Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.example.poll"),
+            Some("com.apple.messages.URLBalloonProvider"),
+            None,
+        ];
+        for (n, balloon_bundle_id) in bundle_ids.into_iter().enumerate() {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    balloon_bundle_id,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let counts = Message::unhandled_bundle_ids(fixture.connection()).unwrap();
+        assert_eq!(
+            counts,
+            HashMap::from([
+                ("com.example.game".to_string(), 2),
+                ("com.example.poll".to_string(), 1),
+            ])
         );
     }
 
     #[test]
-    fn can_get_time_date_read_before_date() {
-        // Get offset
-        let offset = get_offset();
+    fn can_get_reply_originator() {
+        let fixture = TestDatabase::new();
+        let sms = fixture.add_chat("+15558675309", "SMS", &[]);
+        let imessage = fixture.add_chat("+15558675309", "iMessage", &[]);
+        let originator = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+        fixture.add_message(
+            Some(sms),
+            &TestMessage {
+                guid: originator,
+                text: Some("Hello"),
+                ..Default::default()
+            },
+        );
+        let db = fixture.connection();
 
-        // Create message
-        let mut message = blank();
-        // May 17, 2022  9:30:31 PM
-        message.date = 674530231992568192;
-        // May 17, 2022  9:30:31 PM
-        message.date_delivered = 674530231992568192;
-        // May 17, 2022  8:29:42 PM
-        message.date_read = 674526582885055488;
+        let mut reply = blank();
+        reply.thread_originator_guid = Some(originator.to_string());
+        reply.chat_id = Some(imessage);
+        assert_eq!(
+            reply.reply_originator(db, true).unwrap().unwrap().guid,
+            originator
+        );
+        assert!(reply.reply_originator(db, false).unwrap().is_none());
 
-        assert_eq!(message.time_until_read(&offset), None);
+        reply.chat_id = Some(sms);
+        assert!(reply.reply_originator(db, false).unwrap().is_some());
+
+        reply.thread_originator_guid = Some("A44CE9D7-AAAA-BBBB-CCCC-000000000002".to_string());
+        assert!(reply.reply_originator(db, true).unwrap().is_none());
     }
 
     #[test]
-    fn can_get_message_expression_none() {
-        let m = blank();
-        assert_eq!(m.get_expressive(), expressives::Expressive::None);
+    fn cant_get_reply_originator_not_reply() {
+        let fixture = TestDatabase::new();
+        assert!(blank()
+            .reply_originator(fixture.connection(), true)
+            .unwrap()
+            .is_none());
     }
 
     #[test]
-    fn can_get_message_expression_bubble() {
+    fn can_get_is_system_message_name_change() {
         let mut m = blank();
-        m.expressive_send_style_id = Some("com.apple.MobileSMS.expressivesend.gentle".to_string());
-        assert_eq!(
-            m.get_expressive(),
-            expressives::Expressive::Bubble(expressives::BubbleEffect::Gentle)
-        );
+        m.group_title = Some("Group".to_string());
+        assert!(m.is_system_message());
     }
 
     #[test]
-    fn can_get_message_expression_screen() {
+    fn can_get_is_system_message_photo_change() {
         let mut m = blank();
-        m.expressive_send_style_id =
-            Some("com.apple.messages.effect.CKHappyBirthdayEffect".to_string());
-        assert_eq!(
-            m.get_expressive(),
-            expressives::Expressive::Screen(expressives::ScreenEffect::Balloons)
-        );
+        m.group_action_type = 1;
+        assert!(m.is_system_message());
     }
 
     #[test]
-    fn can_get_no_balloon_bundle_id() {
-        let m = blank();
-        assert_eq!(m.parse_balloon_bundle_id(), None);
+    fn can_get_is_system_message_unsent() {
+        let mut m = blank();
+        m.edited_parts = Some(EditedMessage {
+            parts: vec![EditedMessagePart {
+                status: EditStatus::Unsent,
+                edit_history: vec![],
+            }],
+        });
+        assert!(m.is_system_message());
     }
 
     #[test]
-    fn can_get_balloon_bundle_id_os() {
+    fn can_get_is_system_message_membership() {
         let mut m = blank();
-        m.balloon_bundle_id = Some("com.apple.Handwriting.HandwritingProvider".to_owned());
-        assert_eq!(
-            m.parse_balloon_bundle_id(),
-            Some("com.apple.Handwriting.HandwritingProvider")
-        );
+        m.item_type = 1;
+        assert!(m.is_system_message());
+        m.item_type = 3;
+        assert!(m.is_system_message());
     }
 
     #[test]
-    fn can_get_balloon_bundle_id_url() {
+    fn can_get_is_system_message_location() {
         let mut m = blank();
-        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_owned());
-        assert_eq!(
-            m.parse_balloon_bundle_id(),
-            Some("com.apple.messages.URLBalloonProvider")
-        );
+        m.item_type = 4;
+        assert!(m.is_system_message());
+        m.share_status = true;
+        assert!(m.is_system_message());
     }
 
     #[test]
-    fn can_get_balloon_bundle_id_apple() {
+    fn cant_get_is_system_message_normal() {
         let mut m = blank();
-        m.balloon_bundle_id = Some("com.apple.messages.MSMessageExtensionBalloonPlugin:0000000000:com.apple.PassbookUIService.PeerPaymentMessagesExtension".to_owned());
-        assert_eq!(
-            m.parse_balloon_bundle_id(),
-            Some("com.apple.PassbookUIService.PeerPaymentMessagesExtension")
-        );
+        m.text = Some("Hello".to_string());
+        assert!(!m.is_system_message());
+        m.item_type = 6;
+        assert!(!m.is_system_message());
     }
 
     #[test]
-    fn can_get_balloon_bundle_id_third_party() {
-        let mut m = blank();
-        m.balloon_bundle_id = Some("com.apple.messages.MSMessageExtensionBalloonPlugin:QPU8QS3E62:com.contextoptional.OpenTable.Messages".to_owned());
-        assert_eq!(
-            m.parse_balloon_bundle_id(),
-            Some("com.contextoptional.OpenTable.Messages")
+    fn can_get_since_known() {
+        let fixture = TestDatabase::new();
+        for n in 1..=3 {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    text: Some("Hello"),
+                    date: n,
+                    ..Default::default()
+                },
+            );
+        }
+        let db = fixture.connection();
+
+        let exported = Message::get_since(db, &HashMap::new()).unwrap();
+        assert_eq!(exported.len(), 3);
+        let known: HashMap<String, u64> = exported
+            .iter()
+            .map(|message| (message.guid.clone(), message.content_fingerprint()))
+            .collect();
+        assert!(Message::get_since(db, &known).unwrap().is_empty());
+
+        db.execute("UPDATE message SET text = 'Hi' WHERE date = 2", [])
+            .unwrap();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000004",
+                text: Some("Backfilled"),
+                date: 0,
+                ..Default::default()
+            },
         );
-        assert!(matches!(
-            m.variant(),
-            Variant::App(CustomBalloon::Application(
-                "com.contextoptional.OpenTable.Messages"
-            ))
-        ));
+        let changed: Vec<i64> = Message::get_since(db, &known)
+            .unwrap()
+            .iter()
+            .map(|message| message.date)
+            .collect();
+        assert_eq!(changed, [0, 2]);
     }
 
     #[test]
-    fn can_get_valid_guid() {
-        let mut m = blank();
-        m.associated_message_guid = Some("A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+    fn can_get_since_date() {
+        let fixture = TestDatabase::new();
+        for (n, (date, date_edited)) in [(1, 0), (2, 5), (3, 0), (4, 0)].into_iter().enumerate() {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date,
+                    date_edited,
+                    ..Default::default()
+                },
+            );
+        }
 
-        assert_eq!(
-            Some((0usize, "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A")),
-            m.clean_associated_guid()
-        );
+        let dates: Vec<i64> = Message::get_since_date(fixture.connection(), 2)
+            .unwrap()
+            .iter()
+            .map(|message| message.date)
+            .collect();
+        assert_eq!(dates, [2, 3, 4]);
     }
 
     #[test]
-    fn cant_get_invalid_guid() {
-        let mut m = blank();
-        m.associated_message_guid = Some("FAKE_GUID".to_string());
+    fn can_get_by_chat() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        let other_chat = fixture.add_chat("+15551234567", "iMessage", &[]);
+        for (n, chat_id, date) in [(1, chat, 3), (2, other_chat, 1), (3, chat, 2)] {
+            fixture.add_message(
+                Some(chat_id),
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+        fixture.add_attachment(1, "a.jpg", "image/jpeg");
+
+        let (mut statement, params) = Message::get_by_chat(fixture.connection(), chat).unwrap();
+        let messages: Vec<Message> = statement
+            .query_map(params, |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap())
+            .collect();
+        let dates: Vec<i64> = messages.iter().map(|message| message.date).collect();
+        assert_eq!(dates, [2, 3]);
+        assert!(messages.iter().all(|message| message.chat_id == Some(chat)));
+        assert_eq!(messages[1].num_attachments, 1);
+    }
 
-        assert_eq!(None, m.clean_associated_guid());
+    #[test]
+    fn can_get_page_across_same_date() {
+        let fixture = TestDatabase::new();
+        for (n, date) in [(1, 1), (2, 2), (3, 2), (4, 2), (5, 3)] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut rowids = vec![];
+        let mut cursor = None;
+        loop {
+            let (page, next) = Message::get_page(fixture.connection(), cursor.as_ref(), 2).unwrap();
+            rowids.extend(page.iter().map(|message| message.rowid));
+            match next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(rowids, [1, 2, 3, 4, 5]);
     }
 
     #[test]
-    fn can_get_valid_guid_p() {
-        let mut m = blank();
-        m.associated_message_guid = Some("p:1/A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+    fn can_get_page_offset() {
+        let fixture = TestDatabase::new();
+        for (n, date) in [(1, 1), (2, 3), (3, 2), (4, 2), (5, 4)] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let page = |limit, offset| -> Vec<i32> {
+            let (mut statement, params) =
+                Message::get_page_offset(fixture.connection(), limit, offset).unwrap();
+            statement
+                .query_map(params, |row| Ok(Message::from_row(row)))
+                .unwrap()
+                .map(|message| Message::extract(message).unwrap().rowid)
+                .collect()
+        };
+        assert_eq!(page(2, 0), [1, 3]);
+        assert_eq!(page(2, 2), [4, 2]);
+        assert_eq!(page(2, 4), [5]);
+        assert!(page(2, 6).is_empty());
+    }
+
+    #[test]
+    fn can_search() {
+        let fixture = TestDatabase::new();
+        for (n, text) in [
+            (1, Some("Dinner at 8?")),
+            (2, None),
+            (3, Some("Running late for dinner")),
+            (4, Some("50% off")),
+            (5, Some("500 off")),
+        ] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    text,
+                    date: n as i64,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let search = |term| -> Vec<i32> {
+            let (mut statement, params) = Message::search(fixture.connection(), term).unwrap();
+            statement
+                .query_map(params, |row| Ok(Message::from_row(row)))
+                .unwrap()
+                .map(|message| Message::extract(message).unwrap().rowid)
+                .collect()
+        };
+        assert_eq!(search("DINNER"), [1, 3]);
+        assert_eq!(search("0%"), [4]);
+        assert!(search("lunch").is_empty());
+        assert_eq!(search(""), [1, 3, 4, 5]);
+    }
+
+    #[test]
+    fn can_get_in_range() {
+        let fixture = TestDatabase::new();
+        let base = 674526582 * TIMESTAMP_FACTOR;
+        for n in 1..=4 {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date: base + n * TIMESTAMP_FACTOR,
+                    ..Default::default()
+                },
+            );
+        }
 
+        let offset = get_offset();
+        let start = get_local_time(&(base + 2 * TIMESTAMP_FACTOR), &offset).unwrap();
+        let end = get_local_time(&(base + 3 * TIMESTAMP_FACTOR), &offset).unwrap();
+        let (mut statement, params) =
+            Message::get_in_range(fixture.connection(), &start, &end).unwrap();
+        let dates: Vec<i64> = statement
+            .query_map(params, |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap().date)
+            .collect();
         assert_eq!(
-            Some((1usize, "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A")),
-            m.clean_associated_guid()
+            dates,
+            [base + 2 * TIMESTAMP_FACTOR, base + 3 * TIMESTAMP_FACTOR]
         );
     }
 
     #[test]
-    fn cant_get_invalid_guid_p() {
+    fn can_get_in_range_subsecond() {
+        let fixture = TestDatabase::new();
+        let base = 674526582 * TIMESTAMP_FACTOR;
+        for (n, date) in [(1, base), (2, base + 400_000_000), (3, base + 600_000_000)] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let offset = get_offset();
+        let start = get_local_time(&base, &offset).unwrap();
+        let end = start + Duration::milliseconds(500);
+        let (mut statement, params) =
+            Message::get_in_range(fixture.connection(), &start, &end).unwrap();
+        assert_eq!(params, [base, base + 500_000_000]);
+        let rowids: Vec<i32> = statement
+            .query_map(params, |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap().rowid)
+            .collect();
+        assert_eq!(rowids, [1, 2]);
+    }
+
+    #[test]
+    fn can_get_in_range_in_seconds() {
+        let fixture = TestDatabase::new();
+        for n in 1..=4 {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("A44CE9D7-AAAA-BBBB-CCCC-{n:012}"),
+                    date: 674526580 + n,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let offset = get_offset();
+        let start = get_local_time(&(674526582 * TIMESTAMP_FACTOR), &offset).unwrap();
+        let end = get_local_time(&(674526583 * TIMESTAMP_FACTOR), &offset).unwrap();
+        let (mut statement, params) =
+            Message::get_in_range(fixture.connection(), &start, &end).unwrap();
+        assert_eq!(params, [674526582, 674526583]);
+        let dates: Vec<i64> = statement
+            .query_map(params, |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap().date)
+            .collect();
+        assert_eq!(dates, [674526582, 674526583]);
+    }
+
+    #[test]
+    fn can_get_service_sms() {
         let mut m = blank();
-        m.associated_message_guid = Some("p:1/FAKE_GUID".to_string());
+        m.service = Some("SMS".to_string());
+        assert!(matches!(m.service(), Service::SMS));
+    }
 
-        assert_eq!(None, m.clean_associated_guid());
+    #[test]
+    fn can_get_service_mms() {
+        let mut m = blank();
+        m.service = Some("SMS".to_string());
+        m.num_attachments = 1;
+        assert!(matches!(m.service(), Service::MMS));
     }
 
     #[test]
-    fn can_get_valid_guid_bp() {
+    fn can_get_service_rcs() {
         let mut m = blank();
-        m.associated_message_guid = Some("bp:A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A".to_string());
+        m.service = Some("RCS".to_string());
+        m.num_attachments = 1;
+        assert!(matches!(m.service(), Service::RCS));
+    }
 
-        assert_eq!(
-            Some((0usize, "A44CE9D7-AAAA-BBBB-CCCC-23C54E1A9B6A")),
-            m.clean_associated_guid()
-        );
+    #[test]
+    fn can_get_service_imessage_attachment() {
+        let mut m = blank();
+        m.service = Some("iMessage".to_string());
+        m.num_attachments = 1;
+        assert!(matches!(m.service(), Service::iMessage));
     }
 
     #[test]
-    fn cant_get_invalid_guid_bp() {
+    fn can_get_body_ranges_legacy() {
         let mut m = blank();
-        m.associated_message_guid = Some("bp:FAKE_GUID".to_string());
+        m.text = Some("\u{FFFC}Hello\u{FFFD}".to_string());
 
-        assert_eq!(None, m.clean_associated_guid());
+        assert_eq!(m.body_ranges(), [Some(0..3), Some(3..8), Some(8..11)]);
     }
 
     #[test]
-    fn can_get_fully_unsent_true_single() {
+    fn can_get_body_ranges_multiple_attachments() {
         let mut m = blank();
-        m.edited_parts = Some(EditedMessage {
-            parts: vec![EditedMessagePart {
-                status: EditStatus::Unsent,
-                edit_history: vec![],
-            }],
-        });
+        m.text = Some("\u{FFFC}\u{FFFC}Hi".to_string());
 
-        assert!(m.is_fully_unsent());
+        assert_eq!(m.body_ranges(), [Some(0..3), Some(3..6), Some(6..8)]);
     }
 
     #[test]
-    fn can_get_fully_unsent_true_multiple() {
+    fn can_get_body_ranges_app() {
         let mut m = blank();
-        m.edited_parts = Some(EditedMessage {
-            parts: vec![
-                EditedMessagePart {
-                    status: EditStatus::Unsent,
-                    edit_history: vec![],
-                },
-                EditedMessagePart {
-                    status: EditStatus::Unsent,
-                    edit_history: vec![],
-                },
-            ],
-        });
+        m.balloon_bundle_id = Some("com.apple.messages.URLBalloonProvider".to_string());
+        m.text = Some("\u{FFFD}".to_string());
+        assert_eq!(m.body_ranges(), [Some(0..3)]);
 
-        assert!(m.is_fully_unsent());
+        m.text = None;
+        assert_eq!(m.body_ranges(), [None]);
     }
 
     #[test]
-    fn can_get_fully_unsent_false() {
+    fn can_get_body_ranges_retracted() {
         let mut m = blank();
         m.edited_parts = Some(EditedMessage {
             parts: vec![EditedMessagePart {
-                status: EditStatus::Original,
+                status: EditStatus::Unsent,
                 edit_history: vec![],
             }],
         });
+        m.components = Some(vec![]);
+        m.text = Some(String::new());
 
-        assert!(!m.is_fully_unsent());
+        assert_eq!(m.body_ranges(), [None]);
     }
 
     #[test]
-    fn can_get_fully_unsent_false_multiple() {
-        let mut m = blank();
-        m.edited_parts = Some(EditedMessage {
-            parts: vec![
-                EditedMessagePart {
-                    status: EditStatus::Unsent,
-                    edit_history: vec![],
-                },
-                EditedMessagePart {
-                    status: EditStatus::Original,
-                    edit_history: vec![],
-                },
-            ],
-        });
+    fn can_get_edit_history() {
+        let fixture = TestDatabase::new();
+        let edited = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                date_edited: 690513494000000000,
+                ..Default::default()
+            },
+        );
+        let unsent = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                date_edited: 690513494000000000,
+                ..Default::default()
+            },
+        );
+        let db = fixture.connection();
+        for (rowid, path) in [(edited, "Edited.plist"), (unsent, "Deleted.plist")] {
+            let mut bytes = vec![];
+            File::open(
+                current_dir()
+                    .unwrap()
+                    .join("test_data/edited_message")
+                    .join(path),
+            )
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+            db.execute(
+                "UPDATE message SET message_summary_info = ?1 WHERE ROWID = ?2",
+                rusqlite::params![bytes, rowid],
+            )
+            .unwrap();
+        }
 
-        assert!(!m.is_fully_unsent());
+        let mut message = blank();
+        message.rowid = edited;
+        message.date_edited = 690513494000000000;
+        assert_eq!(
+            message.edit_history(db).unwrap(),
+            ["First message  ", "Edit 1", "Edit 2", "Edited message"]
+        );
+
+        message.rowid = unsent;
+        assert!(message.edit_history(db).is_none());
     }
 
     #[test]
-    fn can_get_part_edited_true() {
-        let mut m = blank();
-        m.edited_parts = Some(EditedMessage {
-            parts: vec![
-                EditedMessagePart {
-                    status: EditStatus::Edited,
-                    edit_history: vec![],
-                },
-                EditedMessagePart {
-                    status: EditStatus::Original,
-                    edit_history: vec![],
-                },
-            ],
-        });
-
-        assert!(m.is_part_edited(0));
+    fn cant_get_edit_history_not_edited() {
+        let fixture = TestDatabase::new();
+        assert!(blank().edit_history(fixture.connection()).is_none());
     }
 
     #[test]
-    fn can_get_part_edited_false() {
+    fn can_get_reply_index() {
         let mut m = blank();
-        m.edited_parts = Some(EditedMessage {
-            parts: vec![
-                EditedMessagePart {
-                    status: EditStatus::Edited,
-                    edit_history: vec![],
-                },
-                EditedMessagePart {
-                    status: EditStatus::Original,
-                    edit_history: vec![],
-                },
-            ],
-        });
-
-        assert!(!m.is_part_edited(1));
+        m.thread_originator_part = Some("2:0:5".to_string());
+        assert_eq!(m.get_reply_index(), 2);
     }
 
     #[test]
-    fn can_get_part_edited_blank() {
-        let m = blank();
+    fn cant_get_reply_index_non_numeric() {
+        let mut m = blank();
+        m.thread_originator_part = Some("abc:1:2".to_string());
+        assert_eq!(m.get_reply_index(), 0);
+    }
 
-        assert!(!m.is_part_edited(0));
+    #[test]
+    fn cant_get_reply_index_missing() {
+        let mut m = blank();
+        assert_eq!(m.get_reply_index(), 0);
+        m.thread_originator_part = Some(String::new());
+        assert_eq!(m.get_reply_index(), 0);
     }
 
     #[test]
-    fn can_get_fully_unsent_none() {
-        let m = blank();
+    fn can_get_reactions_with_quoted_guid() {
+        let fixture = TestDatabase::new();
+        let target = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+        let reaction = "A44CE9D7-AAAA-BBBB-CCCC-\"00000000002";
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: reaction,
+                associated_message_guid: Some(&format!("p:0/{target}")),
+                associated_message_type: 2000,
+                ..Default::default()
+            },
+        );
 
-        assert!(!m.is_fully_unsent());
+        let mut original = blank();
+        original.guid = target.to_string();
+        let lookup = HashMap::from([(target.to_string(), vec![reaction.to_string()])]);
+        let reactions = original
+            .get_reactions(fixture.connection(), &lookup)
+            .unwrap();
+        assert_eq!(reactions[&0][0].guid, reaction);
+
+        original.guid = "A44CE9D7-AAAA-BBBB-CCCC-000000000009".to_string();
+        assert!(original
+            .get_reactions(fixture.connection(), &lookup)
+            .unwrap()
+            .is_empty());
     }
 }
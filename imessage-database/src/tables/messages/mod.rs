@@ -5,5 +5,8 @@
 pub use message::Message;
 
 pub(crate) mod body;
+pub mod merge;
 pub mod message;
 pub mod models;
+pub mod render;
+pub mod stream;
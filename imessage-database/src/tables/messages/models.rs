@@ -2,7 +2,15 @@
  This module contains Data structures and models that represent message data.
 */
 
-use crate::message_types::text_effects::TextEffect;
+use std::io::Cursor;
+
+use chrono::{DateTime, Duration, Local};
+use plist::Value;
+
+use crate::{
+    error::plist::PlistParseError, message_types::text_effects::TextEffect,
+    tables::attachment::Attachment, util::typedstream::parser::TypedStreamReader,
+};
 
 /// Defines the parts of a message bubble, i.e. the content that can exist in a single message.
 ///
@@ -12,11 +20,14 @@ use crate::message_types::text_effects::TextEffect;
 ///
 /// iMessage bubbles can only contain data of one variant of this enum at a time.
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BubbleComponent<'a> {
     /// A text message with associated formatting, generally representing ranges present in a `NSAttributedString`
     Text(Vec<TextAttributes<'a>>),
-    /// An attachment
-    Attachment,
+    /// An attachment, with its zero-based position among the attachments of the message
+    ///
+    /// The position matches the index of the attachment in [`Message::attachments()`](crate::tables::messages::Message::attachments).
+    Attachment(usize),
     /// An [app integration](crate::message_types::app)
     App,
     /// A component that was retracted, found by parsing the [`EditedMessage`](crate::message_types::edited::EditedMessage)
@@ -25,18 +36,177 @@ pub enum BubbleComponent<'a> {
 
 /// Defines different types of services we can receive messages from.
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Service<'a> {
     /// An iMessage
     #[allow(non_camel_case_types)]
     iMessage,
-    /// A message sent as SMS
+    /// A text-only message sent over the carrier network
     SMS,
+    /// A message with media sent over the carrier network
+    MMS,
+    /// A message sent with [Rich Communication Services](https://support.apple.com/en-us/104972), available since iOS 18 and macOS Sequoia
+    RCS,
     /// Any other type of message
     Other(&'a str),
     /// Used when service field is not set
     Unknown,
 }
 
+impl<'a> Service<'a> {
+    /// Get the service that corresponds to a `service` or `service_name` column value
+    pub fn from_name(name: Option<&'a str>) -> Self {
+        match name {
+            Some("iMessage") => Service::iMessage,
+            Some("SMS") => Service::SMS,
+            Some("MMS") => Service::MMS,
+            Some("RCS") => Service::RCS,
+            Some(service_name) => Service::Other(service_name),
+            None => Service::Unknown,
+        }
+    }
+
+    /// The hex color of a sent message bubble for this service, i.e. blue for iMessage and green for SMS
+    ///
+    /// Services without an established color use gray.
+    pub fn theme_color(&self) -> &'static str {
+        match self {
+            Service::iMessage => "#1982FC",
+            Service::SMS | Service::MMS | Service::RCS => "#65C466",
+            Service::Other(_) | Service::Unknown => "#8E8E93",
+        }
+    }
+
+    /// `true` if the message was sent with iMessage, else `false`
+    pub fn is_imessage(&self) -> bool {
+        matches!(self, Service::iMessage)
+    }
+}
+
+/// Defines the possible contents of a message's `payload_data` BLOB.
+///
+/// Most payloads are binary or XML plists, but some are raw [`typedstream`](crate::util::typedstream) data
+/// that must be routed to the [`TypedStreamReader`] instead.
+#[derive(Debug)]
+pub enum PayloadData {
+    /// A binary or XML plist
+    Plist(Value),
+    /// Raw [`typedstream`](crate::util::typedstream) data
+    TypedStream(Vec<u8>),
+    /// Data that is neither a plist nor [`typedstream`](crate::util::typedstream) data
+    Unknown(Vec<u8>),
+}
+
+impl PayloadData {
+    /// Classify the raw bytes of a `payload_data` BLOB, returning `None` if there is no data
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<Self> {
+        if bytes.is_empty() {
+            return None;
+        }
+
+        if let Ok(value) = Value::from_reader(Cursor::new(&bytes)) {
+            return Some(PayloadData::Plist(value));
+        }
+
+        if TypedStreamReader::from(&bytes).validate_header().is_ok() {
+            return Some(PayloadData::TypedStream(bytes));
+        }
+
+        Some(PayloadData::Unknown(bytes))
+    }
+
+    /// Decode the raw bytes of a `payload_data` BLOB, returning `None` if there is no data
+    ///
+    /// Unlike [`Self::from_bytes()`], data that is neither a plist nor [`typedstream`](crate::util::typedstream)
+    /// data is an error instead of [`PayloadData::Unknown`], so the reason the plist failed to decode can be reported.
+    pub fn try_from_bytes(bytes: Vec<u8>) -> Result<Option<Self>, PlistParseError> {
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+
+        match Value::from_reader(Cursor::new(&bytes)) {
+            Ok(value) => Ok(Some(PayloadData::Plist(value))),
+            Err(_) if TypedStreamReader::from(&bytes).validate_header().is_ok() => {
+                Ok(Some(PayloadData::TypedStream(bytes)))
+            }
+            Err(why) => Err(PlistParseError::UndecodablePayload(why)),
+        }
+    }
+}
+
+/// Prefix used by the chat and handle identifiers of [Apple Messages for Business](https://support.apple.com/en-us/HT208888) conversations
+pub const BUSINESS_ID_PREFIX: &str = "urn:biz:";
+
+/// Defines who a message was addressed to.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecipientKind {
+    /// A conversation with a single other participant
+    Individual,
+    /// A conversation with more than one other participant
+    Group,
+    /// A conversation with a business via Apple Messages for Business
+    Business,
+}
+
+impl RecipientKind {
+    /// Classify a conversation given its identifier and the number of participants that are not the database owner
+    pub fn from_chat(chat_identifier: &str, num_participants: usize) -> Self {
+        if chat_identifier.starts_with(BUSINESS_ID_PREFIX) {
+            RecipientKind::Business
+        } else if num_participants > 1 {
+            RecipientKind::Group
+        } else {
+            RecipientKind::Individual
+        }
+    }
+}
+
+/// Replacements for the placeholder characters in message text, used by [`Message::clean_text_with()`](crate::tables::messages::Message::clean_text_with)
+///
+/// Message text marks the position of each attachment with `U+FFFC` and each app message with `U+FFFD`.
+/// The default replaces both with an empty string, removing them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Placeholders<'a> {
+    /// Replaces `U+FFFC`, the attachment placeholder
+    pub attachment: &'a str,
+    /// Replaces `U+FFFD`, the app message placeholder
+    pub app: &'a str,
+}
+
+/// How long it took for a message to be read, found by [`Message::read_info()`](crate::tables::messages::Message::read_info)
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReadInfo {
+    /// The database owner sent the message, and the recipient read it after this long
+    SentReadAfter(Duration),
+    /// The database owner received the message, and read it after this long
+    ReceivedReadAfter(Duration),
+    /// The message has no usable read data
+    Unknown,
+}
+
+/// A position in the `message` table used for keyset pagination with [`Message::get_page()`](crate::tables::messages::Message::get_page).
+///
+/// Messages are ordered by `date`, then `ROWID`, so a cursor is stable even when several messages share a timestamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageCursor {
+    /// The `date` of the last message on the previous page
+    pub date: i64,
+    /// The `ROWID` of the last message on the previous page
+    pub rowid: i32,
+}
+
+/// The image displayed in a link preview, found by [`Message::preview_image()`](crate::tables::messages::Message::preview_image)
+#[derive(Debug)]
+pub enum PreviewImage {
+    /// The image was saved as an attachment of the message, so it exists on disk
+    Attachment(Attachment),
+    /// The image is only referenced by the URL stored in the message's `payload_data`
+    URL(String),
+}
+
+/// The dates of the first and last messages in a chat, found by [`Message::chat_date_span()`](crate::tables::messages::Message::chat_date_span)
+pub type DateSpan = (DateTime<Local>, DateTime<Local>);
+
 /// Defines ranges of text and associated attributes parsed from [`typedstream`](crate::util::typedstream) `attributedBody` data.
 ///
 /// Ranges specify locations attributes applied to specific portions of a [`Message`](crate::tables::messages::Message)'s [`text`](crate::tables::messages::Message::text). For example, given message text with a [`Mention`](TextEffect::Mention) like:
@@ -58,6 +228,7 @@ pub enum Service<'a> {
 /// ])];
 /// ```
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct TextAttributes<'a> {
     /// The start index of the affected range of message text
     pub start: usize,
@@ -72,3 +243,103 @@ impl<'a> TextAttributes<'a> {
         Self { start, end, effect }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env::current_dir, fs::File, io::Read};
+
+    use crate::tables::messages::models::{PayloadData, RecipientKind, Service};
+
+    fn read_test_file(path: &str) -> Vec<u8> {
+        let path = current_dir().unwrap().as_path().join(path);
+        let mut file = File::open(path).unwrap();
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn can_get_payload_plist() {
+        let bytes = read_test_file("test_data/app_message/Game.plist");
+        assert!(matches!(
+            PayloadData::from_bytes(bytes),
+            Some(PayloadData::Plist(_))
+        ));
+    }
+
+    #[test]
+    fn can_get_payload_typedstream() {
+        let bytes = read_test_file("test_data/typedstream/AttributedBodyTextOnly");
+        assert!(matches!(
+            PayloadData::from_bytes(bytes),
+            Some(PayloadData::TypedStream(_))
+        ));
+    }
+
+    #[test]
+    fn can_get_payload_unknown() {
+        let bytes = vec![0, 1, 2, 3];
+        assert!(matches!(
+            PayloadData::from_bytes(bytes),
+            Some(PayloadData::Unknown(_))
+        ));
+    }
+
+    #[test]
+    fn can_get_payload_empty() {
+        assert!(PayloadData::from_bytes(vec![]).is_none());
+    }
+
+    #[test]
+    fn can_get_recipient_individual() {
+        assert_eq!(
+            RecipientKind::from_chat("+15558675309", 1),
+            RecipientKind::Individual
+        );
+    }
+
+    #[test]
+    fn can_get_recipient_group() {
+        assert_eq!(
+            RecipientKind::from_chat("chat123456789", 3),
+            RecipientKind::Group
+        );
+    }
+
+    #[test]
+    fn can_get_recipient_business() {
+        assert_eq!(
+            RecipientKind::from_chat("urn:biz:00000000-0000-0000-0000-000000000000", 1),
+            RecipientKind::Business
+        );
+    }
+
+    #[test]
+    fn can_get_service_theme_color() {
+        assert_eq!(Service::iMessage.theme_color(), "#1982FC");
+        assert_eq!(Service::SMS.theme_color(), "#65C466");
+        assert_eq!(Service::MMS.theme_color(), "#65C466");
+        assert_eq!(Service::Other("Jabber").theme_color(), "#8E8E93");
+        assert_eq!(Service::Unknown.theme_color(), "#8E8E93");
+    }
+
+    #[test]
+    fn can_get_service_is_imessage() {
+        assert!(Service::iMessage.is_imessage());
+        assert!(!Service::SMS.is_imessage());
+        assert!(!Service::Other("iMessage Lite").is_imessage());
+        assert!(!Service::Unknown.is_imessage());
+    }
+
+    #[test]
+    fn can_get_service_from_name() {
+        assert!(matches!(Service::from_name(Some("SMS")), Service::SMS));
+        assert!(matches!(Service::from_name(Some("MMS")), Service::MMS));
+        assert!(matches!(Service::from_name(Some("RCS")), Service::RCS));
+        assert!(matches!(
+            Service::from_name(Some("iMessage Lite")),
+            Service::Other("iMessage Lite")
+        ));
+        assert!(matches!(Service::from_name(None), Service::Unknown));
+    }
+}
@@ -0,0 +1,703 @@
+/*!
+ Render a single message as HTML or as a [`RenderedMessage`].
+
+ Exporters with their own layout can build markup from [`Message::body()`], but this module provides a
+ self-contained rendering of one message bubble that is consistent and testable. [`Message::render()`] resolves
+ the same data into plain values, so exporters for other formats do not need to reach into the message's internals.
+*/
+
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::{
+    message_types::variants::{Reaction, Variant},
+    tables::{
+        attachment::{Attachment, AttachmentResolver, MediaType},
+        messages::{models::BubbleComponent, Message},
+        table::{ME, UNKNOWN},
+    },
+};
+
+/// The data used to render a message with [`Message::render_html()`] or [`Message::render()`]
+pub struct RenderContext<'a> {
+    /// The attachments of the message being rendered, in the order returned by [`Message::attachments()`]
+    pub attachments: &'a [Attachment],
+    /// Resolves the path used to display each attachment; when `None`, or when the resolver returns `None`,
+    /// the attachment's [`filename()`](Attachment::filename) is used
+    pub resolver: Option<&'a dyn AttachmentResolver>,
+    /// Tapbacks on the message being rendered, keyed by body component index, as cached by
+    /// [`Message::cache()`](crate::tables::table::Cacheable::cache)
+    pub reactions: Option<&'a HashMap<usize, Vec<Message>>>,
+    /// Map of `handle_id` to the name to display for that handle
+    pub names: &'a HashMap<i32, String>,
+    /// The name to display for messages sent by the database owner, defaults to [`ME`]
+    pub me: Option<&'a str>,
+    /// The [`strftime`](chrono::format::strftime) format used to render the message date
+    pub date_format: &'a str,
+    /// The date offset from [`get_offset()`](crate::util::dates::get_offset)
+    pub offset: i64,
+}
+
+impl RenderContext<'_> {
+    /// Get the name to display for the sender of a message
+    fn sender(&self, message: &Message) -> &str {
+        if message.is_from_me() {
+            return self.me.unwrap_or(ME);
+        }
+        message
+            .handle_id
+            .and_then(|handle_id| self.names.get(&handle_id))
+            .map_or(UNKNOWN, String::as_str)
+    }
+}
+
+/// Everything an exporter needs to display a message, created by [`Message::render()`]
+///
+/// With the `serde` feature enabled, this serializes to a stable JSON schema: the field names of this struct and
+/// the types it contains are part of the crate's public API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RenderedMessage {
+    /// The globally unique ID of the message
+    pub guid: String,
+    /// The date the message was sent, formatted with [`RenderContext::date_format`]
+    pub date: String,
+    /// The name to display for the sender
+    pub sender: String,
+    /// `true` if the database owner sent the message
+    pub is_from_me: bool,
+    /// How far the message got on its way to the recipient
+    pub status: DeliveryStatus,
+    /// The subject line, rendered before the parts
+    pub subject: Option<String>,
+    /// One entry for each component of the message's [`body()`](Message::body)
+    pub parts: Vec<RenderedPart>,
+    /// The GUID of the message that started the thread this message replies to
+    pub reply_to: Option<String>,
+    /// The number of replies to this message
+    pub num_replies: i32,
+}
+
+/// The delivery state of a message, as shown below the last sent message in Messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum DeliveryStatus {
+    /// The database owner sent the message, but it has not been delivered
+    Sent,
+    /// The message was delivered to the recipient
+    Delivered,
+    /// The recipient read the message
+    Read,
+    /// The database owner received the message
+    Received,
+}
+
+/// A single component of a [`RenderedMessage`] with the tapbacks placed on it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RenderedPart {
+    /// The content of the component
+    pub content: RenderedContent,
+    /// The tapbacks placed on the component, in the order they were sent
+    pub reactions: Vec<RenderedReaction>,
+}
+
+/// The content of a [`RenderedPart`], corresponding to a [`BubbleComponent`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize),
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub enum RenderedContent {
+    /// A text bubble
+    Text {
+        /// The text of the bubble, joined from its attribute ranges
+        text: String,
+    },
+    /// A reference to an attachment
+    Attachment {
+        /// The path used to display the attachment, resolved like [`Message::render_html()`] does
+        path: String,
+        /// The name of the file when it was sent or received
+        filename: String,
+        /// The MIME type of the attachment, if known
+        mime_type: Option<String>,
+    },
+    /// An attachment bubble whose attachment is missing from [`RenderContext::attachments`]
+    MissingAttachment,
+    /// An app message, identified by its balloon bundle ID
+    App {
+        /// The balloon bundle ID of the app that sent the message, if known
+        bundle_id: Option<String>,
+    },
+    /// A component that was unsent
+    Retracted,
+}
+
+/// A tapback placed on a [`RenderedPart`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RenderedReaction {
+    /// The kind of tapback, i.e. `Loved`
+    pub kind: String,
+    /// The name to display for the handle that placed the tapback
+    pub sender: String,
+}
+
+impl Message {
+    /// Resolve the message into a [`RenderedMessage`] that contains the same data as [`Self::render_html()`]
+    ///
+    /// Text is not escaped, since the output is not specific to HTML. The text must be populated with
+    /// [`Self::generate_text()`] first.
+    pub fn render(&self, ctx: &RenderContext) -> RenderedMessage {
+        let status = if !self.is_from_me() {
+            DeliveryStatus::Received
+        } else if self.date_read != 0 {
+            DeliveryStatus::Read
+        } else if self.date_delivered != 0 {
+            DeliveryStatus::Delivered
+        } else {
+            DeliveryStatus::Sent
+        };
+
+        let parts = self
+            .body()
+            .iter()
+            .enumerate()
+            .map(|(idx, component)| {
+                let content = match component {
+                    BubbleComponent::Text(_) => RenderedContent::Text {
+                        text: self.component_text(component),
+                    },
+                    BubbleComponent::Attachment(position) => match ctx.attachments.get(*position) {
+                        Some(attachment) => RenderedContent::Attachment {
+                            path: attachment_path(ctx, attachment),
+                            filename: attachment.filename().to_string(),
+                            mime_type: attachment.mime_type.clone(),
+                        },
+                        None => RenderedContent::MissingAttachment,
+                    },
+                    BubbleComponent::App => RenderedContent::App {
+                        bundle_id: self.balloon_bundle_id.clone(),
+                    },
+                    BubbleComponent::Retracted => RenderedContent::Retracted,
+                };
+                RenderedPart {
+                    content,
+                    reactions: reactions(ctx, idx)
+                        .map(|(kind, sender)| RenderedReaction {
+                            kind: format!("{kind:?}"),
+                            sender: sender.to_string(),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        RenderedMessage {
+            guid: self.guid.clone(),
+            date: self
+                .date(&ctx.offset)
+                .map(|date| date.format(ctx.date_format).to_string())
+                .unwrap_or_default(),
+            sender: ctx.sender(self).to_string(),
+            is_from_me: self.is_from_me(),
+            status,
+            subject: self.subject().map(str::to_string),
+            parts,
+            reply_to: self.parent_guid().map(str::to_string),
+            num_replies: self.num_replies,
+        }
+    }
+
+    /// Get the text of a [`BubbleComponent::Text`], joining its attribute ranges
+    fn component_text(&self, component: &BubbleComponent) -> String {
+        let text = self.text.as_deref().unwrap_or_default();
+        let BubbleComponent::Text(attrs) = component else {
+            return String::new();
+        };
+        let joined: String = attrs
+            .iter()
+            .filter_map(|attr| text.get(attr.start..attr.end))
+            .collect();
+        if joined.is_empty() {
+            text.to_string()
+        } else {
+            joined
+        }
+    }
+
+    /// Render the message as a `<div class="message">` element
+    ///
+    /// The markup contains the date, the sender, one `message_part` for each component of the
+    /// [`body()`](Self::body), and the tapbacks placed on each component. Message text is escaped, so
+    /// content like `<script>` is displayed instead of interpreted. The text must be populated with
+    /// [`Self::generate_text()`] first.
+    pub fn render_html(&self, ctx: &RenderContext) -> String {
+        let mut out = String::from("<div class=\"message\">");
+        out.push_str(if self.is_from_me() {
+            "<div class=\"sent\">"
+        } else {
+            "<div class=\"received\">"
+        });
+
+        // Header
+        let date = self
+            .date(&ctx.offset)
+            .map(|date| date.format(ctx.date_format).to_string())
+            .unwrap_or_default();
+        out.push_str(&format!(
+            "<p><span class=\"timestamp\">{}</span><span class=\"sender\">{}</span></p>",
+            html_escape(&date),
+            html_escape(ctx.sender(self))
+        ));
+        if let Some(subject) = self.subject() {
+            out.push_str(&format!(
+                "<p>Subject: <span class=\"subject\">{}</span></p>",
+                html_escape(subject)
+            ));
+        }
+
+        // Body
+        for (idx, component) in self.body().iter().enumerate() {
+            out.push_str("<div class=\"message_part\">");
+            match component {
+                BubbleComponent::Text(_) => out.push_str(&format!(
+                    "<span class=\"bubble\">{}</span>",
+                    html_escape(&self.component_text(component))
+                )),
+                BubbleComponent::Attachment(position) => match ctx.attachments.get(*position) {
+                    Some(attachment) => out.push_str(&render_attachment(ctx, attachment)),
+                    None => out
+                        .push_str("<span class=\"attachment_error\">Attachment not found!</span>"),
+                },
+                BubbleComponent::App => out.push_str(&format!(
+                    "<div class=\"app\">{}</div>",
+                    html_escape(self.balloon_bundle_id.as_deref().unwrap_or_default())
+                )),
+                BubbleComponent::Retracted => {
+                    out.push_str("<span class=\"unsent\">This message was unsent!</span>");
+                }
+            }
+            out.push_str(&render_reactions(ctx, idx));
+            out.push_str("</div>");
+        }
+
+        out.push_str("</div></div>");
+        out
+    }
+}
+
+/// Get the kind and sender of each tapback that is placed on the body component at `idx`
+fn reactions<'a>(
+    ctx: &'a RenderContext,
+    idx: usize,
+) -> impl Iterator<Item = (Reaction, &'a str)> + 'a {
+    ctx.reactions
+        .and_then(|reactions| reactions.get(&idx))
+        .into_iter()
+        .flatten()
+        .filter_map(|reaction| match reaction.variant() {
+            Variant::Reaction(_, true, kind) => Some((kind, ctx.sender(reaction))),
+            _ => None,
+        })
+}
+
+/// Render the tapbacks placed on the body component at `idx`, or an empty string if there are none
+fn render_reactions(ctx: &RenderContext, idx: usize) -> String {
+    let reactions: String = reactions(ctx, idx)
+        .map(|(kind, sender)| {
+            format!(
+                "<div class=\"reaction\">{kind:?} by {}</div>",
+                html_escape(sender)
+            )
+        })
+        .collect();
+
+    if reactions.is_empty() {
+        return reactions;
+    }
+    format!("<div class=\"reactions\">{reactions}</div>")
+}
+
+/// Get the path used to display an attachment
+///
+/// This is the only place the [`AttachmentResolver`] is called, so attachments are only resolved when they are displayed.
+fn attachment_path(ctx: &RenderContext, attachment: &Attachment) -> String {
+    match &attachment.copied_path {
+        Some(path) => path.display().to_string(),
+        None => ctx
+            .resolver
+            .and_then(|resolver| resolver.resolve(attachment))
+            .map_or_else(
+                || attachment.filename().to_string(),
+                |path| path.display().to_string(),
+            ),
+    }
+}
+
+/// Render an attachment as the element that displays its media type
+fn render_attachment(ctx: &RenderContext, attachment: &Attachment) -> String {
+    let path = html_escape(&attachment_path(ctx, attachment));
+    match attachment.mime_type() {
+        MediaType::Image(_) => format!("<img src=\"{path}\">"),
+        MediaType::Video(_) => format!("<video controls src=\"{path}\"></video>"),
+        MediaType::Audio(_) => format!("<audio controls src=\"{path}\"></audio>"),
+        _ => format!(
+            "<a href=\"{path}\">{}</a>",
+            html_escape(attachment.filename())
+        ),
+    }
+}
+
+/// Escape the characters that have special meaning in HTML
+///
+/// Message text can contain markup pasted by the sender, so it must be escaped before it is
+/// written to an HTML document.
+///
+/// # Example:
+///
+/// ```
+/// use imessage_database::tables::messages::render::html_escape;
+///
+/// assert_eq!(html_escape("Tom & Jerry"), "Tom &amp; Jerry");
+/// ```
+pub fn html_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Shorten `text` to at most `max_chars` characters, followed by a marker with the number of characters removed
+///
+/// The text is cut on a character boundary, so multi-byte characters are never split. If the text is
+/// already short enough, it is borrowed unchanged.
+///
+/// # Example:
+///
+/// ```
+/// use imessage_database::tables::messages::render::truncate_text;
+///
+/// assert_eq!(truncate_text("hello world", 5), "hello [truncated 6 chars]");
+/// ```
+pub fn truncate_text(text: &str, max_chars: usize) -> Cow<'_, str> {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => {
+            let removed = text[end..].chars().count();
+            Cow::Owned(format!("{} [truncated {removed} chars]", &text[..end]))
+        }
+        None => Cow::Borrowed(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, collections::HashMap, path::PathBuf};
+
+    use crate::tables::{
+        attachment::{Attachment, AttachmentResolver},
+        messages::{
+            render::{
+                html_escape, truncate_text, DeliveryStatus, RenderContext, RenderedContent,
+                RenderedMessage, RenderedPart, RenderedReaction,
+            },
+            Message,
+        },
+        table::{Cacheable, Table},
+        test_utils::{TestDatabase, TestMessage},
+    };
+
+    const TARGET: &str = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+
+    /// Resolves every attachment into an export directory
+    struct ExportResolver;
+
+    impl AttachmentResolver for ExportResolver {
+        fn resolve(&self, attachment: &Attachment) -> Option<PathBuf> {
+            Some(PathBuf::from("/export").join(attachment.filename()))
+        }
+    }
+
+    fn render(fixture: &TestDatabase) -> String {
+        render_with(fixture, None)
+    }
+
+    fn render_with(fixture: &TestDatabase, resolver: Option<&dyn AttachmentResolver>) -> String {
+        with_context(fixture, resolver, Message::render_html)
+    }
+
+    fn render_message(fixture: &TestDatabase) -> RenderedMessage {
+        with_context(fixture, None, Message::render)
+    }
+
+    fn with_context<T>(
+        fixture: &TestDatabase,
+        resolver: Option<&dyn AttachmentResolver>,
+        render: impl FnOnce(&Message, &RenderContext) -> T,
+    ) -> T {
+        let db = fixture.connection();
+        let mut statement = Message::get(db).unwrap();
+        let mut message = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap())
+            .find(|message| message.guid == TARGET)
+            .unwrap();
+        message.generate_text(db).unwrap();
+
+        let attachments = message.attachments(db).unwrap();
+        let reactions = Message::cache(db).unwrap();
+        let names = HashMap::from([(1, "Alice".to_string())]);
+        let ctx = RenderContext {
+            attachments: &attachments,
+            resolver,
+            reactions: reactions.get(TARGET),
+            names: &names,
+            me: None,
+            date_format: "%Y",
+            offset: 0,
+        };
+        render(&message, &ctx)
+    }
+
+    #[test]
+    fn can_render_text() {
+        let fixture = TestDatabase::new();
+        let alice = fixture.add_handle("+15558675309");
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("Hello world"),
+                handle_id: alice,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            render(&fixture),
+            "<div class=\"message\"><div class=\"received\"><p><span class=\"timestamp\">1970</span><span class=\"sender\">Alice</span></p><div class=\"message_part\"><span class=\"bubble\">Hello world</span></div></div></div>"
+        );
+    }
+
+    #[test]
+    fn can_render_attachment_and_reactions() {
+        let fixture = TestDatabase::new();
+        let message = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("\u{FFFC}Nice"),
+                is_from_me: true,
+                ..Default::default()
+            },
+        );
+        fixture.add_attachment(message, "~/Library/Messages/Attachments/a.png", "image/png");
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                handle_id: 1,
+                associated_message_guid: Some(&format!("p:0/{TARGET}")),
+                associated_message_type: 2000,
+                ..Default::default()
+            },
+        );
+
+        let html = render(&fixture);
+        assert!(html.starts_with("<div class=\"message\"><div class=\"sent\">"));
+        assert!(html.contains("<span class=\"sender\">Me</span>"));
+        assert!(html.contains(
+            "<div class=\"message_part\"><img src=\"a.png\"><div class=\"reactions\"><div class=\"reaction\">Loved by Alice</div></div></div>"
+        ));
+        assert!(html.contains("<span class=\"bubble\">Nice</span>"));
+    }
+
+    #[test]
+    fn can_render_escaped_text() {
+        let fixture = TestDatabase::new();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("<b>\"Tom & Jerry\"</b>"),
+                ..Default::default()
+            },
+        );
+
+        assert!(render(&fixture).contains(
+            "<span class=\"bubble\">&lt;b&gt;&quot;Tom &amp; Jerry&quot;&lt;/b&gt;</span>"
+        ));
+    }
+
+    #[test]
+    fn can_escape_script() {
+        assert_eq!(
+            html_escape("<script>alert(1)</script>"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;"
+        );
+    }
+
+    #[test]
+    fn can_escape_quotes() {
+        assert_eq!(html_escape("\"a\" 'b'"), "&quot;a&quot; &#39;b&#39;");
+    }
+
+    #[test]
+    fn can_escape_plain_text() {
+        assert_eq!(html_escape("Hello world"), "Hello world");
+    }
+
+    #[test]
+    fn can_truncate_text() {
+        assert_eq!(truncate_text("hello world", 5), "hello [truncated 6 chars]");
+    }
+
+    #[test]
+    fn can_truncate_text_on_char_boundary() {
+        assert_eq!(truncate_text("héllo🙂!", 6), "héllo🙂 [truncated 1 chars]");
+        assert_eq!(
+            truncate_text("日本語テキスト", 3),
+            "日本語 [truncated 4 chars]"
+        );
+        assert_eq!(truncate_text("🙂🙂", 1), "🙂 [truncated 1 chars]");
+    }
+
+    #[test]
+    fn can_truncate_text_short() {
+        assert!(matches!(truncate_text("hello", 5), Cow::Borrowed("hello")));
+        assert!(matches!(truncate_text("", 0), Cow::Borrowed("")));
+    }
+
+    #[test]
+    fn can_render_resolved_attachment() {
+        let fixture = TestDatabase::new();
+        let message = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("\u{FFFC}"),
+                ..Default::default()
+            },
+        );
+        fixture.add_attachment(
+            message,
+            "~/Library/Messages/Attachments/a.mov",
+            "video/quicktime",
+        );
+
+        assert!(render_with(&fixture, Some(&ExportResolver))
+            .contains("<video controls src=\"/export/a.mov\"></video>"));
+    }
+
+    #[test]
+    fn can_render_message() {
+        let fixture = TestDatabase::new();
+        let message = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("\u{FFFC}<b>Nice</b>"),
+                is_from_me: true,
+                date_delivered: 1,
+                thread_originator_guid: Some("A44CE9D7-AAAA-BBBB-CCCC-000000000003"),
+                ..Default::default()
+            },
+        );
+        fixture.add_attachment(message, "~/Library/Messages/Attachments/a.png", "image/png");
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                handle_id: 1,
+                associated_message_guid: Some(&format!("p:1/{TARGET}")),
+                associated_message_type: 2001,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            render_message(&fixture),
+            RenderedMessage {
+                guid: TARGET.to_string(),
+                date: "1970".to_string(),
+                sender: "Me".to_string(),
+                is_from_me: true,
+                status: DeliveryStatus::Delivered,
+                subject: None,
+                parts: vec![
+                    RenderedPart {
+                        content: RenderedContent::Attachment {
+                            path: "a.png".to_string(),
+                            filename: "a.png".to_string(),
+                            mime_type: Some("image/png".to_string()),
+                        },
+                        reactions: vec![],
+                    },
+                    RenderedPart {
+                        content: RenderedContent::Text {
+                            text: "<b>Nice</b>".to_string(),
+                        },
+                        reactions: vec![RenderedReaction {
+                            kind: "Liked".to_string(),
+                            sender: "Alice".to_string(),
+                        }],
+                    },
+                ],
+                reply_to: Some("A44CE9D7-AAAA-BBBB-CCCC-000000000003".to_string()),
+                num_replies: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn can_render_message_app() {
+        let fixture = TestDatabase::new();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("\u{FFFD}"),
+                balloon_bundle_id: Some("com.apple.messages.URLBalloonProvider"),
+                ..Default::default()
+            },
+        );
+
+        let rendered = render_message(&fixture);
+        assert_eq!(rendered.parts.len(), 1);
+        assert_eq!(
+            rendered.parts[0].content,
+            RenderedContent::App {
+                bundle_id: Some("com.apple.messages.URLBalloonProvider".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn can_render_message_received() {
+        let fixture = TestDatabase::new();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("Hello"),
+                handle_id: 1,
+                date_read: 1,
+                ..Default::default()
+            },
+        );
+
+        let rendered = render_message(&fixture);
+        assert_eq!(rendered.sender, "Alice");
+        assert_eq!(rendered.status, DeliveryStatus::Received);
+    }
+}
@@ -0,0 +1,142 @@
+/*!
+ Iterate over every message in a database without managing a [`Statement`](rusqlite::Statement).
+
+ [`MessageStream`] reads the `message` table in fixed-size pages using [`Message::get_page()`], so only one
+ page of messages is held in memory at a time and the iterator does not need to borrow a prepared statement.
+*/
+
+use std::collections::VecDeque;
+
+use rusqlite::Connection;
+
+use crate::{
+    error::table::TableError,
+    tables::messages::{models::MessageCursor, Message},
+};
+
+/// The number of messages read from the database at a time
+const PAGE_SIZE: usize = 1000;
+
+/// An iterator over every message in a database, ordered by date
+///
+/// Created by [`Message::stream()`].
+///
+/// # Example:
+///
+/// ```no_run
+/// use imessage_database::util::dirs::default_db_path;
+/// use imessage_database::tables::table::get_connection;
+/// use imessage_database::tables::messages::Message;
+///
+/// let db_path = default_db_path();
+/// let conn = get_connection(&db_path).unwrap();
+/// for message in Message::stream(&conn) {
+///     let message = message.unwrap();
+///     println!("{}", message.guid);
+/// }
+/// ```
+pub struct MessageStream<'a> {
+    /// The database to read messages from
+    db: &'a Connection,
+    /// Messages from the current page that have not been yielded yet
+    buffer: VecDeque<Message>,
+    /// The position after the last message that was read
+    cursor: Option<MessageCursor>,
+    /// Whether the last page was read, or reading a page failed
+    done: bool,
+}
+
+impl<'a> MessageStream<'a> {
+    fn new(db: &'a Connection) -> Self {
+        Self {
+            db,
+            buffer: VecDeque::new(),
+            cursor: None,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for MessageStream<'_> {
+    type Item = Result<Message, TableError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.is_empty() && !self.done {
+            match Message::get_page(self.db, self.cursor.as_ref(), PAGE_SIZE) {
+                Ok((messages, cursor)) => {
+                    self.done = cursor.is_none();
+                    self.cursor = cursor;
+                    self.buffer.extend(messages);
+                }
+                Err(why) => {
+                    self.done = true;
+                    return Some(Err(why));
+                }
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+impl Message {
+    /// Iterate over every message in the database, ordered by date
+    ///
+    /// Unlike [`Table::get()`](crate::tables::table::Table::get), no statement needs to be prepared and kept
+    /// alive by the caller. Messages are read in pages, so memory use does not grow with the size of the table.
+    /// If reading a page fails, the error is yielded once and the iterator ends.
+    pub fn stream(db: &Connection) -> MessageStream<'_> {
+        MessageStream::new(db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tables::{
+        messages::Message,
+        test_utils::{TestDatabase, TestMessage},
+    };
+
+    #[test]
+    fn can_stream_messages() {
+        let fixture = TestDatabase::new();
+        for date in [3, 1, 2] {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("STREAM-{date}"),
+                    date,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let dates: Vec<i64> = Message::stream(fixture.connection())
+            .map(|message| message.unwrap().date)
+            .collect();
+        assert_eq!(dates, [1, 2, 3]);
+    }
+
+    #[test]
+    fn can_stream_across_pages() {
+        let fixture = TestDatabase::new();
+        let total = super::PAGE_SIZE + 1;
+        for date in 0..total {
+            fixture.add_message(
+                None,
+                &TestMessage {
+                    guid: &format!("STREAM-{date}"),
+                    date: date as i64,
+                    ..Default::default()
+                },
+            );
+        }
+
+        assert_eq!(Message::stream(fixture.connection()).count(), total);
+    }
+
+    #[test]
+    fn can_stream_empty_table() {
+        let fixture = TestDatabase::new();
+        assert_eq!(Message::stream(fixture.connection()).count(), 0);
+    }
+}
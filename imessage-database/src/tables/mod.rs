@@ -11,3 +11,5 @@ pub mod chat_handle;
 pub mod handle;
 pub mod messages;
 pub mod table;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
@@ -0,0 +1,338 @@
+/*!
+ Helpers to build an in-memory iMessage database for tests.
+
+ The schema only contains the columns the queries in this crate read, so features that
+ query the database can be tested without a real `chat.db`. This module is available
+ to other crates with the `test-utils` feature.
+*/
+
+use rusqlite::{params, Connection};
+
+use crate::tables::table::{
+    ATTACHMENT, CHAT, CHAT_HANDLE_JOIN, CHAT_MESSAGE_JOIN, HANDLE, MESSAGE,
+    MESSAGE_ATTACHMENT_JOIN, RECENTLY_DELETED,
+};
+
+/// The values of a row to insert into the `message` table with [`TestDatabase::add_message()`]
+///
+/// Fields not set by the caller with struct update syntax, i.e. `..Default::default()`,
+/// use the values of a plain incoming iMessage.
+#[derive(Debug, Default)]
+pub struct TestMessage<'a> {
+    pub guid: &'a str,
+    pub text: Option<&'a str>,
+    /// Defaults to `iMessage` when `None`
+    pub service: Option<&'a str>,
+    pub handle_id: i32,
+    pub subject: Option<&'a str>,
+    pub date: i64,
+    pub date_read: i64,
+    pub date_delivered: i64,
+    pub date_edited: i64,
+    pub is_from_me: bool,
+    pub item_type: i32,
+    pub other_handle: i32,
+    pub group_title: Option<&'a str>,
+    pub group_action_type: i32,
+    pub associated_message_guid: Option<&'a str>,
+    pub associated_message_type: i32,
+    pub balloon_bundle_id: Option<&'a str>,
+    pub expressive_send_style_id: Option<&'a str>,
+    pub thread_originator_guid: Option<&'a str>,
+    pub thread_originator_part: Option<&'a str>,
+}
+
+/// An in-memory database with the tables used by this crate's queries
+///
+/// These helpers are only meant for tests, so they panic instead of returning errors.
+pub struct TestDatabase {
+    db: Connection,
+}
+
+impl TestDatabase {
+    /// Create an empty in-memory database with the iMessage schema
+    pub fn new() -> Self {
+        let db = Connection::open_in_memory().expect("Unable to open in-memory database");
+        db.execute_batch(&format!(
+            "
+            CREATE TABLE {MESSAGE} (
+                ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+                guid TEXT UNIQUE NOT NULL,
+                text TEXT,
+                service TEXT,
+                handle_id INTEGER DEFAULT 0,
+                destination_caller_id TEXT,
+                subject TEXT,
+                date INTEGER,
+                date_read INTEGER,
+                date_delivered INTEGER,
+                is_from_me INTEGER DEFAULT 0,
+                is_read INTEGER DEFAULT 0,
+                item_type INTEGER DEFAULT 0,
+                other_handle INTEGER DEFAULT 0,
+                share_status INTEGER DEFAULT 0,
+                share_direction INTEGER DEFAULT 0,
+                group_title TEXT,
+                group_action_type INTEGER DEFAULT 0,
+                associated_message_guid TEXT,
+                associated_message_type INTEGER DEFAULT 0,
+                balloon_bundle_id TEXT,
+                expressive_send_style_id TEXT,
+                thread_originator_guid TEXT,
+                thread_originator_part TEXT,
+                date_edited INTEGER DEFAULT 0,
+                attributedBody BLOB,
+                payload_data BLOB,
+                message_summary_info BLOB
+            );
+            CREATE TABLE {CHAT} (
+                ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+                guid TEXT,
+                chat_identifier TEXT,
+                service_name TEXT,
+                display_name TEXT
+            );
+            CREATE TABLE {HANDLE} (
+                ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+                id TEXT NOT NULL,
+                service TEXT,
+                person_centric_id TEXT
+            );
+            CREATE TABLE {ATTACHMENT} (
+                ROWID INTEGER PRIMARY KEY AUTOINCREMENT,
+                guid TEXT,
+                filename TEXT,
+                uti TEXT,
+                mime_type TEXT,
+                transfer_name TEXT,
+                total_bytes INTEGER DEFAULT 0,
+                is_sticker INTEGER DEFAULT 0,
+                hide_attachment INTEGER DEFAULT 0,
+                emoji_image_short_description TEXT
+            );
+            CREATE TABLE {CHAT_MESSAGE_JOIN} (
+                chat_id INTEGER,
+                message_id INTEGER,
+                message_date INTEGER DEFAULT 0,
+                PRIMARY KEY (chat_id, message_id)
+            );
+            CREATE TABLE {CHAT_HANDLE_JOIN} (
+                chat_id INTEGER,
+                handle_id INTEGER,
+                UNIQUE (chat_id, handle_id)
+            );
+            CREATE TABLE {MESSAGE_ATTACHMENT_JOIN} (
+                message_id INTEGER,
+                attachment_id INTEGER,
+                UNIQUE (message_id, attachment_id)
+            );
+            CREATE TABLE {RECENTLY_DELETED} (
+                chat_id INTEGER,
+                message_id INTEGER,
+                delete_date INTEGER,
+                PRIMARY KEY (chat_id, message_id)
+            );
+            "
+        ))
+        .expect("Unable to create schema");
+        Self { db }
+    }
+
+    /// Get the connection to the database, to pass to the queries under test
+    pub fn connection(&self) -> &Connection {
+        &self.db
+    }
+
+    /// Insert a row into the `handle` table, returning its `ROWID`
+    pub fn add_handle(&self, id: &str) -> i32 {
+        self.db
+            .execute(
+                &format!("INSERT INTO {HANDLE} (id, service) VALUES (?1, 'iMessage')"),
+                params![id],
+            )
+            .expect("Unable to insert handle");
+        self.db.last_insert_rowid() as i32
+    }
+
+    /// Insert a row into the `chat` table with the given participants, returning its `ROWID`
+    pub fn add_chat(&self, chat_identifier: &str, service_name: &str, handles: &[i32]) -> i32 {
+        self.db
+            .execute(
+                &format!("INSERT INTO {CHAT} (chat_identifier, service_name) VALUES (?1, ?2)"),
+                params![chat_identifier, service_name],
+            )
+            .expect("Unable to insert chat");
+        let chat_id = self.db.last_insert_rowid() as i32;
+
+        for handle_id in handles {
+            self.db
+                .execute(
+                    &format!("INSERT INTO {CHAT_HANDLE_JOIN} (chat_id, handle_id) VALUES (?1, ?2)"),
+                    params![chat_id, handle_id],
+                )
+                .expect("Unable to insert chat participant");
+        }
+        chat_id
+    }
+
+    /// Insert a row into the `message` table, returning its `ROWID`
+    ///
+    /// The message is added to `chat_id` if it is provided.
+    pub fn add_message(&self, chat_id: Option<i32>, message: &TestMessage) -> i32 {
+        self.db
+            .execute(
+                &format!(
+                    "INSERT INTO {MESSAGE} (
+                        guid, text, service, handle_id, subject, date, date_read, date_delivered,
+                        date_edited, is_from_me, item_type, other_handle, group_title, group_action_type,
+                        associated_message_guid, associated_message_type, balloon_bundle_id,
+                        expressive_send_style_id, thread_originator_guid, thread_originator_part
+                    ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)"
+                ),
+                params![
+                    message.guid,
+                    message.text,
+                    message.service.unwrap_or("iMessage"),
+                    message.handle_id,
+                    message.subject,
+                    message.date,
+                    message.date_read,
+                    message.date_delivered,
+                    message.date_edited,
+                    message.is_from_me,
+                    message.item_type,
+                    message.other_handle,
+                    message.group_title,
+                    message.group_action_type,
+                    message.associated_message_guid,
+                    message.associated_message_type,
+                    message.balloon_bundle_id,
+                    message.expressive_send_style_id,
+                    message.thread_originator_guid,
+                    message.thread_originator_part,
+                ],
+            )
+            .expect("Unable to insert message");
+        let message_id = self.db.last_insert_rowid() as i32;
+
+        if let Some(chat_id) = chat_id {
+            self.db
+                .execute(
+                    &format!(
+                        "INSERT INTO {CHAT_MESSAGE_JOIN} (chat_id, message_id, message_date) VALUES (?1, ?2, ?3)"
+                    ),
+                    params![chat_id, message_id, message.date],
+                )
+                .expect("Unable to insert message into chat");
+        }
+        message_id
+    }
+
+    /// Insert a row into the `attachment` table that belongs to `message_id`, returning its `ROWID`
+    pub fn add_attachment(&self, message_id: i32, filename: &str, mime_type: &str) -> i32 {
+        let transfer_name = filename.rsplit('/').next().unwrap_or(filename);
+        self.db
+            .execute(
+                &format!(
+                    "INSERT INTO {ATTACHMENT} (filename, mime_type, transfer_name) VALUES (?1, ?2, ?3)"
+                ),
+                params![filename, mime_type, transfer_name],
+            )
+            .expect("Unable to insert attachment");
+        let attachment_id = self.db.last_insert_rowid() as i32;
+
+        self.db
+            .execute(
+                &format!(
+                    "INSERT INTO {MESSAGE_ATTACHMENT_JOIN} (message_id, attachment_id) VALUES (?1, ?2)"
+                ),
+                params![message_id, attachment_id],
+            )
+            .expect("Unable to insert attachment into message");
+        attachment_id
+    }
+}
+
+impl Default for TestDatabase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tables::{
+        messages::Message,
+        table::Table,
+        test_utils::{TestDatabase, TestMessage},
+    };
+
+    #[test]
+    fn can_stream_messages() {
+        let fixture = TestDatabase::new();
+        let handle = fixture.add_handle("+15558675309");
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[handle]);
+        let message = fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                text: Some("Hello world"),
+                handle_id: handle,
+                ..Default::default()
+            },
+        );
+        fixture.add_attachment(message, "~/Library/Messages/Attachments/a.png", "image/png");
+
+        let db = fixture.connection();
+        let mut statement = Message::get(db).unwrap();
+        let messages: Vec<Message> = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap())
+            .collect();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].text.as_deref(), Some("Hello world"));
+        assert_eq!(messages[0].chat_id, Some(chat));
+        assert!(messages[0].has_attachments());
+        assert_eq!(messages[0].attachments(db).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn can_get_replies() {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000001",
+                text: Some("Hello"),
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                text: Some("Hi"),
+                date: 1,
+                thread_originator_guid: Some("A44CE9D7-AAAA-BBBB-CCCC-000000000001"),
+                thread_originator_part: Some("0:0:5"),
+                ..Default::default()
+            },
+        );
+
+        let db = fixture.connection();
+        let mut statement = Message::get(db).unwrap();
+        let original = statement
+            .query_map([], |row| Ok(Message::from_row(row)))
+            .unwrap()
+            .map(|message| Message::extract(message).unwrap())
+            .find(|message| message.rowid == 1)
+            .unwrap();
+
+        assert!(original.has_replies());
+        let replies = original.get_replies(db).unwrap();
+        assert_eq!(replies.get(&0).map(Vec::len), Some(1));
+    }
+}
@@ -4,21 +4,97 @@
  Most dates are stored as nanosecond-precision unix timestamps with an epoch of `1/1/2001 00:00:00` in the local time zone.
 */
 
-use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use std::{env::var, fmt::Write, fs::read_link};
+
+use chrono::{DateTime, Datelike, Duration, Local, Months, TimeZone};
 
 use crate::error::message::MessageError;
 
 const SEPARATOR: &str = ", ";
+/// The format used to render dates for reading
+const DATE_FORMAT: &str = "%b %d, %Y %l:%M:%S %p";
+/// Directory name that precedes the time zone name in the target of `/etc/localtime`
+const ZONEINFO: &str = "zoneinfo/";
 pub const TIMESTAMP_FACTOR: i64 = 1000000000;
+/// The number of seconds from `1970-01-01 00:00:00` to `2001-01-01 00:00:00`, returned by [`get_offset()`]
+pub const APPLE_EPOCH_OFFSET: i64 = 978_307_200;
+/// The number of seconds from `2001-01-01` to `2101-01-01`, the latest plausible date for a message
+const MAX_PLAUSIBLE_SECONDS: i64 = 3_155_760_000;
+
+/// The unit that a database stores its timestamps in
+///
+/// Modern databases store nanoseconds, which is what [`get_local_time()`] expects, but older databases may
+/// use coarser units. Use [`TimestampScale::to_nanoseconds()`] to convert timestamps before parsing them.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum TimestampScale {
+    /// Whole seconds since `2001-01-01 00:00:00`
+    Seconds,
+    /// Milliseconds since `2001-01-01 00:00:00`
+    Milliseconds,
+    /// Microseconds since `2001-01-01 00:00:00`
+    Microseconds,
+    /// Nanoseconds since `2001-01-01 00:00:00`, used by modern databases
+    #[default]
+    Nanoseconds,
+}
+
+impl TimestampScale {
+    /// The number of timestamp units in one second
+    pub fn units_per_second(&self) -> i64 {
+        match self {
+            TimestampScale::Seconds => 1,
+            TimestampScale::Milliseconds => 1_000,
+            TimestampScale::Microseconds => 1_000_000,
+            TimestampScale::Nanoseconds => TIMESTAMP_FACTOR,
+        }
+    }
+
+    /// Infer the scale of a timestamp from the table
+    ///
+    /// This picks the coarsest unit that places the timestamp between `2001` and `2100`. Returns `None` if the
+    /// timestamp is not positive or no unit produces a plausible date.
+    ///
+    /// # Example:
+    ///
+    /// ```
+    /// use imessage_database::util::dates::TimestampScale;
+    ///
+    /// assert_eq!(TimestampScale::detect(674526582), Some(TimestampScale::Seconds));
+    /// assert_eq!(TimestampScale::detect(674526582885055488), Some(TimestampScale::Nanoseconds));
+    /// ```
+    pub fn detect(stamp: i64) -> Option<Self> {
+        if stamp <= 0 {
+            return None;
+        }
+        [
+            TimestampScale::Seconds,
+            TimestampScale::Milliseconds,
+            TimestampScale::Microseconds,
+            TimestampScale::Nanoseconds,
+        ]
+        .into_iter()
+        .find(|scale| stamp / scale.units_per_second() <= MAX_PLAUSIBLE_SECONDS)
+    }
+
+    /// Convert a timestamp in this scale to nanoseconds, the scale used by [`get_local_time()`]
+    pub fn to_nanoseconds(&self, stamp: i64) -> i64 {
+        stamp.saturating_mul(TIMESTAMP_FACTOR / self.units_per_second())
+    }
+
+    /// Convert a timestamp in nanoseconds to this scale, the inverse of [`Self::to_nanoseconds()`]
+    pub fn from_nanoseconds(&self, stamp: i64) -> i64 {
+        stamp / (TIMESTAMP_FACTOR / self.units_per_second())
+    }
+}
 
 /// Get the date offset for the iMessage Database
 ///
 /// This offset is used to adjust the unix timestamps stored in the iMessage database
 /// with a non-standard epoch of `2001-01-01 00:00:00` in the local time zone.
-pub fn get_offset() -> i64 {
-    Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0)
-        .unwrap()
-        .timestamp()
+///
+/// The offset never changes, so this is a `const fn` that returns [`APPLE_EPOCH_OFFSET`].
+pub const fn get_offset() -> i64 {
+    APPLE_EPOCH_OFFSET
 }
 
 /// Create a `DateTime<Local>` from an arbitrary date and offset
@@ -26,10 +102,73 @@ pub fn get_offset() -> i64 {
 /// This is used to create date data for anywhere dates are stored in the table, including
 /// `PLIST` payloads or [`typedstream`](crate::util::typedstream) data.
 pub fn get_local_time(date_stamp: &i64, offset: &i64) -> Result<DateTime<Local>, MessageError> {
+    get_time_in(date_stamp, offset, &Local)
+}
+
+/// Create a `DateTime` in the time zone `tz` from an arbitrary date and offset
+///
+/// This is the same as [`get_local_time()`], but renders the date in a specific zone instead of the zone of
+/// the machine running the export, i.e. [`Utc`](chrono::Utc) or a [`FixedOffset`](chrono::FixedOffset).
+///
+/// # Example:
+///
+/// ```
+/// use chrono::{FixedOffset, Utc};
+/// use imessage_database::util::dates::{get_offset, get_time_in};
+///
+/// let offset = get_offset();
+/// let utc = get_time_in(&674526582885055488, &offset, &Utc).unwrap();
+/// assert_eq!(utc.format("%Y-%m-%d %H:%M:%S").to_string(), "2022-05-18 00:29:42");
+///
+/// let tokyo = FixedOffset::east_opt(9 * 3600).unwrap();
+/// let date = get_time_in(&674526582885055488, &offset, &tokyo).unwrap();
+/// assert_eq!(date.format("%Y-%m-%d %H:%M:%S").to_string(), "2022-05-18 09:29:42");
+/// ```
+pub fn get_time_in<Tz: TimeZone>(
+    date_stamp: &i64,
+    offset: &i64,
+    tz: &Tz,
+) -> Result<DateTime<Tz>, MessageError> {
     let utc_stamp = DateTime::from_timestamp((date_stamp / TIMESTAMP_FACTOR) + offset, 0)
         .ok_or(MessageError::InvalidTimestamp(*date_stamp))?
         .naive_utc();
-    Ok(Local.from_utc_datetime(&utc_stamp))
+    Ok(tz.from_utc_datetime(&utc_stamp))
+}
+
+/// Convert a date to a timestamp as stored in the iMessage database, the inverse of [`get_local_time()`]
+///
+/// Timestamps are created with nanosecond precision, but any fraction of a second is discarded.
+pub fn to_timestamp(date: &DateTime<Local>, offset: &i64) -> i64 {
+    (date.timestamp() - offset) * TIMESTAMP_FACTOR
+}
+
+/// Create a timestamp as stored in the iMessage database from a date and time in the local time zone
+///
+/// This makes fixtures readable instead of using opaque timestamps. Returns `None` if the components
+/// are not a valid date, or if the time does not exist or is ambiguous in the local time zone, i.e.
+/// during daylight saving time transitions.
+///
+/// # Example:
+///
+/// ```
+/// use imessage_database::util::dates::{from_components, get_local_time, get_offset};
+///
+/// let stamp = from_components(2021, 5, 17, 9, 30, 0).unwrap();
+/// let date = get_local_time(&stamp, &get_offset()).unwrap();
+/// assert_eq!(date.format("%Y-%m-%d %H:%M:%S").to_string(), "2021-05-17 09:30:00");
+/// ```
+pub fn from_components(
+    year: i32,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+) -> Option<i64> {
+    Local
+        .with_ymd_and_hms(year, month, day, hour, minute, second)
+        .single()
+        .map(|date| to_timestamp(&date, &get_offset()))
 }
 
 /// Format a date from the iMessage table for reading
@@ -44,14 +183,104 @@ pub fn get_local_time(date_stamp: &i64, offset: &i64) -> Result<DateTime<Local>,
 /// println!("{date}");
 /// ```
 pub fn format(date: &Result<DateTime<Local>, MessageError>) -> String {
+    format_with(date, DATE_FORMAT)
+}
+
+/// Format a date from the iMessage table for reading with a custom [`chrono` format string](chrono::format::strftime)
+///
+/// If `fmt` is not a valid format string, the date is rendered with the default format used by [`format()`].
+///
+/// # Example:
+///
+/// ```
+/// use chrono::prelude::*;
+/// use imessage_database::util::dates::format_with;
+///
+/// let date = Ok(Local.with_ymd_and_hms(2022, 5, 17, 20, 29, 42).unwrap());
+/// assert_eq!(format_with(&date, "%Y-%m-%d %H:%M:%S"), "2022-05-17 20:29:42");
+/// ```
+pub fn format_with(date: &Result<DateTime<Local>, MessageError>, fmt: &str) -> String {
     match date {
-        Ok(d) => DateTime::format(d, "%b %d, %Y %l:%M:%S %p").to_string(),
+        Ok(d) => {
+            let mut out = String::new();
+            match write!(out, "{}", d.format(fmt)) {
+                Ok(()) => out,
+                Err(_) => d.format(DATE_FORMAT).to_string(),
+            }
+        }
         Err(why) => why.to_string(),
     }
 }
 
+/// Format an optional date for reading, using the same format as [`format()`]
+///
+/// Returns `None` when there is no date, so callers can omit absent timestamps instead of rendering blanks.
+///
+/// # Example:
+///
+/// ```
+/// use chrono::prelude::*;
+/// use imessage_database::util::dates::format_opt;
+///
+/// let date = Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).single();
+/// assert_eq!(format_opt(&date), Some("May 20, 2020  9:10:11 AM".to_string()));
+/// assert_eq!(format_opt(&None), None);
+/// ```
+pub fn format_opt(date: &Option<DateTime<Local>>) -> Option<String> {
+    date.as_ref()
+        .map(|d| DateTime::format(d, DATE_FORMAT).to_string())
+}
+
+/// Format a date from the iMessage table for reading, followed by the local time zone label
+///
+/// See [`timezone_label`] for details on how the label is resolved.
+///
+/// # Example:
+///
+/// ```
+/// use chrono::offset::Local;
+/// use imessage_database::util::dates::format_with_timezone;
+///
+/// let date = format_with_timezone(&Ok(Local::now()));
+/// println!("{date}"); // i.e. "May 20, 2020  9:10:11 AM America/New_York"
+/// ```
+pub fn format_with_timezone(date: &Result<DateTime<Local>, MessageError>) -> String {
+    match date {
+        Ok(d) => format!("{} {}", format(date), timezone_label(d)),
+        Err(why) => why.to_string(),
+    }
+}
+
+/// Get a label for the system's local time zone, i.e. `America/New_York`
+///
+/// The name is resolved from the `TZ` environment variable, then from the target of the
+/// `/etc/localtime` symlink used by macOS and most Linux distributions. If the name cannot
+/// be determined, this falls back to the numeric UTC offset of `date`, i.e. `-04:00`.
+pub fn timezone_label(date: &DateTime<Local>) -> String {
+    var("TZ")
+        .ok()
+        .map(|tz| tz.trim_start_matches(':').to_string())
+        .filter(|tz| !tz.is_empty())
+        .or_else(|| {
+            let target = read_link("/etc/localtime").ok()?;
+            timezone_name_from_path(target.to_str()?).map(String::from)
+        })
+        .unwrap_or_else(|| date.format("%:z").to_string())
+}
+
+/// Parse a time zone name from a path into the tz database, i.e. `/usr/share/zoneinfo/America/New_York`
+fn timezone_name_from_path(path: &str) -> Option<&str> {
+    let idx = path.rfind(ZONEINFO)?;
+    path.get(idx + ZONEINFO.len()..)
+        .filter(|name| !name.is_empty())
+}
+
 /// Generate a readable diff from two local timestamps.
 ///
+/// Returns `None` if `end` is before `start` or if either date is invalid. Equal timestamps
+/// produce `Some("0 seconds")`, and diffs shorter than one second are rendered in milliseconds,
+/// i.e. `500 milliseconds`.
+///
 /// # Example:
 ///
 /// ```
@@ -67,14 +296,37 @@ pub fn readable_diff(
     end: Result<DateTime<Local>, MessageError>,
 ) -> Option<String> {
     // Calculate diff
-    let diff: Duration = end.ok()? - start.ok()?;
+    readable_duration(end.ok()? - start.ok()?)
+}
+
+/// Generate a readable string from a [`Duration`], using the same rules as [`readable_diff`].
+///
+/// # Example:
+///
+/// ```
+/// use chrono::Duration;
+/// use imessage_database::util::dates::readable_duration;
+///
+/// let diff = Duration::seconds(302);
+/// println!("{}", readable_duration(diff).unwrap()) // "5 minutes, 2 seconds"
+/// ```
+pub fn readable_duration(diff: Duration) -> Option<String> {
     let seconds = diff.num_seconds();
 
     // Early escape for invalid date diff
-    if seconds < 0 {
+    if diff < Duration::zero() {
         return None;
     }
 
+    // Early escape for diffs that do not fill a single second
+    if seconds == 0 {
+        return Some(match diff.num_milliseconds() {
+            0 => "0 seconds".to_string(),
+            1 => "1 millisecond".to_string(),
+            millis => format!("{millis} milliseconds"),
+        });
+    }
+
     // 42 is the length of a diff string that has all components with 2 digits each
     // This allocation improved performance over `::new()` by 20%
     // (21.99s to 27.79s over 250k messages)
@@ -125,13 +377,143 @@ pub fn readable_diff(
     Some(out_s)
 }
 
+/// Generate a coarse readable diff from two local timestamps for summarizing long gaps
+///
+/// Unlike [`readable_diff`], which never uses units larger than days, this uses years, months, weeks, and days,
+/// and only keeps the two largest nonzero units. Months are counted on the calendar, so `Jan 31` to `Feb 28`
+/// is one month. Diffs shorter than a day use the same format as [`readable_diff`].
+///
+/// Returns `None` if `end` is before `start` or if either date is invalid.
+///
+/// # Example:
+///
+/// ```
+/// use chrono::prelude::*;
+/// use imessage_database::util::dates::readable_diff_coarse;
+///
+/// let start = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
+/// let end = Ok(Local.with_ymd_and_hms(2022, 6, 24, 9, 15, 13).unwrap());
+/// assert_eq!(readable_diff_coarse(start, end).unwrap(), "2 years, 1 month");
+/// ```
+pub fn readable_diff_coarse(
+    start: Result<DateTime<Local>, MessageError>,
+    end: Result<DateTime<Local>, MessageError>,
+) -> Option<String> {
+    let (start, end) = (start.ok()?, end.ok()?);
+    if end < start {
+        return None;
+    }
+
+    // Compare wall-clock times so a DST change does not shift the day boundary
+    let (start_naive, end_naive) = (start.naive_local(), end.naive_local());
+    let mut months = (end_naive.year() - start_naive.year()) * 12 + end_naive.month() as i32
+        - start_naive.month() as i32;
+    let mut anchor = start_naive.checked_add_months(Months::new(months.max(0) as u32))?;
+    if anchor > end_naive {
+        months -= 1;
+        anchor = start_naive.checked_add_months(Months::new(months.max(0) as u32))?;
+    }
+    let days = (end_naive - anchor).num_days();
+
+    let units = [
+        (i64::from(months / 12), "year"),
+        (i64::from(months % 12), "month"),
+        (days / 7, "week"),
+        (days % 7, "day"),
+    ];
+    let parts: Vec<String> = units
+        .iter()
+        .filter(|(count, _)| *count != 0)
+        .take(2)
+        .map(|(count, unit)| match count {
+            1 => format!("{count} {unit}"),
+            _ => format!("{count} {unit}s"),
+        })
+        .collect();
+
+    if parts.is_empty() {
+        return readable_duration(end - start);
+    }
+    Some(parts.join(SEPARATOR))
+}
+
+/// Determine if `cur` falls on a different calendar day than `prev` in the local time zone
+///
+/// This is used to decide when to insert a date divider between two adjacent messages.
+///
+/// # Example:
+///
+/// ```
+/// use chrono::prelude::*;
+/// use imessage_database::util::dates::is_new_day;
+///
+/// let prev = Local.with_ymd_and_hms(2020, 5, 20, 23, 59, 59).unwrap();
+/// let cur = Local.with_ymd_and_hms(2020, 5, 21, 0, 0, 0).unwrap();
+/// assert!(is_new_day(&prev, &cur));
+/// ```
+pub fn is_new_day(prev: &DateTime<Local>, cur: &DateTime<Local>) -> bool {
+    prev.date_naive() != cur.date_naive()
+}
+
+/// Format a date relative to `now` for use in transcript headers
+///
+/// - Dates on the same day as `now` yield `Today`
+/// - Dates on the day before `now` yield `Yesterday`
+/// - Dates within the week before `now` yield the name of the weekday, i.e. `Monday`
+/// - Any other date, including dates after `now`, yields the full date, i.e. `May 20, 2020`
+///
+/// # Example:
+///
+/// ```
+/// use chrono::prelude::*;
+/// use imessage_database::util::dates::format_relative;
+///
+/// let now = Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap();
+/// let date = Local.with_ymd_and_hms(2020, 5, 19, 23, 0, 0).unwrap();
+/// assert_eq!(format_relative(&date, &now), "Yesterday");
+/// ```
+pub fn format_relative(date: &DateTime<Local>, now: &DateTime<Local>) -> String {
+    let days = (now.date_naive() - date.date_naive()).num_days();
+    match days {
+        0 => "Today".to_string(),
+        1 => "Yesterday".to_string(),
+        2..=6 => date.format("%A").to_string(),
+        _ => date.format("%b %d, %Y").to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
         error::message::MessageError,
-        util::dates::{format, readable_diff},
+        util::dates::{
+            format, format_opt, format_relative, format_with, from_components, get_local_time,
+            get_offset, is_new_day, readable_diff, readable_diff_coarse, timezone_name_from_path,
+            to_timestamp, TimestampScale,
+        },
     };
-    use chrono::prelude::*;
+    use chrono::{prelude::*, Duration};
+
+    #[test]
+    fn can_format_date_with_custom_format() {
+        let date = Local.with_ymd_and_hms(2022, 5, 17, 20, 29, 42).unwrap();
+        assert_eq!(
+            format_with(&Ok(date), "%Y-%m-%dT%H:%M:%S"),
+            "2022-05-17T20:29:42"
+        );
+    }
+
+    #[test]
+    fn can_format_date_with_invalid_format() {
+        let date = Local.with_ymd_and_hms(2022, 5, 17, 20, 29, 42).unwrap();
+        assert_eq!(format_with(&Ok(date), "%Q"), format(&Ok(date)));
+    }
+
+    #[test]
+    fn can_format_date_with_error() {
+        let date = Err(MessageError::InvalidTimestamp(0));
+        assert_eq!(format_with(&date, "%Y"), format(&date));
+    }
 
     #[test]
     fn can_format_date_single_digit() {
@@ -240,6 +622,47 @@ mod tests {
         assert_eq!(readable_diff(start, end), Some("791 days".to_owned()));
     }
 
+    #[test]
+    fn can_format_coarse_diff_years_months() {
+        let start = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
+        let end = Ok(Local.with_ymd_and_hms(2022, 6, 24, 9, 10, 11).unwrap());
+        assert_eq!(
+            readable_diff_coarse(start, end),
+            Some("2 years, 1 month".to_owned())
+        );
+    }
+
+    #[test]
+    fn can_format_coarse_diff_end_of_month() {
+        let start = Ok(Local.with_ymd_and_hms(2021, 1, 31, 9, 10, 11).unwrap());
+        let end = Ok(Local.with_ymd_and_hms(2021, 2, 28, 9, 10, 11).unwrap());
+        assert_eq!(readable_diff_coarse(start, end), Some("1 month".to_owned()));
+    }
+
+    #[test]
+    fn can_format_coarse_diff_partial_month() {
+        let start = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
+        let end = Ok(Local.with_ymd_and_hms(2020, 6, 19, 9, 10, 11).unwrap());
+        assert_eq!(
+            readable_diff_coarse(start, end),
+            Some("4 weeks, 2 days".to_owned())
+        );
+    }
+
+    #[test]
+    fn can_format_coarse_diff_under_a_day() {
+        let start = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
+        let end = Ok(Local.with_ymd_and_hms(2020, 5, 20, 12, 10, 11).unwrap());
+        assert_eq!(readable_diff_coarse(start, end), Some("3 hours".to_owned()));
+    }
+
+    #[test]
+    fn cant_format_coarse_diff_bad_order() {
+        let start = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
+        let end = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 10).unwrap());
+        assert_eq!(readable_diff_coarse(start, end), None);
+    }
+
     #[test]
     fn can_format_diff_all() {
         let start = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
@@ -254,6 +677,186 @@ mod tests {
     fn can_format_no_diff() {
         let start = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
         let end = Ok(Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap());
-        assert_eq!(readable_diff(start, end), Some("".to_owned()));
+        assert_eq!(readable_diff(start, end), Some("0 seconds".to_owned()));
+    }
+
+    #[test]
+    fn can_format_diff_sub_second() {
+        let start = Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap();
+        let end = start + Duration::milliseconds(500);
+        assert_eq!(
+            readable_diff(Ok(start), Ok(end)),
+            Some("500 milliseconds".to_owned())
+        );
+    }
+
+    #[test]
+    fn cant_format_diff_sub_second_backwards() {
+        let end = Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap();
+        let start = end + Duration::milliseconds(500);
+        assert_eq!(readable_diff(Ok(start), Ok(end)), None);
+    }
+
+    #[test]
+    fn can_get_timezone_name_linux() {
+        assert_eq!(
+            timezone_name_from_path("/usr/share/zoneinfo/America/New_York"),
+            Some("America/New_York")
+        );
+    }
+
+    #[test]
+    fn can_get_timezone_name_macos() {
+        assert_eq!(
+            timezone_name_from_path("/var/db/timezone/zoneinfo/Europe/Berlin"),
+            Some("Europe/Berlin")
+        );
+    }
+
+    #[test]
+    fn cant_get_timezone_name_invalid() {
+        assert_eq!(timezone_name_from_path("/etc/localtime"), None);
+        assert_eq!(timezone_name_from_path("/usr/share/zoneinfo/"), None);
+    }
+
+    #[test]
+    fn can_detect_new_day_midnight() {
+        let prev = Local.with_ymd_and_hms(2020, 5, 20, 23, 59, 59).unwrap();
+        let cur = Local.with_ymd_and_hms(2020, 5, 21, 0, 0, 0).unwrap();
+        assert!(is_new_day(&prev, &cur));
+    }
+
+    #[test]
+    fn can_detect_new_day_month_boundary() {
+        let prev = Local.with_ymd_and_hms(2020, 5, 31, 22, 0, 0).unwrap();
+        let cur = Local.with_ymd_and_hms(2020, 6, 1, 8, 0, 0).unwrap();
+        assert!(is_new_day(&prev, &cur));
+    }
+
+    #[test]
+    fn can_detect_new_day_same_day_next_month() {
+        let prev = Local.with_ymd_and_hms(2020, 5, 20, 9, 0, 0).unwrap();
+        let cur = Local.with_ymd_and_hms(2020, 6, 20, 9, 0, 0).unwrap();
+        assert!(is_new_day(&prev, &cur));
+    }
+
+    #[test]
+    fn cant_detect_new_day_same_day() {
+        let prev = Local.with_ymd_and_hms(2020, 5, 20, 0, 0, 0).unwrap();
+        let cur = Local.with_ymd_and_hms(2020, 5, 20, 23, 59, 59).unwrap();
+        assert!(!is_new_day(&prev, &cur));
+    }
+
+    #[test]
+    fn can_format_relative_today() {
+        let now = Local.with_ymd_and_hms(2020, 5, 20, 23, 59, 59).unwrap();
+        let date = Local.with_ymd_and_hms(2020, 5, 20, 0, 0, 0).unwrap();
+        assert_eq!(format_relative(&date, &now), "Today");
+    }
+
+    #[test]
+    fn can_format_relative_yesterday() {
+        let now = Local.with_ymd_and_hms(2020, 5, 20, 0, 0, 1).unwrap();
+        let date = Local.with_ymd_and_hms(2020, 5, 19, 23, 59, 59).unwrap();
+        assert_eq!(format_relative(&date, &now), "Yesterday");
+    }
+
+    #[test]
+    fn can_format_relative_weekday() {
+        let now = Local.with_ymd_and_hms(2020, 5, 20, 9, 0, 0).unwrap();
+        let date = Local.with_ymd_and_hms(2020, 5, 18, 9, 0, 0).unwrap();
+        assert_eq!(format_relative(&date, &now), "Monday");
+    }
+
+    #[test]
+    fn can_format_relative_weekday_oldest() {
+        let now = Local.with_ymd_and_hms(2020, 5, 20, 9, 0, 0).unwrap();
+        let date = Local.with_ymd_and_hms(2020, 5, 14, 9, 0, 0).unwrap();
+        assert_eq!(format_relative(&date, &now), "Thursday");
+    }
+
+    #[test]
+    fn can_format_relative_full_date() {
+        let now = Local.with_ymd_and_hms(2020, 5, 20, 9, 0, 0).unwrap();
+        let date = Local.with_ymd_and_hms(2020, 5, 13, 9, 0, 0).unwrap();
+        assert_eq!(format_relative(&date, &now), "May 13, 2020");
+    }
+
+    #[test]
+    fn can_format_relative_future() {
+        let now = Local.with_ymd_and_hms(2020, 5, 20, 9, 0, 0).unwrap();
+        let date = Local.with_ymd_and_hms(2020, 5, 21, 9, 0, 0).unwrap();
+        assert_eq!(format_relative(&date, &now), "May 21, 2020");
+    }
+
+    #[test]
+    fn can_format_opt_some() {
+        let date = Local.with_ymd_and_hms(2020, 5, 20, 10, 10, 11).single();
+        assert_eq!(
+            format_opt(&date),
+            Some("May 20, 2020 10:10:11 AM".to_string())
+        );
+    }
+
+    #[test]
+    fn can_format_opt_none() {
+        assert_eq!(format_opt(&None), None);
+    }
+
+    #[test]
+    fn can_detect_each_timestamp_scale() {
+        // 2022-05-17 in seconds since 2001-01-01
+        let seconds = 674526582;
+        for scale in [
+            TimestampScale::Seconds,
+            TimestampScale::Milliseconds,
+            TimestampScale::Microseconds,
+            TimestampScale::Nanoseconds,
+        ] {
+            let stamp = seconds * scale.units_per_second();
+            assert_eq!(TimestampScale::detect(stamp), Some(scale));
+
+            let date = get_local_time(&scale.to_nanoseconds(stamp), &get_offset()).unwrap();
+            assert_eq!(date.year(), 2022);
+        }
+    }
+
+    #[test]
+    fn cant_detect_invalid_timestamp_scale() {
+        assert_eq!(TimestampScale::detect(0), None);
+        assert_eq!(TimestampScale::detect(-1), None);
+        assert_eq!(TimestampScale::detect(i64::MAX), None);
+    }
+
+    #[test]
+    fn can_get_offset() {
+        assert_eq!(
+            get_offset(),
+            Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0)
+                .unwrap()
+                .timestamp()
+        );
+    }
+
+    #[test]
+    fn can_round_trip_timestamp() {
+        let stamp = 674526582000000000;
+        let date = get_local_time(&stamp, &get_offset()).unwrap();
+        assert_eq!(to_timestamp(&date, &get_offset()), stamp);
+    }
+
+    #[test]
+    fn can_get_timestamp_from_components() {
+        let stamp = from_components(2020, 5, 20, 9, 10, 11).unwrap();
+        let date = get_local_time(&stamp, &get_offset()).unwrap();
+        assert_eq!(
+            date,
+            Local.with_ymd_and_hms(2020, 5, 20, 9, 10, 11).unwrap()
+        );
+    }
+
+    #[test]
+    fn cant_get_timestamp_from_invalid_components() {
+        assert_eq!(from_components(2020, 2, 30, 9, 10, 11), None);
     }
 }
@@ -0,0 +1,802 @@
+/*!
+ Contains logic for streaming messages from a database as JSON, and reactions as JSON or CSV.
+*/
+
+use std::{collections::HashMap, io::Write};
+
+use rusqlite::Connection;
+
+use crate::{
+    error::{json::JsonError, message::MessageError, table::TableError},
+    message_types::variants::Variant,
+    tables::{
+        messages::Message,
+        table::{Cacheable, Table},
+    },
+    util::{
+        dates::{get_local_time, get_offset, TimestampScale},
+        query_context::QueryContext,
+    },
+};
+
+/// The layout of the JSON output
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum JsonFormat {
+    /// A single JSON array containing every message
+    #[default]
+    Array,
+    /// One JSON object per line, also known as NDJSON
+    Lines,
+}
+
+/// How dates are written in the JSON output
+///
+/// Dates that are not set in the table, like the read date of an unread message, are always written as `null`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum JsonDateFormat {
+    /// An RFC 3339 string in the local time zone, i.e. `2023-01-01T12:00:00-08:00`
+    #[default]
+    Rfc3339,
+    /// Seconds since the unix epoch
+    Unix,
+    /// The raw value stored in the table, in the database's [`TimestampScale`] since `2001-01-01 00:00:00`
+    Apple,
+}
+
+/// Which messages to include in the JSON output, based on who sent them
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum Direction {
+    /// Include all messages
+    #[default]
+    All,
+    /// Only include messages sent by the database owner
+    Sent,
+    /// Only include messages received by the database owner
+    Received,
+}
+
+/// The layout of the output of [`export_reactions()`]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ReactionFormat {
+    /// One JSON object per line, also known as NDJSON
+    #[default]
+    Lines,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+/// The columns written by [`export_reactions()`], in order
+const REACTION_COLUMNS: [&str; 7] = [
+    "target_guid",
+    "index",
+    "reaction",
+    "added",
+    "is_from_me",
+    "handle_id",
+    "date",
+];
+
+/// Options for [`export_json()`]
+#[derive(Debug, Default)]
+pub struct JsonOptions {
+    /// The layout of the output
+    pub format: JsonFormat,
+    /// How dates are written
+    pub date_format: JsonDateFormat,
+    /// If `true`, tapbacks and stickers are nested in `reactions` and `stickers` arrays on the message they target
+    /// instead of being written as messages
+    pub inline_reactions: bool,
+    /// Which messages to include, based on who sent them
+    pub direction: Direction,
+    /// Date filters for the messages to include
+    ///
+    /// Dates in the output are converted from [`QueryContext::scale`], so it must match the database.
+    pub query_context: QueryContext,
+}
+
+/// Stream every message in a database to `writer` as JSON, returning the number of messages written
+///
+/// Messages are written in date order one at a time, so the database is never fully loaded into memory.
+/// When [`JsonOptions::inline_reactions`] is set, the tapbacks and stickers are cached up front with [`Message::cache()`]
+/// and [`Message::cache_stickers()`].
+///
+/// Messages without any text are written with `"text":null`, but failing to parse the text of a message is an error.
+///
+/// # Example:
+///
+/// ```no_run
+/// use std::io::stdout;
+///
+/// use imessage_database::util::dirs::default_db_path;
+/// use imessage_database::tables::table::get_connection;
+/// use imessage_database::util::json::{export_json, JsonFormat, JsonOptions};
+///
+/// let db_path = default_db_path();
+/// let conn = get_connection(&db_path).unwrap();
+/// let options = JsonOptions {
+///     format: JsonFormat::Lines,
+///     inline_reactions: true,
+///     ..Default::default()
+/// };
+/// export_json(&conn, stdout().lock(), &options).unwrap();
+/// ```
+pub fn export_json<W: Write>(
+    db: &Connection,
+    mut writer: W,
+    options: &JsonOptions,
+) -> Result<u64, JsonError> {
+    let scale = options.query_context.scale;
+    let dates = DateEncoding {
+        format: options.date_format,
+        scale,
+        offset: get_offset(),
+    };
+    let (reactions, stickers) = if options.inline_reactions {
+        (
+            Message::cache(db).map_err(JsonError::Table)?,
+            Message::cache_stickers(db).map_err(JsonError::Table)?,
+        )
+    } else {
+        (HashMap::new(), HashMap::new())
+    };
+
+    // Inlined tapbacks and stickers are written with the message they target, so skip their rows
+    let context = QueryContext {
+        start: options.query_context.start,
+        end: options.query_context.end,
+        include_reactions: options.query_context.include_reactions && !options.inline_reactions,
+        scale,
+    };
+
+    let mut statement = Message::stream_rows(db, &context).map_err(JsonError::Table)?;
+    let messages = statement
+        .query_map([], |row| Ok(Message::from_row(row)))
+        .map_err(|why| JsonError::Table(TableError::Messages(why)))?;
+
+    if options.format == JsonFormat::Array {
+        writer.write_all(b"[").map_err(JsonError::Write)?;
+    }
+
+    let mut written: u64 = 0;
+    for message in messages {
+        let mut message = Message::extract(message).map_err(JsonError::Table)?;
+        let include = match options.direction {
+            Direction::All => true,
+            Direction::Sent => message.is_from_me(),
+            Direction::Received => !message.is_from_me(),
+        };
+        if !include {
+            continue;
+        }
+
+        match message.generate_text(db) {
+            Ok(_) | Err(MessageError::NoText) => {}
+            Err(why) => return Err(JsonError::Message(why)),
+        }
+        let mut object = message_object(&message, &dates);
+        if options.inline_reactions {
+            object.push((
+                "reactions",
+                reactions_array(reactions.get(&message.guid), &dates),
+            ));
+            object.push((
+                "stickers",
+                stickers_array(stickers.get(&message.guid), &dates),
+            ));
+        }
+
+        let separator: &[u8] = match options.format {
+            JsonFormat::Array if written > 0 => b",\n",
+            JsonFormat::Array => b"\n",
+            JsonFormat::Lines => b"",
+        };
+        writer.write_all(separator).map_err(JsonError::Write)?;
+        writer
+            .write_all(encode_object(&object).as_bytes())
+            .map_err(JsonError::Write)?;
+        if options.format == JsonFormat::Lines {
+            writer.write_all(b"\n").map_err(JsonError::Write)?;
+        }
+        written += 1;
+    }
+
+    if options.format == JsonFormat::Array {
+        let end: &[u8] = if written > 0 { b"\n]\n" } else { b"]\n" };
+        writer.write_all(end).map_err(JsonError::Write)?;
+    }
+    writer.flush().map_err(JsonError::Write)?;
+
+    Ok(written)
+}
+
+/// Write each tapback in a reaction cache to `writer` as its own event, returning the number of events written
+///
+/// `reactions` is the cache built by [`Message::cache()`]. Each event contains the GUID of the message that was
+/// reacted to, the index of the body component, the [`Reaction`](crate::message_types::variants::Reaction), whether
+/// it was added or removed, who sent it, and its date. Events are ordered by date. This is useful for analyzing
+/// reactions separately from the messages they target, i.e. for charting how often each reaction is used.
+///
+/// Dates are converted from `scale`, which should be the database's [`Message::timestamp_scale()`].
+///
+/// # Example:
+///
+/// ```no_run
+/// use std::io::stdout;
+///
+/// use imessage_database::util::dirs::default_db_path;
+/// use imessage_database::tables::{messages::Message, table::{get_connection, Cacheable}};
+/// use imessage_database::util::json::{export_reactions, JsonDateFormat, ReactionFormat};
+///
+/// let db_path = default_db_path();
+/// let conn = get_connection(&db_path).unwrap();
+/// let reactions = Message::cache(&conn).unwrap();
+/// let scale = Message::timestamp_scale(&conn).unwrap();
+/// export_reactions(&reactions, stdout().lock(), ReactionFormat::Csv, JsonDateFormat::Unix, scale).unwrap();
+/// ```
+pub fn export_reactions<W: Write>(
+    reactions: &HashMap<String, HashMap<usize, Vec<Message>>>,
+    mut writer: W,
+    format: ReactionFormat,
+    date_format: JsonDateFormat,
+    scale: TimestampScale,
+) -> Result<u64, JsonError> {
+    let dates = DateEncoding {
+        format: date_format,
+        scale,
+        offset: get_offset(),
+    };
+
+    let mut events: Vec<(&str, &Message)> = reactions
+        .iter()
+        .flat_map(|(target, indexes)| {
+            indexes
+                .values()
+                .flatten()
+                .map(move |reaction| (target.as_str(), reaction))
+        })
+        .collect();
+    events.sort_by(|(_, a), (_, b)| a.date.cmp(&b.date).then_with(|| a.guid.cmp(&b.guid)));
+
+    if format == ReactionFormat::Csv {
+        writeln!(writer, "{}", REACTION_COLUMNS.join(",")).map_err(JsonError::Write)?;
+    }
+
+    let mut written: u64 = 0;
+    for (target, reaction) in events {
+        let Variant::Reaction(idx, added, kind) = reaction.variant() else {
+            continue;
+        };
+
+        let line = match format {
+            ReactionFormat::Lines => {
+                let values = [
+                    encode_string(target),
+                    idx.to_string(),
+                    encode_string(&format!("{kind:?}")),
+                    added.to_string(),
+                    reaction.is_from_me().to_string(),
+                    encode_option(reaction.handle_id),
+                    encode_date(reaction.date, &dates),
+                ];
+                let fields: Vec<(&str, String)> =
+                    REACTION_COLUMNS.iter().copied().zip(values).collect();
+                encode_object(&fields)
+            }
+            ReactionFormat::Csv => [
+                encode_csv(target),
+                idx.to_string(),
+                format!("{kind:?}"),
+                added.to_string(),
+                reaction.is_from_me().to_string(),
+                reaction
+                    .handle_id
+                    .map(|handle_id| handle_id.to_string())
+                    .unwrap_or_default(),
+                match encode_date(reaction.date, &dates).as_str() {
+                    "null" => String::new(),
+                    date => encode_csv(date.trim_matches('"')),
+                },
+            ]
+            .join(","),
+        };
+        writeln!(writer, "{line}").map_err(JsonError::Write)?;
+        written += 1;
+    }
+    writer.flush().map_err(JsonError::Write)?;
+
+    Ok(written)
+}
+
+/// Build the JSON fields for a message
+fn message_object(message: &Message, dates: &DateEncoding) -> Vec<(&'static str, String)> {
+    vec![
+        ("rowid", message.rowid.to_string()),
+        ("guid", encode_string(&message.guid)),
+        ("chat_id", encode_option(message.chat_id)),
+        ("handle_id", encode_option(message.handle_id)),
+        ("is_from_me", message.is_from_me().to_string()),
+        ("service", encode_option_string(message.service.as_deref())),
+        ("date", encode_date(message.date, dates)),
+        ("date_read", encode_date(message.date_read, dates)),
+        ("date_delivered", encode_date(message.date_delivered, dates)),
+        ("subject", encode_option_string(message.subject())),
+        ("text", encode_option_string(message.text.as_deref())),
+        (
+            "thread_originator_guid",
+            encode_option_string(message.thread_originator_guid.as_deref()),
+        ),
+        (
+            "associated_message_guid",
+            encode_option_string(message.associated_message_guid.as_deref()),
+        ),
+        (
+            "associated_message_type",
+            encode_option(message.associated_message_type),
+        ),
+        (
+            "balloon_bundle_id",
+            encode_option_string(message.balloon_bundle_id.as_deref()),
+        ),
+        ("num_attachments", message.num_attachments.to_string()),
+    ]
+}
+
+/// Build a JSON array from the messages associated with a message, ordered by body component index
+///
+/// Messages that `encode` returns `None` for are skipped.
+fn associated_array(
+    associated: Option<&HashMap<usize, Vec<Message>>>,
+    encode: impl Fn(&Message) -> Option<String>,
+) -> String {
+    let mut indexes: Vec<&usize> = associated
+        .map(|map| map.keys().collect())
+        .unwrap_or_default();
+    indexes.sort_unstable();
+
+    let items: Vec<String> = indexes
+        .into_iter()
+        .flat_map(|idx| {
+            associated
+                .and_then(|map| map.get(idx))
+                .into_iter()
+                .flatten()
+        })
+        .filter_map(encode)
+        .collect();
+
+    format!("[{}]", items.join(","))
+}
+
+/// Build the JSON array of tapbacks on a message, ordered by body component index
+fn reactions_array(
+    reactions: Option<&HashMap<usize, Vec<Message>>>,
+    dates: &DateEncoding,
+) -> String {
+    associated_array(reactions, |reaction| match reaction.variant() {
+        Variant::Reaction(idx, added, kind) => Some(encode_object(&[
+            ("guid", encode_string(&reaction.guid)),
+            ("index", idx.to_string()),
+            ("reaction", encode_string(&format!("{kind:?}"))),
+            ("added", added.to_string()),
+            ("is_from_me", reaction.is_from_me().to_string()),
+            ("handle_id", encode_option(reaction.handle_id)),
+            ("date", encode_date(reaction.date, dates)),
+        ])),
+        _ => None,
+    })
+}
+
+/// Build the JSON array of stickers placed on a message, ordered by body component index
+fn stickers_array(stickers: Option<&HashMap<usize, Vec<Message>>>, dates: &DateEncoding) -> String {
+    associated_array(stickers, |sticker| match sticker.variant() {
+        Variant::Sticker(idx) => Some(encode_object(&[
+            ("guid", encode_string(&sticker.guid)),
+            ("index", idx.to_string()),
+            ("is_from_me", sticker.is_from_me().to_string()),
+            ("handle_id", encode_option(sticker.handle_id)),
+            ("date", encode_date(sticker.date, dates)),
+        ])),
+        _ => None,
+    })
+}
+
+/// How to encode the dates read from the table
+struct DateEncoding {
+    /// The output format
+    format: JsonDateFormat,
+    /// The unit the table stores its timestamps in
+    scale: TimestampScale,
+    /// The offset from [`get_offset()`]
+    offset: i64,
+}
+
+/// Encode a list of fields whose values are already JSON as a JSON object
+fn encode_object(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{value}", encode_string(key)))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Encode a date from the table, using `null` for dates that are not set
+fn encode_date(stamp: i64, dates: &DateEncoding) -> String {
+    if stamp == 0 {
+        return String::from("null");
+    }
+    let date = || get_local_time(&dates.scale.to_nanoseconds(stamp), &dates.offset).ok();
+    match dates.format {
+        JsonDateFormat::Apple => stamp.to_string(),
+        JsonDateFormat::Unix => encode_option(date().map(|date| date.timestamp())),
+        JsonDateFormat::Rfc3339 => {
+            encode_option_string(date().map(|date| date.to_rfc3339()).as_deref())
+        }
+    }
+}
+
+/// Encode an optional number, using `null` for `None`
+fn encode_option<T: ToString>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => String::from("null"),
+    }
+}
+
+/// Encode an optional string, using `null` for `None`
+fn encode_option_string(value: Option<&str>) -> String {
+    match value {
+        Some(value) => encode_string(value),
+        None => String::from("null"),
+    }
+}
+
+/// Encode a CSV field, quoting it if it contains a separator, quote, or line break
+fn encode_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    value.to_string()
+}
+
+/// Encode a string as a quoted and escaped JSON string
+fn encode_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::json::JsonError,
+        tables::{
+            messages::Message,
+            table::Cacheable,
+            test_utils::{TestDatabase, TestMessage},
+        },
+        util::query_context::QueryContext,
+        util::{
+            dates::{get_offset, TimestampScale},
+            json::{
+                encode_csv, encode_string, export_json, export_reactions, Direction,
+                JsonDateFormat, JsonFormat, JsonOptions, ReactionFormat,
+            },
+        },
+    };
+
+    const TARGET: &str = "A44CE9D7-AAAA-BBBB-CCCC-000000000001";
+
+    fn fixture() -> TestDatabase {
+        let fixture = TestDatabase::new();
+        let chat = fixture.add_chat("+15558675309", "iMessage", &[]);
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: TARGET,
+                text: Some("Hello \"world\""),
+                date: 1,
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                text: Some("Hi"),
+                date: 2,
+                is_from_me: true,
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            Some(chat),
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000003",
+                date: 3,
+                is_from_me: true,
+                associated_message_guid: Some(&format!("p:0/{TARGET}")),
+                associated_message_type: 2000,
+                ..Default::default()
+            },
+        );
+        fixture
+    }
+
+    fn export(fixture: &TestDatabase, options: &JsonOptions) -> (u64, String) {
+        let mut out = vec![];
+        let written = export_json(fixture.connection(), &mut out, options).unwrap();
+        (written, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn can_export_array() {
+        let fixture = fixture();
+        let options = JsonOptions {
+            date_format: JsonDateFormat::Apple,
+            ..Default::default()
+        };
+        let (written, out) = export(&fixture, &options);
+
+        assert_eq!(written, 3);
+        assert!(out.starts_with("[\n{\"rowid\":1,"));
+        assert!(out.ends_with("}\n]\n"));
+        assert_eq!(out.lines().count(), 5);
+        assert!(out.contains("\"text\":\"Hello \\\"world\\\"\""));
+        assert!(out.contains("\"date\":1,\"date_read\":null"));
+        assert!(!out.contains("\"reactions\""));
+    }
+
+    #[test]
+    fn can_export_lines() {
+        let fixture = fixture();
+        let options = JsonOptions {
+            format: JsonFormat::Lines,
+            ..Default::default()
+        };
+        let (written, out) = export(&fixture, &options);
+
+        assert_eq!(written, 3);
+        assert_eq!(out.lines().count(), 3);
+        assert!(out
+            .lines()
+            .all(|line| line.starts_with('{') && line.ends_with('}')));
+    }
+
+    #[test]
+    fn can_export_inline_reactions() {
+        let fixture = fixture();
+        let options = JsonOptions {
+            format: JsonFormat::Lines,
+            date_format: JsonDateFormat::Apple,
+            inline_reactions: true,
+            ..Default::default()
+        };
+        let (written, out) = export(&fixture, &options);
+
+        assert_eq!(written, 2);
+        let first = out.lines().next().unwrap();
+        assert!(first.ends_with(
+            "\"reactions\":[{\"guid\":\"A44CE9D7-AAAA-BBBB-CCCC-000000000003\",\"index\":0,\"reaction\":\"Loved\",\"added\":true,\"is_from_me\":true,\"handle_id\":0,\"date\":3}],\"stickers\":[]}"
+        ));
+        assert!(out
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("\"reactions\":[],\"stickers\":[]}"));
+    }
+
+    #[test]
+    fn can_export_inline_stickers() {
+        let fixture = fixture();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000004",
+                date: 4,
+                handle_id: 1,
+                associated_message_guid: Some(&format!("p:0/{TARGET}")),
+                associated_message_type: 1000,
+                ..Default::default()
+            },
+        );
+        let options = JsonOptions {
+            format: JsonFormat::Lines,
+            date_format: JsonDateFormat::Apple,
+            inline_reactions: true,
+            ..Default::default()
+        };
+        let (written, out) = export(&fixture, &options);
+
+        assert_eq!(written, 2);
+        assert!(out.lines().next().unwrap().ends_with(
+            "\"stickers\":[{\"guid\":\"A44CE9D7-AAAA-BBBB-CCCC-000000000004\",\"index\":0,\"is_from_me\":false,\"handle_id\":1,\"date\":4}]}"
+        ));
+    }
+
+    #[test]
+    fn cant_export_unparseable_text() {
+        let fixture = TestDatabase::new();
+        let rowid = fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                date: 1,
+                ..Default::default()
+            },
+        );
+        fixture
+            .connection()
+            .execute(
+                "UPDATE message SET attributedBody = ?1 WHERE ROWID = ?2",
+                rusqlite::params![vec![0u8, 1, 2, 3], rowid],
+            )
+            .unwrap();
+
+        let mut out = vec![];
+        let result = export_json(fixture.connection(), &mut out, &JsonOptions::default());
+        assert!(matches!(result, Err(JsonError::Message(_))));
+    }
+
+    #[test]
+    fn can_export_dates_in_seconds() {
+        let fixture = TestDatabase::new();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: TARGET,
+                text: Some("Hello"),
+                date: 674526582,
+                ..Default::default()
+            },
+        );
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000002",
+                date: 674526590,
+                associated_message_guid: Some(&format!("p:0/{TARGET}")),
+                associated_message_type: 2000,
+                ..Default::default()
+            },
+        );
+        let options = JsonOptions {
+            format: JsonFormat::Lines,
+            date_format: JsonDateFormat::Unix,
+            inline_reactions: true,
+            query_context: QueryContext {
+                scale: TimestampScale::Seconds,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let (written, out) = export(&fixture, &options);
+
+        assert_eq!(written, 1);
+        let message_date = 674526582 + get_offset();
+        let reaction_date = 674526590 + get_offset();
+        assert!(out.contains(&format!("\"date\":{message_date},\"date_read\":null")));
+        assert!(out.contains(&format!("\"date\":{reaction_date}}}],\"stickers\"")));
+
+        let reactions = Message::cache(fixture.connection()).unwrap();
+        let mut out = vec![];
+        export_reactions(
+            &reactions,
+            &mut out,
+            ReactionFormat::Csv,
+            JsonDateFormat::Unix,
+            TimestampScale::Seconds,
+        )
+        .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with(&format!(",{reaction_date}\n")));
+    }
+
+    #[test]
+    fn can_export_direction() {
+        let fixture = fixture();
+        let mut options = JsonOptions {
+            format: JsonFormat::Lines,
+            direction: Direction::Received,
+            ..Default::default()
+        };
+        assert_eq!(export(&fixture, &options).0, 1);
+
+        options.direction = Direction::Sent;
+        assert_eq!(export(&fixture, &options).0, 2);
+    }
+
+    #[test]
+    fn can_export_empty() {
+        let fixture = TestDatabase::new();
+        let (written, out) = export(&fixture, &JsonOptions::default());
+
+        assert_eq!(written, 0);
+        assert_eq!(out, "[]\n");
+    }
+
+    #[test]
+    fn can_encode_string() {
+        assert_eq!(
+            encode_string("a\"b\\c\nd\u{1}é"),
+            "\"a\\\"b\\\\c\\nd\\u0001é\""
+        );
+    }
+
+    fn export_reaction_events(fixture: &TestDatabase, format: ReactionFormat) -> (u64, String) {
+        let reactions = Message::cache(fixture.connection()).unwrap();
+        let mut out = vec![];
+        let written = export_reactions(
+            &reactions,
+            &mut out,
+            format,
+            JsonDateFormat::Apple,
+            TimestampScale::Nanoseconds,
+        )
+        .unwrap();
+        (written, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn can_export_reactions_lines() {
+        let fixture = fixture();
+        let (written, out) = export_reaction_events(&fixture, ReactionFormat::Lines);
+
+        assert_eq!(written, 1);
+        assert_eq!(
+            out,
+            format!("{{\"target_guid\":\"{TARGET}\",\"index\":0,\"reaction\":\"Loved\",\"added\":true,\"is_from_me\":true,\"handle_id\":0,\"date\":3}}\n")
+        );
+    }
+
+    #[test]
+    fn can_export_reactions_csv() {
+        let fixture = fixture();
+        fixture.add_message(
+            None,
+            &TestMessage {
+                guid: "A44CE9D7-AAAA-BBBB-CCCC-000000000004",
+                date: 4,
+                handle_id: 1,
+                associated_message_guid: Some(&format!("p:0/{TARGET}")),
+                associated_message_type: 3000,
+                ..Default::default()
+            },
+        );
+        let (written, out) = export_reaction_events(&fixture, ReactionFormat::Csv);
+
+        assert_eq!(written, 2);
+        assert_eq!(
+            out,
+            format!("target_guid,index,reaction,added,is_from_me,handle_id,date\n{TARGET},0,Loved,true,true,0,3\n{TARGET},0,Loved,false,false,1,4\n")
+        );
+    }
+
+    #[test]
+    fn can_export_reactions_empty() {
+        let fixture = TestDatabase::new();
+        let (written, out) = export_reaction_events(&fixture, ReactionFormat::Lines);
+
+        assert_eq!(written, 0);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn can_encode_csv() {
+        assert_eq!(encode_csv("plain"), "plain");
+        assert_eq!(encode_csv("a,\"b\""), "\"a,\"\"b\"\"\"");
+        assert_eq!(encode_csv("a\nb"), "\"a\nb\"");
+    }
+}
@@ -4,6 +4,7 @@
 
 pub mod dates;
 pub mod dirs;
+pub mod json;
 pub mod output;
 pub mod platform;
 pub mod plist;
@@ -5,16 +5,38 @@ use chrono::prelude::*;
 
 use crate::{
     error::query_context::QueryContextError,
-    util::dates::{get_offset, TIMESTAMP_FACTOR},
+    util::dates::{get_offset, TimestampScale, TIMESTAMP_FACTOR},
 };
 
-#[derive(Debug, Default, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq)]
 /// Represents filter configurations for a SQL query.
 pub struct QueryContext {
     /// The start date filter. Only messages sent on or after this date will be included.
     pub start: Option<i64>,
     /// The end date filter. Only messages sent before this date will be included.
     pub end: Option<i64>,
+    /// Whether to include reactions and stickers placed on other messages when streaming messages. Defaults to `true`.
+    ///
+    /// These rows are rendered in context with the message they target, which is fetched separately
+    /// with [`Message::get_reactions()`](crate::tables::messages::Message::get_reactions), so exporters
+    /// can set this to `false` to skip them. This does not affect [`Self::has_filters()`].
+    pub include_reactions: bool,
+    /// The unit the database stores its timestamps in, found with [`Message::timestamp_scale()`](crate::tables::messages::Message::timestamp_scale)
+    ///
+    /// The start and end dates are stored in nanoseconds and converted to this scale when the filters are generated,
+    /// so they compare correctly against the raw `date` column of databases that use coarser units.
+    pub scale: TimestampScale,
+}
+
+impl Default for QueryContext {
+    fn default() -> Self {
+        Self {
+            start: None,
+            end: None,
+            include_reactions: true,
+            scale: TimestampScale::default(),
+        }
+    }
 }
 
 impl QueryContext {
@@ -110,10 +132,10 @@ impl QueryContext {
     /// ```
     pub fn generate_filter_statement(&self, field: &str) -> String {
         let mut filters = String::new();
-        if let Some(start) = self.start {
+        if let Some(start) = self.start.map(|start| self.scale.from_nanoseconds(start)) {
             filters.push_str(&format!("    {field} >= {start}"));
         }
-        if let Some(end) = self.end {
+        if let Some(end) = self.end.map(|end| self.scale.from_nanoseconds(end)) {
             if !filters.is_empty() {
                 filters.push_str(" AND ");
             }
@@ -137,7 +159,7 @@ mod use_tests {
     use chrono::prelude::*;
 
     use crate::util::{
-        dates::{format, get_offset, TIMESTAMP_FACTOR},
+        dates::{format, get_offset, TimestampScale, TIMESTAMP_FACTOR},
         query_context::QueryContext,
     };
 
@@ -146,6 +168,7 @@ mod use_tests {
         let context = QueryContext::default();
         assert!(context.start.is_none());
         assert!(context.end.is_none());
+        assert!(context.include_reactions);
         assert!(!context.has_filters());
     }
 
@@ -233,6 +256,23 @@ mod use_tests {
         assert!(context.has_filters());
     }
 
+    #[test]
+    fn can_create_both_scaled() {
+        let mut context = QueryContext {
+            scale: TimestampScale::Seconds,
+            ..Default::default()
+        };
+        context.set_start("2020-01-01").unwrap();
+        context.set_end("2020-02-02").unwrap();
+
+        let start = context.start.unwrap() / TIMESTAMP_FACTOR;
+        let end = context.end.unwrap() / TIMESTAMP_FACTOR;
+        assert_eq!(
+            context.generate_filter_statement("m.date"),
+            format!(" WHERE\n                     m.date >= {start} AND     m.date <= {end}")
+        );
+    }
+
     #[test]
     fn can_create_invalid_start() {
         let mut context = QueryContext::default();
@@ -32,6 +32,7 @@ pub const OPTION_CUSTOM_NAME: &str = "custom-name";
 pub const OPTION_PLATFORM: &str = "platform";
 pub const OPTION_BYPASS_FREE_SPACE_CHECK: &str = "ignore-disk-warning";
 pub const OPTION_USE_CALLER_ID: &str = "use-caller-id";
+pub const OPTION_TRUNCATE: &str = "truncate";
 
 // Other CLI Text
 pub const SUPPORTED_FILE_TYPES: &str = "txt, html";
@@ -69,6 +70,8 @@ pub struct Options {
     pub platform: Platform,
     /// If true, disable the free disk space check
     pub ignore_disk_space: bool,
+    /// The maximum number of characters of a message's text to include in `txt` exports
+    pub truncate: Option<usize>,
 }
 
 impl Options {
@@ -86,6 +89,7 @@ impl Options {
         let use_caller_id = args.get_flag(OPTION_USE_CALLER_ID);
         let platform_type: Option<&String> = args.get_one(OPTION_PLATFORM);
         let ignore_disk_space = args.get_flag(OPTION_BYPASS_FREE_SPACE_CHECK);
+        let truncate: Option<&String> = args.get_one(OPTION_TRUNCATE);
 
         // Build the export type
         let export_type: Option<ExportType> = match export_file_type {
@@ -123,6 +127,11 @@ impl Options {
                 "Option {OPTION_USE_CALLER_ID} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
             )));
         }
+        if truncate.is_some() && export_file_type.is_none() {
+            return Err(RuntimeError::InvalidOptions(format!(
+                "Option {OPTION_TRUNCATE} is enabled, which requires `--{OPTION_EXPORT_TYPE}`"
+            )));
+        }
 
         // Warn the user if they are exporting to a file type for which lazy loading has no effect
         if no_lazy && export_file_type != Some(&"html".to_string()) {
@@ -131,6 +140,13 @@ impl Options {
             );
         }
 
+        // Warn the user if they are exporting to a file type for which truncation has no effect
+        if truncate.is_some() && export_file_type != Some(&"txt".to_string()) {
+            eprintln!(
+                "Option {OPTION_TRUNCATE} is enabled, but the format specified is not `txt`!"
+            );
+        }
+
         // Ensure that if diagnostics are enabled, no other options are
         if diagnostic && attachment_manager_type.is_some() {
             return Err(RuntimeError::InvalidOptions(format!(
@@ -225,6 +241,16 @@ impl Options {
             None => AttachmentManager::default(),
         };
 
+        // Parse the maximum message length
+        let truncate = match truncate {
+            Some(max_chars) => Some(max_chars.parse::<usize>().map_err(|_| {
+                RuntimeError::InvalidOptions(format!(
+                    "{max_chars} is not a valid number of characters for {OPTION_TRUNCATE}!"
+                ))
+            })?),
+            None => None,
+        };
+
         // Validate the provided export path
         let export_path = validate_path(user_export_path, &export_type.as_ref())?;
 
@@ -241,6 +267,7 @@ impl Options {
             use_caller_id,
             platform,
             ignore_disk_space,
+            truncate,
         })
     }
 
@@ -410,6 +437,14 @@ fn get_command() -> Command {
                 .action(ArgAction::SetTrue)
                 .display_order(12)
         )
+        .arg(
+            Arg::new(OPTION_TRUNCATE)
+                .short('t')
+                .long(OPTION_TRUNCATE)
+                .help("Shorten the text of each message in `txt` exports to at most this many characters\nShortened text ends with a `[truncated N chars]` marker\n")
+                .display_order(13)
+                .value_name("chars"),
+        )
 }
 
 /// Parse arguments from the command line
@@ -455,6 +490,7 @@ mod arg_tests {
             use_caller_id: false,
             platform: Platform::default(),
             ignore_disk_space: false,
+            truncate: None,
         };
 
         assert_eq!(actual, expected);
@@ -566,6 +602,7 @@ mod arg_tests {
             use_caller_id: false,
             platform: Platform::default(),
             ignore_disk_space: false,
+            truncate: None,
         };
 
         assert_eq!(actual, expected);
@@ -598,11 +635,51 @@ mod arg_tests {
             use_caller_id: false,
             platform: Platform::default(),
             ignore_disk_space: false,
+            truncate: None,
         };
 
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn can_build_option_truncate() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "-t", "500"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args).unwrap();
+
+        assert_eq!(actual.truncate, Some(500));
+    }
+
+    #[test]
+    fn cant_build_option_truncate_invalid() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-f", "txt", "-t", "many"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
+    #[test]
+    fn cant_build_option_truncate_no_export_type() {
+        // Get matches from sample args
+        let cli_args: Vec<&str> = vec!["imessage-exporter", "-t", "500"];
+        let command = get_command();
+        let args = command.get_matches_from(cli_args);
+
+        // Build the Options
+        let actual = Options::from_args(&args);
+
+        assert!(actual.is_err());
+    }
+
     #[test]
     fn cant_build_option_attachment_manager_no_export_type() {
         // Get matches from sample args
@@ -718,6 +795,7 @@ mod arg_tests {
             use_caller_id: false,
             platform: Platform::default(),
             ignore_disk_space: false,
+            truncate: None,
         };
 
         assert_eq!(actual, expected);
@@ -747,6 +825,7 @@ mod arg_tests {
             use_caller_id: true,
             platform: Platform::default(),
             ignore_disk_space: false,
+            truncate: None,
         };
 
         assert_eq!(actual, expected);
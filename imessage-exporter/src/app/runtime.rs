@@ -30,7 +30,10 @@ use imessage_database::{
             MAX_LENGTH, ME, ORPHANED, UNKNOWN,
         },
     },
-    util::{dates::get_offset, size::format_file_size},
+    util::{
+        dates::{get_offset, TimestampScale},
+        size::format_file_size,
+    },
 };
 
 /// Stores the application state and handles application lifecycle
@@ -47,10 +50,14 @@ pub struct Config {
     pub real_participants: HashMap<i32, i32>,
     /// Messages that are reactions to other messages
     pub reactions: HashMap<String, HashMap<usize, Vec<Message>>>,
+    /// Messages that are stickers placed on other messages
+    pub stickers: HashMap<String, HashMap<usize, Vec<Message>>>,
     /// App configuration options
     pub options: Options,
     /// Global date offset used by the iMessage database:
     pub offset: i64,
+    /// The unit the iMessage database stores timestamps in
+    pub timestamp_scale: TimestampScale,
     /// The connection we use to query the database
     pub db: Connection,
     /// Converter type used when converting image files
@@ -74,6 +81,17 @@ impl Config {
         }
     }
 
+    /// Get the tapbacks and stickers placed on the body component `idx` of a message, in the order they were sent
+    pub fn reactions_to(&self, message: &Message, idx: usize) -> Vec<&Message> {
+        let mut reactions: Vec<&Message> = [&self.reactions, &self.stickers]
+            .into_iter()
+            .filter_map(|cache| cache.get(&message.guid)?.get(&idx))
+            .flatten()
+            .collect();
+        reactions.sort_by_key(|reaction| reaction.date);
+        reactions
+    }
+
     /// Get the attachment path for the current session
     pub fn attachment_path(&self) -> PathBuf {
         let mut path = self.options.export_path.clone();
@@ -192,7 +210,7 @@ impl Config {
     /// let options = Options::from_args(&args);
     /// let app = Config::new(options).unwrap();
     /// ```
-    pub fn new(options: Options) -> Result<Config, RuntimeError> {
+    pub fn new(mut options: Options) -> Result<Config, RuntimeError> {
         let conn = get_connection(&options.get_db_path()).map_err(RuntimeError::DatabaseError)?;
         eprintln!("Building cache...");
         eprintln!("[1/4] Caching chats...");
@@ -203,7 +221,18 @@ impl Config {
         eprintln!("[3/4] Caching participants...");
         let participants = Handle::cache(&conn).map_err(RuntimeError::DatabaseError)?;
         eprintln!("[4/4] Caching reactions...");
-        let reactions = Message::cache(&conn).map_err(RuntimeError::DatabaseError)?;
+        let timestamp_scale =
+            Message::timestamp_scale(&conn).map_err(RuntimeError::DatabaseError)?;
+        let mut reactions = Message::cache(&conn).map_err(RuntimeError::DatabaseError)?;
+        let mut stickers = Message::cache_stickers(&conn).map_err(RuntimeError::DatabaseError)?;
+        [&mut reactions, &mut stickers]
+            .into_iter()
+            .flat_map(HashMap::values_mut)
+            .flat_map(HashMap::values_mut)
+            .flatten()
+            .for_each(|message| message.normalize_dates(timestamp_scale));
+        // Date filters are compared against the raw `date` column, so they must use the same scale
+        options.query_context.scale = timestamp_scale;
         eprintln!("Cache built!");
 
         // Only attempt to create a converter if we need it
@@ -220,8 +249,10 @@ impl Config {
             real_participants: Handle::dedupe(&participants),
             participants,
             reactions,
+            stickers,
             options,
             offset: get_offset(),
+            timestamp_scale,
             db: conn,
             converter,
         })
@@ -380,7 +411,10 @@ mod filename_tests {
             chat::Chat,
             table::{get_connection, MAX_LENGTH},
         },
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+        util::{
+            dates::TimestampScale, dirs::default_db_path, platform::Platform,
+            query_context::QueryContext,
+        },
     };
     use std::{
         collections::{BTreeSet, HashMap},
@@ -401,6 +435,7 @@ mod filename_tests {
             use_caller_id: false,
             platform: Platform::macOS,
             ignore_disk_space: false,
+            truncate: None,
         }
     }
 
@@ -422,8 +457,10 @@ mod filename_tests {
             participants: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
+            stickers: HashMap::new(),
             options,
             offset: 0,
+            timestamp_scale: TimestampScale::default(),
             db: connection,
             converter: Some(crate::app::converter::Converter::Sips),
         }
@@ -614,7 +651,10 @@ mod who_tests {
     use crate::{app::attachment_manager::AttachmentManager, Config, Options};
     use imessage_database::{
         tables::{chat::Chat, messages::Message, table::get_connection},
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+        util::{
+            dates::TimestampScale, dirs::default_db_path, platform::Platform,
+            query_context::QueryContext,
+        },
     };
     use std::{collections::HashMap, path::PathBuf};
 
@@ -632,6 +672,7 @@ mod who_tests {
             use_caller_id: false,
             platform: Platform::macOS,
             ignore_disk_space: false,
+            truncate: None,
         }
     }
 
@@ -653,8 +694,10 @@ mod who_tests {
             participants: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
+            stickers: HashMap::new(),
             options,
             offset: 0,
+            timestamp_scale: TimestampScale::default(),
             db: connection,
             converter: Some(crate::app::converter::Converter::Sips),
         }
@@ -868,7 +911,10 @@ mod directory_tests {
     use crate::{app::attachment_manager::AttachmentManager, Config, Options};
     use imessage_database::{
         tables::{attachment::Attachment, table::get_connection},
-        util::{dirs::default_db_path, platform::Platform, query_context::QueryContext},
+        util::{
+            dates::TimestampScale, dirs::default_db_path, platform::Platform,
+            query_context::QueryContext,
+        },
     };
     use std::{collections::HashMap, path::PathBuf};
 
@@ -886,6 +932,7 @@ mod directory_tests {
             use_caller_id: false,
             platform: Platform::macOS,
             ignore_disk_space: false,
+            truncate: None,
         }
     }
 
@@ -898,8 +945,10 @@ mod directory_tests {
             participants: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
+            stickers: HashMap::new(),
             options,
             offset: 0,
+            timestamp_scale: TimestampScale::default(),
             db: connection,
             converter: Some(crate::app::converter::Converter::Sips),
         }
@@ -33,11 +33,14 @@ use imessage_database::{
     },
     tables::{
         attachment::{Attachment, MediaType},
-        messages::{models::BubbleComponent, Message},
+        messages::{
+            models::{BubbleComponent, Service},
+            Message,
+        },
         table::{Table, FITNESS_RECEIVER, ME, ORPHANED, YOU},
     },
     util::{
-        dates::{format, get_local_time, readable_diff, TIMESTAMP_FACTOR},
+        dates::{format, get_local_time, readable_diff, readable_duration, TIMESTAMP_FACTOR},
         plist::parse_plist,
     },
 };
@@ -104,6 +107,7 @@ impl<'a> Exporter<'a> for HTML<'a> {
 
         for message in messages {
             let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+            msg.normalize_dates(self.config.timestamp_scale);
 
             // Early escape if we try and render the same message GUID twice
             // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
@@ -207,9 +211,18 @@ impl<'a> Writer<'a> for HTML<'a> {
 
         // Start message div
         if message.is_from_me() {
+            let chat_service = Service::from_name(
+                message
+                    .chat_id
+                    .and_then(|chat_id| self.config.chatrooms.get(&chat_id))
+                    .and_then(|chat| chat.service_name.as_deref()),
+            );
             self.add_line(
                 &mut formatted_message,
-                &format!("<div class=\"sent {:?}\">", message.service()),
+                &format!(
+                    "<div class=\"sent {:?}\">",
+                    message.effective_service(chat_service)
+                ),
                 "",
                 "",
             );
@@ -272,12 +285,16 @@ impl<'a> Writer<'a> for HTML<'a> {
         let message_parts = message.body();
         let mut attachments = Attachment::from_message(&self.config.db, message)?;
         let mut replies = message.get_replies(&self.config.db)?;
+        replies
+            .values_mut()
+            .flatten()
+            .for_each(|reply| reply.normalize_dates(self.config.timestamp_scale));
 
         // Index of where we are in the attachment Vector
         let mut attachment_index: usize = 0;
 
         // Add message subject
-        if let Some(subject) = &message.subject {
+        if let Some(subject) = message.subject() {
             // Add message sender
             self.add_line(
                 &mut formatted_message,
@@ -378,7 +395,7 @@ impl<'a> Writer<'a> for HTML<'a> {
                         }
                     }
                 }
-                BubbleComponent::Attachment => {
+                BubbleComponent::Attachment(_) => {
                     match attachments.get_mut(attachment_index) {
                         Some(attachment) => {
                             if attachment.is_sticker {
@@ -462,36 +479,35 @@ impl<'a> Writer<'a> for HTML<'a> {
             }
 
             // Handle Reactions
-            if let Some(reactions_map) = self.config.reactions.get(&message.guid) {
-                if let Some(reactions) = reactions_map.get(&idx) {
-                    let mut formatted_reactions = String::new();
-
-                    reactions
-                        .iter()
-                        .try_for_each(|reaction| -> Result<(), TableError> {
-                            let formatted = self.format_reaction(reaction)?;
-                            if !formatted.is_empty() {
-                                self.add_line(
-                                    &mut formatted_reactions,
-                                    &self.format_reaction(reaction)?,
-                                    "<div class=\"reaction\">",
-                                    "</div>",
-                                );
-                            }
-                            Ok(())
-                        })?;
+            let reactions = self.config.reactions_to(message, idx);
+            if !reactions.is_empty() {
+                let mut formatted_reactions = String::new();
+
+                reactions
+                    .iter()
+                    .try_for_each(|reaction| -> Result<(), TableError> {
+                        let formatted = self.format_reaction(reaction)?;
+                        if !formatted.is_empty() {
+                            self.add_line(
+                                &mut formatted_reactions,
+                                &self.format_reaction(reaction)?,
+                                "<div class=\"reaction\">",
+                                "</div>",
+                            );
+                        }
+                        Ok(())
+                    })?;
 
-                    if !formatted_reactions.is_empty() {
-                        self.add_line(
-                            &mut formatted_message,
-                            "<hr><p>Reactions:</p>",
-                            "<div class=\"reactions\">",
-                            "",
-                        );
-                        self.add_line(&mut formatted_message, &formatted_reactions, "", "");
-                    }
-                    self.add_line(&mut formatted_message, "</div>", "", "");
+                if !formatted_reactions.is_empty() {
+                    self.add_line(
+                        &mut formatted_message,
+                        "<hr><p>Reactions:</p>",
+                        "<div class=\"reactions\">",
+                        "",
+                    );
+                    self.add_line(&mut formatted_message, &formatted_reactions, "", "");
                 }
+                self.add_line(&mut formatted_message, "</div>", "", "");
             }
 
             // Handle Replies
@@ -644,6 +660,18 @@ impl<'a> Writer<'a> for HTML<'a> {
                             CustomBalloon::Slideshow => self.format_slideshow(&bubble, message),
                             CustomBalloon::CheckIn => self.format_check_in(&bubble, message),
                             CustomBalloon::FindMy => self.format_find_my(&bubble, message),
+                            CustomBalloon::DigitalTouch => self.format_generic_app(
+                                &bubble,
+                                "Digital Touch",
+                                attachments,
+                                message,
+                            ),
+                            CustomBalloon::Animoji => {
+                                self.format_generic_app(&bubble, "Animoji", attachments, message)
+                            }
+                            CustomBalloon::Business => {
+                                self.format_generic_app(&bubble, "Business", attachments, message)
+                            }
                             CustomBalloon::Handwriting => unreachable!(),
                             CustomBalloon::URL => unreachable!(),
                         },
@@ -1392,16 +1420,18 @@ impl<'a> TextEffectFormatter for HTML<'a> {
 impl<'a> HTML<'a> {
     fn get_time(&self, message: &Message) -> String {
         let mut date = format(&message.date(&self.config.offset));
-        let read_after = message.time_until_read(&self.config.offset);
+        // Messages read immediately do not need an annotation
+        let read_after = message
+            .time_until_read_duration(&self.config.offset)
+            .filter(|diff| !diff.is_zero())
+            .and_then(readable_duration);
         if let Some(time) = read_after {
-            if !time.is_empty() {
-                let who = if message.is_from_me() {
-                    "them"
-                } else {
-                    self.config.options.custom_name.as_deref().unwrap_or("you")
-                };
-                date.push_str(&format!(" (Read by {who} after {time})"));
-            }
+            let who = if message.is_from_me() {
+                "them"
+            } else {
+                self.config.options.custom_name.as_deref().unwrap_or("you")
+            };
+            date.push_str(&format!(" (Read by {who} after {time})"));
         }
         date
     }
@@ -1553,7 +1583,9 @@ mod tests {
             table::{get_connection, ME},
         },
         util::{
-            dates::get_offset, dirs::default_db_path, platform::Platform,
+            dates::{get_offset, TimestampScale},
+            dirs::default_db_path,
+            platform::Platform,
             query_context::QueryContext,
         },
     };
@@ -1608,6 +1640,7 @@ mod tests {
             use_caller_id: false,
             platform: Platform::macOS,
             ignore_disk_space: false,
+            truncate: None,
         }
     }
 
@@ -1620,8 +1653,10 @@ mod tests {
             participants: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
+            stickers: HashMap::new(),
             options,
             offset: get_offset(),
+            timestamp_scale: TimestampScale::default(),
             db,
             converter: None,
         }
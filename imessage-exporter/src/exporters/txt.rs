@@ -30,11 +30,11 @@ use imessage_database::{
     },
     tables::{
         attachment::Attachment,
-        messages::{models::BubbleComponent, Message},
+        messages::{models::BubbleComponent, render::truncate_text, Message},
         table::{Table, FITNESS_RECEIVER, ME, ORPHANED, YOU},
     },
     util::{
-        dates::{format, get_local_time, readable_diff, TIMESTAMP_FACTOR},
+        dates::{format, get_local_time, readable_diff, readable_duration, TIMESTAMP_FACTOR},
         plist::parse_plist,
     },
 };
@@ -95,6 +95,7 @@ impl<'a> Exporter<'a> for TXT<'a> {
 
         for message in messages {
             let mut msg = Message::extract(message).map_err(RuntimeError::DatabaseError)?;
+            msg.normalize_dates(self.config.timestamp_scale);
 
             // Early escape if we try and render the same message GUID twice
             // See https://github.com/ReagentX/imessage-exporter/issues/135 for rationale
@@ -191,12 +192,16 @@ impl<'a> Writer<'a> for TXT<'a> {
         let message_parts = message.body();
         let mut attachments = Attachment::from_message(&self.config.db, message)?;
         let mut replies = message.get_replies(&self.config.db)?;
+        replies
+            .values_mut()
+            .flatten()
+            .for_each(|reply| reply.normalize_dates(self.config.timestamp_scale));
 
         // Index of where we are in the attachment Vector
         let mut attachment_index: usize = 0;
 
         // Render subject
-        if let Some(subject) = &message.subject {
+        if let Some(subject) = message.subject() {
             self.add_line(&mut formatted_message, subject, &indent);
         }
 
@@ -247,6 +252,14 @@ impl<'a> Writer<'a> for TXT<'a> {
                                 formatted_text.push_str(text);
                             }
 
+                            if let Some(max_chars) = self.config.options.truncate {
+                                if let Cow::Owned(truncated) =
+                                    truncate_text(&formatted_text, max_chars)
+                                {
+                                    formatted_text = truncated;
+                                }
+                            }
+
                             if formatted_text.starts_with(FITNESS_RECEIVER) {
                                 self.add_line(
                                     &mut formatted_message,
@@ -259,7 +272,7 @@ impl<'a> Writer<'a> for TXT<'a> {
                         }
                     }
                 }
-                BubbleComponent::Attachment => match attachments.get_mut(attachment_index) {
+                BubbleComponent::Attachment(_) => match attachments.get_mut(attachment_index) {
                     Some(attachment) => {
                         if attachment.is_sticker {
                             let result = self.format_sticker(attachment, message);
@@ -309,27 +322,26 @@ impl<'a> Writer<'a> for TXT<'a> {
             }
 
             // Handle Reactions
-            if let Some(reactions_map) = self.config.reactions.get(&message.guid) {
-                if let Some(reactions) = reactions_map.get(&idx) {
-                    let mut formatted_reactions = String::new();
-                    reactions
-                        .iter()
-                        .try_for_each(|reaction| -> Result<(), TableError> {
-                            let formatted = self.format_reaction(reaction)?;
-                            if !formatted.is_empty() {
-                                self.add_line(
-                                    &mut formatted_reactions,
-                                    &self.format_reaction(reaction)?,
-                                    &indent,
-                                );
-                            }
-                            Ok(())
-                        })?;
+            let reactions = self.config.reactions_to(message, idx);
+            if !reactions.is_empty() {
+                let mut formatted_reactions = String::new();
+                reactions
+                    .iter()
+                    .try_for_each(|reaction| -> Result<(), TableError> {
+                        let formatted = self.format_reaction(reaction)?;
+                        if !formatted.is_empty() {
+                            self.add_line(
+                                &mut formatted_reactions,
+                                &self.format_reaction(reaction)?,
+                                &indent,
+                            );
+                        }
+                        Ok(())
+                    })?;
 
-                    if !formatted_reactions.is_empty() {
-                        self.add_line(&mut formatted_message, "Reactions:", &indent);
-                        self.add_line(&mut formatted_message, &formatted_reactions, &indent);
-                    }
+                if !formatted_reactions.is_empty() {
+                    self.add_line(&mut formatted_message, "Reactions:", &indent);
+                    self.add_line(&mut formatted_message, &formatted_reactions, &indent);
                 }
             }
 
@@ -450,6 +462,18 @@ impl<'a> Writer<'a> for TXT<'a> {
                             CustomBalloon::Slideshow => self.format_slideshow(&bubble, indent),
                             CustomBalloon::CheckIn => self.format_check_in(&bubble, indent),
                             CustomBalloon::FindMy => self.format_find_my(&bubble, indent),
+                            CustomBalloon::DigitalTouch => self.format_generic_app(
+                                &bubble,
+                                "Digital Touch",
+                                attachments,
+                                indent,
+                            ),
+                            CustomBalloon::Animoji => {
+                                self.format_generic_app(&bubble, "Animoji", attachments, indent)
+                            }
+                            CustomBalloon::Business => {
+                                self.format_generic_app(&bubble, "Business", attachments, indent)
+                            }
                             CustomBalloon::Handwriting => unreachable!(),
                             CustomBalloon::URL => unreachable!(),
                         },
@@ -958,16 +982,18 @@ impl<'a> BalloonFormatter<&'a str> for TXT<'a> {
 impl<'a> TXT<'a> {
     fn get_time(&self, message: &Message) -> String {
         let mut date = format(&message.date(&self.config.offset));
-        let read_after = message.time_until_read(&self.config.offset);
+        // Messages read immediately do not need an annotation
+        let read_after = message
+            .time_until_read_duration(&self.config.offset)
+            .filter(|diff| !diff.is_zero())
+            .and_then(readable_duration);
         if let Some(time) = read_after {
-            if !time.is_empty() {
-                let who = if message.is_from_me() {
-                    "them"
-                } else {
-                    self.config.options.custom_name.as_deref().unwrap_or("you")
-                };
-                date.push_str(&format!(" (Read by {who} after {time})"));
-            }
+            let who = if message.is_from_me() {
+                "them"
+            } else {
+                self.config.options.custom_name.as_deref().unwrap_or("you")
+            };
+            date.push_str(&format!(" (Read by {who} after {time})"));
         }
         date
     }
@@ -1000,7 +1026,9 @@ mod tests {
             table::{get_connection, ME},
         },
         util::{
-            dates::get_offset, dirs::default_db_path, platform::Platform,
+            dates::{get_offset, TimestampScale},
+            dirs::default_db_path,
+            platform::Platform,
             query_context::QueryContext,
         },
     };
@@ -1055,6 +1083,7 @@ mod tests {
             use_caller_id: false,
             platform: Platform::macOS,
             ignore_disk_space: false,
+            truncate: None,
         }
     }
 
@@ -1067,8 +1096,10 @@ mod tests {
             participants: HashMap::new(),
             real_participants: HashMap::new(),
             reactions: HashMap::new(),
+            stickers: HashMap::new(),
             options,
             offset: get_offset(),
+            timestamp_scale: TimestampScale::default(),
             db,
             converter: None,
         }